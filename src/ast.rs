@@ -1,77 +1,88 @@
-use crate::expression::*;
-use std::{
-    fs::{File, OpenOptions},
-    io::{BufWriter, Write},
-    path::PathBuf,
-};
+use crate::{expression::Expression, parse::Resolution, statement::Statement};
+use std::ops::Range;
 
-pub struct AstGenerator {
-    output_dir: String,
-    types: Vec<String>,
+/// A node of the AST, which knows the part of the source it was parsed from
+pub trait Node {
+    /// Byte range of the source the node was parsed from, set by the parser.
+    /// Nodes the parser adds while desugaring cover the code they stand for.
+    fn span(&self) -> Range<usize>;
+    fn set_span(&mut self, span: Range<usize>);
 }
 
-impl AstGenerator {
-    pub fn new(output_dir: String, types: Vec<String>) -> Self {
-        Self { output_dir, types }
-    }
-
-    pub fn define_ast(&self, base_name: &str) {
-        let file_name = format!("{base_name}.rs");
-        let file_path: PathBuf = [&self.output_dir, &file_name].iter().collect();
-
-        let mut stream = BufWriter::new(File::create(&file_path).unwrap());
-
-        stream
-            .write(b"use crate::expression::Expression;\n\n")
-            .unwrap();
-        stream
-            .write(b"use crate::token::{ Token, LiteralValue };\n\n")
-            .unwrap();
-        stream.flush().unwrap();
+/// Declares AST node structs, each with a `new` constructor taking its fields in order
+/// that counts the node for `--stats`, and a `Node` impl for its span. Fields after
+/// `with` aren't constructor arguments and start out with the given value.
+///
+/// ```ignore
+/// define_ast! {
+///     pub struct CallExpr {
+///         callee: Box<dyn Expression>,
+///         arguments: Vec<Box<dyn Expression>>,
+///     } with {
+///         tail: bool = false,
+///     }
+/// }
+/// ```
+macro_rules! define_ast {
+    ($(
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $($(#[$field_attr:meta])* $field:ident: $ty:ty),* $(,)?
+        }
+        $(with {
+            $($(#[$extra_attr:meta])* $extra:ident: $extra_ty:ty = $default:expr),* $(,)?
+        })?
+    )*) => {$(
+        $(#[$attr])*
+        pub struct $name {
+            $($(#[$field_attr])* $field: $ty,)*
+            $($($(#[$extra_attr])* $extra: $extra_ty,)*)?
+            span: std::ops::Range<usize>,
+        }
 
-        for t in &self.types {
-            let typedef = t.split_once(':').expect("Invalid type definition: {t}");
-            let name = typedef.0.trim();
-            let fields = typedef.1.trim();
-            self.define_type(&file_path, name, fields);
+        impl $name {
+            pub fn new($($field: $ty),*) -> Self {
+                $crate::stats::record_node();
+                Self {
+                    $($field,)*
+                    $($($extra: $default,)*)?
+                    span: 0..0,
+                }
+            }
         }
-    }
 
-    fn define_type(&self, file_path: &PathBuf, base_name: &str, fields: &str) {
-        let options = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(file_path)
-            .unwrap();
-        let mut stream = BufWriter::new(options);
+        impl $crate::ast::Node for $name {
+            fn span(&self) -> std::ops::Range<usize> {
+                self.span.clone()
+            }
 
-        let struct_name = format!("pub struct {base_name} {{");
-        stream.write(struct_name.as_bytes()).unwrap();
+            fn set_span(&mut self, span: std::ops::Range<usize>) {
+                self.span = span;
+            }
+        }
+    )*};
+}
 
-        let field_vec = fields.split(", ");
-        for field in field_vec {
-            let field_def = field
-                .split_once(' ')
-                .expect("to be able to find a name in field");
+pub(crate) use define_ast;
 
-            let field_type = match field_def.0 {
-                "Expr" => "Box<dyn Expression>".as_bytes(),
-                "Literal" => "Box<dyn LiteralValue>".as_bytes(),
-                _ => field_def.0.as_bytes(),
-            };
-            let field_name = field_def.1.as_bytes();
+pub fn print_expr(expr: &dyn Expression) {
+    println!("{}", expr.accept());
+}
 
-            stream.write(b"\n\t").unwrap();
-            stream.write(field_name).unwrap();
-            stream.write(b": ").unwrap();
-            stream.write(field_type).unwrap();
-            stream.write(b",").unwrap();
-        }
-        stream.write(b"\n}\n\n").unwrap();
-        stream.flush().unwrap();
+pub fn print_stmts(stmts: &[Box<dyn Statement>]) {
+    for stmt in stmts {
+        println!("{}", stmt.accept());
     }
 }
 
-pub fn print_expr(expr: &Box<dyn Expression>) {
-    println!("{}", expr.accept());
+/// Prints every variable reference with the scope depth it refers to, one per line
+pub fn print_resolutions(resolutions: &[Resolution]) {
+    for resolution in resolutions {
+        let name = &resolution.name;
+        let depth = match resolution.depth {
+            Some(depth) => format!("depth {depth}"),
+            None => String::from("global"),
+        };
+        println!("[{}:{}] {} {depth}", name.line, name.column, name.lexeme);
+    }
 }
@@ -0,0 +1,124 @@
+//! The token cache written by `compile` and read by `run`. It saves scanning a script
+//! again, but `run` still parses the cached tokens.
+use crate::{
+    token::{LiteralValue, NumberLiteral, StringLiteral, Token},
+    TokenType,
+};
+use std::{fs, io, path::Path, rc::Rc};
+
+/// First line of every cache file, followed by the hash of the source it was compiled from.
+/// It names the interpreter's version too, since another version may scan differently.
+const MAGIC: &str = concat!("LOXC 3 ", env!("CARGO_PKG_VERSION"), " ");
+
+/// Hashes the source a cache was compiled from, with 64-bit FNV-1a.
+/// Unlike `DefaultHasher`, the result doesn't change between Rust versions.
+pub fn source_hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Writes the tokens scanned from `source` to a cache file at `path`.
+///
+/// The cache holds tokens rather than statements: the AST is made of trait objects
+/// that can't be written out, so loading a cache skips scanning but not parsing.
+pub fn write(path: &Path, source: &str, tokens: &[Token]) -> io::Result<()> {
+    let mut out = format!("{MAGIC} {:016x}\n", source_hash(source));
+    for token in tokens {
+        let literal = match &token.literal {
+            None => String::from("-"),
+            Some(literal) if token.token_type == TokenType::Number => {
+                let value = literal.as_number().unwrap_or_default();
+                format!("n{:08x}", value.to_bits())
+            }
+            Some(literal) => format!("s{}", escape(&literal.print_value())),
+        };
+        let doc = match &token.doc {
+            None => String::from("-"),
+            Some(doc) => format!("d{}", escape(doc)),
+        };
+        out.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            token.token_type,
+            token.line,
+            token.column,
+            token.offset,
+            escape(&token.lexeme),
+            literal,
+            doc
+        ));
+    }
+    fs::write(path, out)
+}
+
+/// Reads the tokens cached at `path`, if the cache exists and was compiled from `source`
+pub fn read(path: &Path, source: &str) -> Option<Vec<Token>> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut lines = contents.lines();
+    let hash = lines.next()?.strip_prefix(MAGIC)?.trim();
+    if u64::from_str_radix(hash, 16).ok()? != source_hash(source) {
+        return None;
+    }
+    lines.map(read_token).collect()
+}
+
+fn read_token(line: &str) -> Option<Token> {
+    let mut fields = line.split('\t');
+    let token_type = fields.next()?.parse::<TokenType>().ok()?;
+    let line = fields.next()?.parse().ok()?;
+    let column = fields.next()?.parse().ok()?;
+    let offset = fields.next()?.parse().ok()?;
+    let lexeme = unescape(fields.next()?)?;
+    let literal = fields.next()?;
+    let literal: Option<Box<dyn LiteralValue>> = match (literal.get(..1)?, &literal[1..]) {
+        ("-", "") => None,
+        ("n", bits) => Some(Box::new(NumberLiteral {
+            value: f32::from_bits(u32::from_str_radix(bits, 16).ok()?),
+        })),
+        ("s", value) => Some(Box::new(StringLiteral {
+            value: unescape(value)?,
+        })),
+        _ => return None,
+    };
+    let mut token = Token::new(token_type, lexeme, literal, line, column);
+    token.offset = offset;
+    token.doc = match fields.next()? {
+        "-" => None,
+        doc => Some(Rc::from(unescape(doc.strip_prefix('d')?)?)),
+    };
+    Some(token)
+}
+
+/// Escapes the characters that separate fields and lines in a cache file
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn unescape(text: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => unescaped.push('\\'),
+            't' => unescaped.push('\t'),
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(unescaped)
+}
@@ -0,0 +1,228 @@
+use crate::{
+    environment::Environment,
+    expression::{ErrorKind, RuntimeError},
+    hooks,
+    statement::Statement,
+    token::{LiteralType, LiteralValue, Token},
+};
+use std::rc::Rc;
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// Signature shared by all functions implemented in Rust
+pub type NativeFn = dyn Fn(
+    &mut Environment,
+    Vec<Box<dyn LiteralValue>>,
+    &Token,
+) -> Result<Option<Box<dyn LiteralValue>>>;
+
+/// A runtime value that can be invoked with `callee(arguments)`
+pub trait Callable {
+    fn arity(&self) -> usize;
+
+    /// Whether the function takes any number of arguments beyond its arity
+    fn variadic(&self) -> bool {
+        false
+    }
+
+    fn call(
+        &self,
+        env: &mut Environment,
+        arguments: Vec<Box<dyn LiteralValue>>,
+        paren: &Token,
+    ) -> Result<Option<Box<dyn LiteralValue>>>;
+
+    /// The doc comment of the function's declaration, returned by `doc(fn)`
+    fn doc(&self) -> Option<Rc<str>> {
+        None
+    }
+
+    /// The function, if it is defined in Lox and so can be tail called
+    fn as_lox_function(&self) -> Option<&LoxFunction> {
+        None
+    }
+
+    /// Identifies the function for `==`. Copies of a function share it, while every
+    /// evaluation of a `fun` expression creates a function with a new one.
+    fn id(&self) -> usize;
+}
+
+/// A call in tail position, run by the function call it returns from
+pub struct TailCall {
+    pub function: LoxFunction,
+    pub arguments: Vec<Box<dyn LiteralValue>>,
+}
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    /// Takes `arity` or more arguments
+    pub variadic: bool,
+    pub function: Rc<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(name: &str, arity: usize, function: Rc<NativeFn>) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            variadic: false,
+            function,
+        }
+    }
+
+    /// Accepts extra arguments after the first `arity` ones
+    pub fn variadic(mut self) -> Self {
+        self.variadic = true;
+        self
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn variadic(&self) -> bool {
+        self.variadic
+    }
+
+    fn id(&self) -> usize {
+        Rc::as_ptr(&self.function) as *const () as usize
+    }
+
+    fn call(
+        &self,
+        env: &mut Environment,
+        arguments: Vec<Box<dyn LiteralValue>>,
+        paren: &Token,
+    ) -> Result<Option<Box<dyn LiteralValue>>> {
+        (self.function)(env, arguments, paren)
+    }
+}
+
+impl LiteralValue for NativeFunction {
+    fn print_value(&self) -> String {
+        String::from("<native fn>")
+    }
+
+    fn get_type(&self) -> LiteralType {
+        LiteralType::FunctionLiteral
+    }
+
+    fn as_callable(&self) -> Option<&dyn Callable> {
+        Some(self)
+    }
+}
+
+/// A function defined in Lox, together with the environment it closes over
+#[derive(Clone)]
+pub struct LoxFunction {
+    params: Vec<Token>,
+    body: Rc<Vec<Box<dyn Statement>>>,
+    closure: Environment,
+    doc: Option<Rc<str>>,
+    /// Shared by the copies of this function, whose address tells it apart from others
+    identity: Rc<()>,
+}
+
+impl LoxFunction {
+    pub fn new(
+        params: Vec<Token>,
+        body: Rc<Vec<Box<dyn Statement>>>,
+        closure: Environment,
+    ) -> Self {
+        Self {
+            params,
+            body,
+            closure,
+            doc: None,
+            identity: Rc::new(()),
+        }
+    }
+
+    pub fn with_doc(mut self, doc: Option<Rc<str>>) -> Self {
+        self.doc = doc;
+        self
+    }
+
+    /// Runs the body once with the given arguments, leaving tail calls to `call`
+    fn run(&self, arguments: Vec<Box<dyn LiteralValue>>) -> Result<Option<Box<dyn LiteralValue>>> {
+        let mut environment = Environment::new(Some(self.closure.clone()));
+        for (param, argument) in self.params.iter().zip(arguments) {
+            environment.define(param.lexeme.clone(), Some(argument));
+        }
+
+        for s in self.body.iter() {
+            match hooks::execute(s.as_ref(), &mut environment) {
+                Ok(_) => (),
+                Err(e) if e.kind == ErrorKind::Return => return Ok(e.value),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Callable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Runs the body, and then the body of every function it tail calls in turn,
+    /// so tail recursion doesn't grow the stack
+    fn call(
+        &self,
+        _env: &mut Environment,
+        arguments: Vec<Box<dyn LiteralValue>>,
+        _paren: &Token,
+    ) -> Result<Option<Box<dyn LiteralValue>>> {
+        let mut tail_call: Option<Box<TailCall>> = None;
+        let mut arguments = arguments;
+        loop {
+            let function = match &tail_call {
+                Some(tail_call) => &tail_call.function,
+                None => self,
+            };
+            match function.run(arguments) {
+                Err(e) if e.kind == ErrorKind::TailCall => {
+                    let mut next = e.tail_call.expect("tail calls carry their callee");
+                    arguments = std::mem::take(&mut next.arguments);
+                    tail_call = Some(next);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn doc(&self) -> Option<Rc<str>> {
+        self.doc.clone()
+    }
+
+    fn as_lox_function(&self) -> Option<&LoxFunction> {
+        Some(self)
+    }
+
+    fn id(&self) -> usize {
+        Rc::as_ptr(&self.identity) as usize
+    }
+}
+
+impl LiteralValue for LoxFunction {
+    fn print_value(&self) -> String {
+        String::from("<fn>")
+    }
+
+    fn get_type(&self) -> LiteralType {
+        LiteralType::FunctionLiteral
+    }
+
+    fn as_callable(&self) -> Option<&dyn Callable> {
+        Some(self)
+    }
+
+    fn trace(&self, visit: &mut dyn FnMut(&Environment)) {
+        visit(&self.closure);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::token::Token;
+use clap::ValueEnum;
+use once_cell::sync::Lazy;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[36m";
+const YELLOW: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+/// Columns between tab stops when quoting source lines
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    /// The error, the source line it occurred on and any notes
+    #[default]
+    Human,
+    /// One JSON object per line, for editors and CI systems
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    /// Printed, but doesn't affect the exit code unless `--deny-warnings` is passed
+    Warning,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A scan, parse or runtime error, or a warning, ready to be printed
+pub struct Diagnostic<'a> {
+    pub severity: Severity,
+    /// Stable name of the kind of error, like `TypeError`
+    pub code: &'a str,
+    /// The first lines of the human readable output, including the location
+    pub header: String,
+    /// What went wrong, without the location
+    pub message: String,
+    /// The file `line` and `column` are in
+    pub source: SourceId,
+    pub line: usize,
+    pub column: usize,
+    /// How many columns to underline, starting at `column`
+    pub width: usize,
+    pub notes: &'a [String],
+}
+
+/// A file registered with `set_source`. Tokens remember the file they were scanned
+/// from, so errors quote the right one when a program is made of several files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SourceId(usize);
+
+struct Source {
+    file: String,
+    lines: Vec<String>,
+}
+
+/// How diagnostics are printed, and the programs they quote from
+#[derive(Default)]
+struct Settings {
+    /// The source with id `n` is at index `n - 1`, since id 0 means none
+    sources: Vec<Source>,
+    color: bool,
+    format: ErrorFormat,
+    warnings: usize,
+}
+
+impl Settings {
+    fn source(&self, id: SourceId) -> Option<&Source> {
+        self.sources.get(id.0.wrapping_sub(1))
+    }
+}
+
+static SETTINGS: Lazy<Mutex<Settings>> = Lazy::new(|| Mutex::new(Settings::default()));
+
+/// The source tokens are scanned from, which every new token is tagged with
+static CURRENT_SOURCE: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers a file so that errors can name it and quote the line they occur on, and
+/// makes it the one new tokens are scanned from. Registering a file again replaces
+/// its text. Without a registered source, errors are printed without a quote.
+pub fn set_source(file: &str, source: &str) -> SourceId {
+    let mut settings = SETTINGS.lock().expect("diagnostics lock poisoned");
+    let lines = source.lines().map(|l| l.to_string()).collect();
+    let index = match settings.sources.iter().position(|s| s.file == file) {
+        Some(index) => {
+            settings.sources[index].lines = lines;
+            index
+        }
+        None => {
+            settings.sources.push(Source {
+                file: file.to_string(),
+                lines,
+            });
+            settings.sources.len() - 1
+        }
+    };
+    CURRENT_SOURCE.store(index + 1, Ordering::Relaxed);
+    SourceId(index + 1)
+}
+
+/// The source registered last, which tokens scanned now come from
+pub fn current_source() -> SourceId {
+    SourceId(CURRENT_SOURCE.load(Ordering::Relaxed))
+}
+
+/// Starts counting warnings from zero, for a new run of the program
+pub fn reset_warnings() {
+    SETTINGS.lock().expect("diagnostics lock poisoned").warnings = 0;
+}
+
+pub fn set_color(color: bool) {
+    SETTINGS.lock().expect("diagnostics lock poisoned").color = color;
+}
+
+pub fn set_format(format: ErrorFormat) {
+    SETTINGS.lock().expect("diagnostics lock poisoned").format = format;
+}
+
+/// How many columns of its line the token covers, at least one
+pub fn token_width(token: &Token) -> usize {
+    let first_line = token.lexeme.lines().next().unwrap_or_default();
+    first_line.graphemes(true).count().max(1)
+}
+
+/// How many terminal columns `text` takes up when printed at the start of a line,
+/// with tabs going to the next tab stop and wide characters taking two columns
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true)
+        .fold(0, |width, grapheme| match grapheme {
+            "\t" => width + TAB_WIDTH - width % TAB_WIDTH,
+            grapheme => width + grapheme_width(grapheme),
+        })
+}
+
+/// Columns taken by a grapheme: two for East Asian wide characters and emoji,
+/// none for control characters and one for everything else
+fn grapheme_width(grapheme: &str) -> usize {
+    let Some(first) = grapheme.chars().next() else {
+        return 0;
+    };
+    // U+FE0F asks for the emoji presentation of the character before it
+    if grapheme.contains('\u{fe0f}') {
+        return 2;
+    }
+    match first as u32 {
+        0..=0x1f | 0x7f..=0x9f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f1e6..=0x1f1ff
+        | 0x1f300..=0x1f64f
+        | 0x1f680..=0x1f6ff
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+/// The line with its tabs replaced by the spaces up to the next tab stop
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::new();
+    for grapheme in line.graphemes(true) {
+        match grapheme {
+            "\t" => {
+                let width = display_width(&expanded);
+                expanded.push_str(&" ".repeat(TAB_WIDTH - width % TAB_WIDTH));
+            }
+            grapheme => expanded.push_str(grapheme),
+        }
+    }
+    expanded
+}
+
+/// How many warnings have been emitted so far
+pub fn warning_count() -> usize {
+    SETTINGS.lock().expect("diagnostics lock poisoned").warnings
+}
+
+/// Prints the diagnostic to stderr in the configured format
+pub fn emit(diagnostic: Diagnostic) {
+    let mut settings = SETTINGS.lock().expect("diagnostics lock poisoned");
+    if diagnostic.severity == Severity::Warning {
+        settings.warnings += 1;
+    }
+    match settings.format {
+        ErrorFormat::Human => eprintln!("{}", render_human(&settings, &diagnostic)),
+        ErrorFormat::Json => eprintln!("{}", render_json(&settings, &diagnostic)),
+    }
+}
+
+/// The header, followed by the source line with the offending columns underlined
+/// and then every note
+fn render_human(settings: &Settings, diagnostic: &Diagnostic) -> String {
+    let paint = |style: &str, text: &str| match settings.color {
+        true => format!("{style}{text}{RESET}"),
+        false => text.to_string(),
+    };
+
+    let highlight = match diagnostic.severity {
+        Severity::Error => RED,
+        Severity::Warning => YELLOW,
+    };
+
+    let mut output = paint(highlight, &diagnostic.header);
+    let source = settings.source(diagnostic.source);
+    // Name the file when it isn't the one being run, like the prelude
+    if let Some(source) = source.filter(|_| diagnostic.source != current_source()) {
+        output.push_str(&format!(
+            "\n{} {}:{}:{}",
+            paint(BLUE, "-->"),
+            source.file,
+            diagnostic.line,
+            diagnostic.column
+        ));
+    }
+    let lines = source.map(|s| s.lines.as_slice()).unwrap_or_default();
+    if let Some(text) = lines.get(diagnostic.line.wrapping_sub(1)) {
+        let number = diagnostic.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // Columns count graphemes, which can be wider than one column on screen
+        let graphemes = text.graphemes(true).collect::<Vec<&str>>();
+        let before = diagnostic.column.saturating_sub(1);
+        let prefix = graphemes[..before.min(graphemes.len())].concat();
+        let underlined = graphemes
+            .iter()
+            .skip(before)
+            .take(diagnostic.width)
+            .copied()
+            .collect::<String>();
+        let prefix_width = display_width(&prefix) + before.saturating_sub(graphemes.len());
+        let padding = " ".repeat(prefix_width);
+        let carets = match underlined.is_empty() {
+            true => "^".repeat(diagnostic.width),
+            false => "^".repeat(
+                (display_width(&format!("{prefix}{underlined}")) - display_width(&prefix)).max(1),
+            ),
+        };
+        output.push_str(&format!("\n{} {}", gutter, paint(BLUE, "|")));
+        output.push_str(&format!(
+            "\n{} {}",
+            paint(BLUE, &format!("{number} |")),
+            expand_tabs(text)
+        ));
+        output.push_str(&format!(
+            "\n{} {}{}",
+            gutter,
+            paint(BLUE, "|"),
+            paint(highlight, &format!(" {padding}{carets}"))
+        ));
+    }
+    for note in diagnostic.notes {
+        output.push_str(&format!("\n  {} {note}", paint(CYAN, "note:")));
+    }
+    output
+}
+
+/// A single-line JSON object with the severity, file, position, code, message and notes
+fn render_json(settings: &Settings, diagnostic: &Diagnostic) -> String {
+    let file = match settings.source(diagnostic.source) {
+        Some(source) => json_string(&source.file),
+        None => String::from("null"),
+    };
+    let notes = diagnostic
+        .notes
+        .iter()
+        .map(|note| json_string(note))
+        .collect::<Vec<String>>()
+        .join(",");
+    format!(
+        "{{\"severity\":{},\"file\":{},\"line\":{},\"column\":{},\"code\":{},\"message\":{},\"notes\":[{}]}}",
+        json_string(diagnostic.severity.name()),
+        file,
+        diagnostic.line,
+        diagnostic.column,
+        json_string(diagnostic.code),
+        json_string(&diagnostic.message),
+        notes
+    )
+}
+
+/// Quotes and escapes `value` as a JSON string
+pub(crate) fn json_string(value: &str) -> String {
+    let mut output = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if c.is_control() => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
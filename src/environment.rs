@@ -1,62 +1,251 @@
 use crate::{
-    expression::RuntimeError,
+    expression::{ErrorKind, RuntimeError},
+    limits, stats, suggest,
     token::{LiteralValue, Token},
 };
-use std::collections::HashMap;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    mem,
+    rc::{Rc, Weak},
+};
 
 type Result<T> = std::result::Result<T, RuntimeError>;
 
+/// The fewest scopes that have to exist before the collector runs on its own
+const MIN_COLLECTION_THRESHOLD: usize = 1024;
+
+thread_local! {
+    /// Every scope created so far, so the collector can find reference cycles between them
+    static SCOPES: RefCell<Vec<Weak<RefCell<Scope>>>> = const { RefCell::new(Vec::new()) };
+    /// How many scopes may exist before the next automatic collection
+    static NEXT_COLLECTION: Cell<usize> = const { Cell::new(MIN_COLLECTION_THRESHOLD) };
+    /// Collect before every new scope, to shake out bugs in the collector
+    static GC_STRESS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A handle to a scope of variables. Clones share the same scope, which is
+/// what lets closures observe and modify the variables they captured.
 #[derive(Clone)]
 pub struct Environment {
+    scope: Rc<RefCell<Scope>>,
+}
+
+struct Scope {
     values: HashMap<String, Option<Box<dyn LiteralValue>>>,
-    enclosing: Option<Box<Environment>>,
+    enclosing: Option<Environment>,
 }
+
 impl Environment {
-    pub fn new(enclosing: Option<Box<Environment>>) -> Self {
+    pub fn new(enclosing: Option<Environment>) -> Self {
+        stats::record_environment();
+        let live = SCOPES.with(|scopes| scopes.borrow().len());
+        if GC_STRESS.get() || live >= NEXT_COLLECTION.get() {
+            collect_garbage();
+        }
+
         let values: HashMap<String, Option<Box<dyn LiteralValue>>> = HashMap::new();
-        Self { values, enclosing }
+        let scope = Rc::new(RefCell::new(Scope { values, enclosing }));
+        SCOPES.with(|scopes| scopes.borrow_mut().push(Rc::downgrade(&scope)));
+        Self { scope }
     }
 
     pub fn define(&mut self, name: String, value: Option<Box<dyn LiteralValue>>) {
-        self.values.insert(name, value);
+        let mut scope = self.scope.borrow_mut();
+        if scope.enclosing.is_none() {
+            limits::progress();
+        }
+        scope.values.insert(name, value);
     }
 
     pub fn get(&self, name: Token) -> Result<Option<Box<dyn LiteralValue>>> {
-        if let Some(item) = self.values.get(&name.lexeme) {
-            return Ok(item.clone());
-        } else {
-            if let Some(e) = &self.enclosing {
-                return e.get(name);
+        let mut environment = self.clone();
+        loop {
+            if let Some(item) = environment.scope.borrow().values.get(&name.lexeme) {
+                return Ok(item.clone());
+            }
+            match environment.enclosing() {
+                Some(enclosing) => environment = enclosing,
+                None => return Err(self.undefined(name)),
+            }
+        }
+    }
+
+    /// Assigns an existing variable, where `None` is nil
+    pub fn assign(&mut self, name: Token, value: Option<Box<dyn LiteralValue>>) -> Result<()> {
+        let mut environment = self.clone();
+        loop {
+            if let Some(item) = environment.scope.borrow_mut().values.get_mut(&name.lexeme) {
+                *item = value;
+                return Ok(());
+            }
+            match environment.enclosing() {
+                Some(enclosing) => environment = enclosing,
+                None => return Err(self.undefined(name)),
+            }
+        }
+    }
+
+    /// The scope `depth` levels up the chain of enclosing scopes, where 0 is this scope
+    pub fn ancestor(&self, depth: usize) -> Option<Environment> {
+        let mut environment = self.clone();
+        for _ in 0..depth {
+            environment = environment.enclosing()?;
+        }
+        Some(environment)
+    }
+
+    /// Looks a variable up in the scope `depth` levels up, without searching any
+    /// other scope. For variables whose scope a resolver has already determined.
+    pub fn get_at(&self, depth: usize, name: Token) -> Result<Option<Box<dyn LiteralValue>>> {
+        if let Some(environment) = self.ancestor(depth) {
+            if let Some(item) = environment.scope.borrow().values.get(&name.lexeme) {
+                return Ok(item.clone());
             }
-            let message = format!("Undefined variable '{}'.", name.lexeme);
-            return Err(RuntimeError {
-                token: name,
-                message,
-            });
         }
+        Err(self.undefined(name))
     }
 
-    pub fn assign(&mut self, name: Token, value: Box<dyn LiteralValue>) -> Result<()> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme.clone(), Some(value));
-            return Ok(());
+    /// Assigns a variable in the scope `depth` levels up, like `get_at`
+    pub fn assign_at(
+        &mut self,
+        depth: usize,
+        name: Token,
+        value: Option<Box<dyn LiteralValue>>,
+    ) -> Result<()> {
+        if let Some(environment) = self.ancestor(depth) {
+            let mut scope = environment.scope.borrow_mut();
+            if let Some(item) = scope.values.get_mut(&name.lexeme) {
+                *item = value;
+                return Ok(());
+            }
         }
-        if let Some(e) = self.enclosing.as_mut() {
-            return e.assign(name, value);
+        Err(self.undefined(name))
+    }
+
+    /// The names defined in this scope itself, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self
+            .scope
+            .borrow()
+            .values
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    /// The names visible from this scope, through all of its enclosing scopes
+    pub fn visible_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut environment = Some(self.clone());
+        while let Some(current) = environment {
+            names.extend(current.names());
+            environment = current.enclosing();
         }
+        names.sort();
+        names.dedup();
+        names
+    }
 
+    /// The error for a variable that isn't defined in any visible scope, suggesting
+    /// the visible name closest to it
+    fn undefined(&self, name: Token) -> RuntimeError {
         let message = format!("Undefined variable '{}'.", name.lexeme);
-        return Err(RuntimeError {
-            token: name,
-            message,
-        });
+        let names = self.visible_names();
+        let suggestion = suggest::closest(&name.lexeme, names.iter().map(String::as_str))
+            .map(|candidate| format!("did you mean '{candidate}'?"));
+        let error = RuntimeError::new(name, message).with_kind(ErrorKind::UndefinedVariable);
+        match suggestion {
+            Some(note) => error.with_note(note),
+            None => error,
+        }
     }
 
-    pub fn revert_to(&mut self, target: &Environment) {
-        self.values = target.values.clone();
+    pub fn enclosing(&self) -> Option<Environment> {
+        self.scope.borrow().enclosing.clone()
     }
+}
+
+pub fn set_gc_stress(stress: bool) {
+    GC_STRESS.set(stress);
+}
+
+/// Frees scopes that are only kept alive by reference cycles, like a function
+/// stored in the scope it closes over, and returns how many were freed.
+///
+/// Every scope's reference count is compared with the number of references to it
+/// from other scopes. Scopes with more references than that are in use by the
+/// interpreter itself, so everything reachable from them stays alive and the
+/// rest is cleared, which breaks the cycles.
+pub fn collect_garbage() -> usize {
+    let scopes = SCOPES.with(|scopes| {
+        let mut scopes = scopes.borrow_mut();
+        scopes.retain(|scope| scope.strong_count() > 0);
+        scopes.iter().filter_map(Weak::upgrade).collect::<Vec<_>>()
+    });
+    let index = scopes
+        .iter()
+        .enumerate()
+        .map(|(i, scope)| (Rc::as_ptr(scope), i))
+        .collect::<HashMap<_, _>>();
 
-    pub fn enclosing(&self) -> Option<&Box<Environment>> {
-        self.enclosing.as_ref()
+    let mut internal = vec![0; scopes.len()];
+    let mut edges = vec![Vec::new(); scopes.len()];
+    let mut roots = Vec::new();
+    for (i, cell) in scopes.iter().enumerate() {
+        // A scope that is being modified right now is in use, so it is kept with
+        // everything it references
+        let Ok(scope) = cell.try_borrow() else {
+            roots.push(i);
+            continue;
+        };
+        let mut visit = |env: &Environment| {
+            if let Some(&j) = index.get(&Rc::as_ptr(&env.scope)) {
+                internal[j] += 1;
+                edges[i].push(j);
+            }
+        };
+        if let Some(enclosing) = &scope.enclosing {
+            visit(enclosing);
+        }
+        for value in scope.values.values().flatten() {
+            value.trace(&mut visit);
+        }
+    }
+    // `scopes` holds one reference to each scope itself
+    for (i, scope) in scopes.iter().enumerate() {
+        if Rc::strong_count(scope) - 1 > internal[i] {
+            roots.push(i);
+        }
     }
+
+    let mut reachable = vec![false; scopes.len()];
+    while let Some(i) = roots.pop() {
+        if !mem::replace(&mut reachable[i], true) {
+            roots.extend(&edges[i]);
+        }
+    }
+
+    let mut freed = 0;
+    for (scope, reachable) in scopes.iter().zip(reachable) {
+        if !reachable {
+            let mut scope = scope.borrow_mut();
+            let values = mem::take(&mut scope.values);
+            let enclosing = scope.enclosing.take();
+            drop(scope);
+            drop((values, enclosing));
+            freed += 1;
+        }
+    }
+    drop(scopes);
+
+    let live = SCOPES.with(|scopes| {
+        let mut scopes = scopes.borrow_mut();
+        scopes.retain(|scope| scope.strong_count() > 0);
+        scopes.len()
+    });
+    NEXT_COLLECTION.set((live * 2).max(MIN_COLLECTION_THRESHOLD));
+    freed
 }
@@ -0,0 +1,52 @@
+use crate::{expression::Expression, statement::Statement};
+
+/// Describes how a program runs, for learning how the interpreter works: every
+/// statement with the tree it's parsed into, which shows how it's desugared, and
+/// the order in which the interpreter evaluates its expressions.
+pub fn explain(source: &str, stmts: &[Box<dyn Statement>]) -> String {
+    let mut out = String::new();
+    for stmt in stmts {
+        explain_stmt(source, stmt.as_ref(), 0, &mut out);
+    }
+    out
+}
+
+fn explain_stmt(source: &str, stmt: &dyn Statement, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    let span = stmt.span();
+    let line = source[..span.start].matches('\n').count() + 1;
+    // Statements spanning several lines are shown by their first, with the
+    // statements nested in them explained after it
+    let text = &source[span];
+    let heading = match text.split_once('\n') {
+        Some((first, _)) => format!("{} ...", first.trim_end()),
+        None => text.to_string(),
+    };
+    out.push_str(&format!("{indent}[line {line}] {heading}\n"));
+
+    let nested = stmt.statements();
+    if nested.is_empty() {
+        out.push_str(&format!("{indent}  parsed as: {}\n", stmt.accept()));
+    }
+    let mut steps = Vec::new();
+    for expr in stmt.expressions() {
+        evaluation_order(expr, &mut steps);
+    }
+    if !steps.is_empty() {
+        out.push_str(&format!("{indent}  evaluates:\n"));
+        for (i, step) in steps.iter().enumerate() {
+            out.push_str(&format!("{indent}    {}. {step}\n", i + 1));
+        }
+    }
+    for stmt in nested {
+        explain_stmt(source, stmt, depth + 1, out);
+    }
+}
+
+/// Lists `expr` after its operands, which is the order they get their values in
+fn evaluation_order(expr: &dyn Expression, steps: &mut Vec<String>) {
+    for operand in expr.operands() {
+        evaluation_order(operand, steps);
+    }
+    steps.push(expr.accept());
+}
@@ -1,44 +1,209 @@
-use crate::interpret::{is_equal, is_truthy, parenthesize};
+use crate::interpret::{is_equal, is_truthy, parenthesize, stringify};
 use crate::{
+    ast::{define_ast, Node},
+    callable::{LoxFunction, TailCall},
+    diagnostic::{self, Diagnostic, Severity},
     environment::Environment,
-    token::{BooleanLiteral, LiteralType, LiteralValue, NumberLiteral, StringLiteral, Token},
+    hooks, interrupt, limits,
+    statement::{parenthesize_stmts, Statement},
+    token::{
+        BooleanLiteral, LiteralType, LiteralValue, NilLiteral, NumberLiteral, StringLiteral, Token,
+    },
     TokenType,
 };
-use std::fmt;
+use std::{cell::Cell, fmt, rc::Rc};
+
+thread_local! {
+    /// Whether dividing by zero raises an error instead of giving infinity or NaN
+    static STRICT_MATH: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes dividing by zero raise a `DivisionByZero` error. Otherwise it follows
+/// IEEE 754 like clox: `1 / 0` is `inf`, `-1 / 0` is `-inf` and `0 / 0` is `nan`.
+pub fn set_strict_math(strict: bool) {
+    STRICT_MATH.set(strict);
+}
 
 type Result<T> = std::result::Result<T, RuntimeError>;
 
+#[derive(Debug, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// Any runtime error that doesn't fit one of the more specific kinds
+    Runtime,
+    /// An operation was applied to a value of the wrong type
+    TypeError,
+    /// A variable was read or assigned before being declared
+    UndefinedVariable,
+    /// A function was called with the wrong number of arguments
+    ArityMismatch,
+    /// Dividing by zero with `--strict-math`
+    DivisionByZero,
+    /// Raised by a `throw` statement that nothing caught
+    Thrown,
+    /// Raised by a failing `assert()`
+    Assertion,
+    /// The program ran out of the steps or time given by `Interpreter::with_limits`
+    BudgetExceeded,
+    /// Too many calls were in progress at once, usually because of endless recursion
+    StackOverflow,
+    /// Not an actual error: unwinds a `return` statement to the function
+    /// call it returns from, carrying the returned value
+    Return,
+    /// Not an actual error: unwinds a `return` of a call in tail position to the
+    /// function call it returns from, which then runs the callee in its place
+    TailCall,
+    /// Not an actual error: unwinds the whole program after `exit(code)`
+    Exit(u8),
+    /// Ctrl-C was pressed while `run` was executing the program
+    Interrupted,
+}
+
+impl ErrorKind {
+    /// Whether this is control flow, or stops the program, so `catch` must let it through
+    pub fn is_unwind(&self) -> bool {
+        matches!(
+            self,
+            ErrorKind::Return | ErrorKind::TailCall | ErrorKind::Exit(_) | ErrorKind::Interrupted
+        )
+    }
+
+    /// The stable name diagnostics report for this kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::Runtime => "RuntimeError",
+            ErrorKind::TypeError => "TypeError",
+            ErrorKind::UndefinedVariable => "UndefinedVariable",
+            ErrorKind::ArityMismatch => "ArityMismatch",
+            ErrorKind::DivisionByZero => "DivisionByZero",
+            ErrorKind::Thrown => "UncaughtException",
+            ErrorKind::Assertion => "AssertionError",
+            ErrorKind::BudgetExceeded => "BudgetExceeded",
+            ErrorKind::StackOverflow => "StackOverflow",
+            ErrorKind::Return => "Return",
+            ErrorKind::TailCall => "TailCall",
+            ErrorKind::Exit(_) => "Exit",
+            ErrorKind::Interrupted => "Interrupted",
+        }
+    }
+}
+
 pub struct RuntimeError {
-    pub token: Token,
+    pub kind: ErrorKind,
+    /// The token the error is reported at, boxed to keep `Result`s small
+    pub token: Box<Token>,
     pub message: String,
+    /// Extra lines of context printed below the message
+    pub notes: Vec<String>,
+    /// The value raised by a `throw` statement, if this error came from one
+    pub value: Option<Box<dyn LiteralValue>>,
+    /// The function and arguments of a tail call, boxed like `token`
+    pub tail_call: Option<Box<TailCall>>,
 }
 
-impl fmt::Display for RuntimeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}\n[line {}]", self.message, self.token.line)
+impl RuntimeError {
+    pub fn new(token: Token, message: String) -> Self {
+        Self {
+            kind: ErrorKind::Runtime,
+            token: Box::new(token),
+            message,
+            notes: Vec::new(),
+            value: None,
+            tail_call: None,
+        }
+    }
+
+    /// Creates the error raised by `throw`, carrying the thrown value
+    pub fn thrown(token: Token, value: Box<dyn LiteralValue>) -> Self {
+        let message = stringify(Some(value.clone()));
+        Self {
+            value: Some(value),
+            ..Self::new(token, message).with_kind(ErrorKind::Thrown)
+        }
+    }
+
+    /// Creates the unwind that makes the enclosing function call run `tail_call` instead
+    pub fn tail_call(token: Token, tail_call: TailCall) -> Self {
+        Self {
+            tail_call: Some(Box::new(tail_call)),
+            ..Self::new(token, String::from("tail call")).with_kind(ErrorKind::TailCall)
+        }
+    }
+
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
-pub enum ExpressionType {
-    Assign,
-    Binary,
-    Grouping,
-    Literal,
-    Unary,
-    Variable,
+impl RuntimeError {
+    /// Prints the error to stderr, quoting the line it occurred on
+    pub fn report(&self) {
+        diagnostic::emit(Diagnostic {
+            severity: Severity::Error,
+            code: self.kind.code(),
+            header: format!(
+                "{}: {}\n[line {}]",
+                self.kind.code(),
+                self.message,
+                self.token.line
+            ),
+            message: self.message.clone(),
+            source: self.token.source,
+            line: self.token.line,
+            column: self.token.column,
+            width: diagnostic::token_width(&self.token),
+            notes: &self.notes,
+        });
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}\n[line {}]",
+            self.kind.code(),
+            self.message,
+            self.token.line
+        )?;
+        for note in &self.notes {
+            write!(f, "\n  note: {note}")?;
+        }
+        Ok(())
+    }
 }
 
-pub trait Expression {
+pub trait Expression: Node {
     fn accept(&self) -> String;
     fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>>;
-    fn get_type(&self) -> ExpressionType;
-    fn get_token(&self) -> Option<Token>;
+
+    /// The expression as a variable reference, if it is one. Variables are the
+    /// only assignable targets, so this is how the parser tells them apart.
+    fn as_variable(&self) -> Option<&VariableExpr> {
+        None
+    }
+
+    /// Marks a call returned by a `return` in tail position, so calling it replaces
+    /// the current function call instead of nesting inside it
+    fn mark_tail_call(&mut self) {}
+
+    /// The expressions evaluated before this one to get its value, in the order they
+    /// are evaluated. A function's body isn't one of them, as it runs when called.
+    fn operands(&self) -> Vec<&dyn Expression> {
+        Vec::new()
+    }
 }
 
-pub struct AssignExpr {
-    name: Token,
-    value: Box<dyn Expression>,
+define_ast! {
+    pub struct AssignExpr {
+        name: Token,
+        value: Box<dyn Expression>,
+    }
 }
 
 impl Expression for AssignExpr {
@@ -47,73 +212,54 @@ impl Expression for AssignExpr {
     }
 
     fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
-        match self.value.evaluate(environment) {
-            Ok(Some(v)) => {
-                environment.assign(self.name.clone(), v.clone())?;
-                Ok(Some(v))
-            }
-            Ok(None) => {
-                return Ok(None);
-            }
-            Err(e) => {
-                return Err(e);
-            }
-        }
+        let value = self.value.evaluate(environment)?;
+        hooks::variable_write(&self.name, value.as_deref())?;
+        // Nil is `None`, and has to overwrite the old value too
+        environment.assign(self.name.clone(), value.clone())?;
+        Ok(value)
     }
 
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Assign
-    }
-
-    fn get_token(&self) -> Option<Token> {
-        Some(self.name.clone())
+    fn operands(&self) -> Vec<&dyn Expression> {
+        vec![self.value.as_ref()]
     }
 }
 
-impl AssignExpr {
-    pub fn new(name: Token, value: Box<dyn Expression>) -> Self {
-        Self { name, value }
+define_ast! {
+    pub struct BinaryExpr {
+        left: Box<dyn Expression>,
+        operator: Token,
+        right: Box<dyn Expression>,
     }
 }
 
-pub struct BinaryExpr {
-    left: Box<dyn Expression>,
-    operator: Token,
-    right: Box<dyn Expression>,
-}
-
 impl Expression for BinaryExpr {
     fn accept(&self) -> String {
-        parenthesize(&self.operator.lexeme, vec![&self.left, &self.right])
+        parenthesize(
+            &self.operator.lexeme,
+            vec![self.left.as_ref(), self.right.as_ref()],
+        )
     }
 
     fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
-        if let (Some(left), Some(right)) = (
-            self.left.evaluate(environment)?,
-            self.right.evaluate(environment)?,
-        ) {
-            let left_type = left.get_type();
-            let right_type = right.get_type();
-
-            let left_val = left.print_value();
-            let right_val = right.print_value();
-
-            if self.operator.token_type == TokenType::BangEqual {
-                let eq = !is_equal(left, right);
-                return Ok(Some(Box::new(BooleanLiteral { value: eq })));
-            } else if self.operator.token_type == TokenType::EqualEqual {
-                let eq = is_equal(left, right);
-                return Ok(Some(Box::new(BooleanLiteral { value: eq })));
-            }
+        let left = self.left.evaluate(environment)?;
+        let right = self.right.evaluate(environment)?;
+
+        // The comma operator only evaluates its left operand for its side effects
+        if self.operator.token_type == TokenType::Comma {
+            return Ok(right);
+        }
 
-            if left_type == LiteralType::NumberLiteral && right_type == LiteralType::NumberLiteral {
-                let left_num = left_val
-                    .parse::<f32>()
-                    .expect("to be able to parse left NumberLiteral in binary expression to f32");
-                let right_num = right_val
-                    .parse::<f32>()
-                    .expect("to be able to parse right NumberLiteral in binary expression to f32");
+        // Equality is defined for every pair of values, nil included
+        if self.operator.token_type == TokenType::BangEqual {
+            let eq = !is_equal(left.as_deref(), right.as_deref());
+            return Ok(Some(Box::new(BooleanLiteral { value: eq })));
+        } else if self.operator.token_type == TokenType::EqualEqual {
+            let eq = is_equal(left.as_deref(), right.as_deref());
+            return Ok(Some(Box::new(BooleanLiteral { value: eq })));
+        }
 
+        if let (Some(left), Some(right)) = (left, right) {
+            if let (Some(left_num), Some(right_num)) = (left.as_number(), right.as_number()) {
                 match self.operator.token_type {
                     TokenType::Minus => {
                         return Ok(Some(Box::new(NumberLiteral {
@@ -121,6 +267,13 @@ impl Expression for BinaryExpr {
                         })));
                     }
                     TokenType::Slash => {
+                        if right_num == 0.0 && STRICT_MATH.get() {
+                            return Err(RuntimeError::new(
+                                self.operator.clone(),
+                                String::from("Division by zero."),
+                            )
+                            .with_kind(ErrorKind::DivisionByZero));
+                        }
                         return Ok(Some(Box::new(NumberLiteral {
                             value: left_num / right_num,
                         })));
@@ -157,80 +310,261 @@ impl Expression for BinaryExpr {
                     }
                     _ => (),
                 }
-            } else if left_type == LiteralType::StringLiteral
-                && right_type == LiteralType::StringLiteral
+            } else if left.get_type() == LiteralType::StringLiteral
+                && right.get_type() == LiteralType::StringLiteral
             {
-                if self.operator.token_type == TokenType::Plus {
-                    let mut left_string = left_val.to_owned();
-                    left_string.push_str(&right_val.to_owned());
-                    return Ok(Some(Box::new(StringLiteral { value: left_string })));
-                }
-                return Err(RuntimeError {
-                    token: self.operator.clone(),
-                    message: String::from("Operands must be numbers."),
-                });
+                let left_val = left.print_value();
+                let right_val = right.print_value();
+                // Strings compare lexicographically, by Unicode code point
+                let value = match self.operator.token_type {
+                    TokenType::Plus => {
+                        let mut left_string = left_val.to_owned();
+                        left_string.push_str(&right_val.to_owned());
+                        return Ok(Some(Box::new(StringLiteral { value: left_string })));
+                    }
+                    TokenType::Greater => left_val > right_val,
+                    TokenType::GreaterEqual => left_val >= right_val,
+                    TokenType::Less => left_val < right_val,
+                    TokenType::LessEqual => left_val <= right_val,
+                    _ => {
+                        return Err(RuntimeError::new(
+                            self.operator.clone(),
+                            String::from("Operands must be numbers."),
+                        )
+                        .with_kind(ErrorKind::TypeError))
+                    }
+                };
+                return Ok(Some(Box::new(BooleanLiteral { value })));
             }
-            Err(RuntimeError {
-                token: self.operator.clone(),
-                message: String::from("Operands must be numbers or strings."),
-            })
+            let message = match self.operator.token_type {
+                TokenType::Minus | TokenType::Slash | TokenType::Star => {
+                    "Operands must be numbers."
+                }
+                _ => "Operands must be two numbers or two strings.",
+            };
+            Err(
+                RuntimeError::new(self.operator.clone(), String::from(message))
+                    .with_kind(ErrorKind::TypeError)
+                    .with_note(format!(
+                        "left operand is a {}, right operand is a {}",
+                        left.type_name(),
+                        right.type_name()
+                    )),
+            )
         } else {
-            return Err(RuntimeError {
-                token: self.operator.clone(),
-                message: String::from("expected value in expression"),
-            });
+            return Err(RuntimeError::new(
+                self.operator.clone(),
+                String::from("expected value in expression"),
+            ));
         }
     }
 
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Binary
+    fn operands(&self) -> Vec<&dyn Expression> {
+        vec![self.left.as_ref(), self.right.as_ref()]
     }
+}
 
-    fn get_token(&self) -> Option<Token> {
-        Some(self.operator.clone())
+define_ast! {
+    pub struct CallExpr {
+        callee: Box<dyn Expression>,
+        paren: Token,
+        arguments: Vec<Box<dyn Expression>>,
+    } with {
+        /// Whether this is the value of a `return` in tail position
+        tail: bool = false,
     }
 }
 
-impl BinaryExpr {
-    pub fn new(left: Box<dyn Expression>, operator: Token, right: Box<dyn Expression>) -> Self {
-        Self {
-            left,
-            operator,
-            right,
+impl Expression for CallExpr {
+    fn accept(&self) -> String {
+        let mut expressions = vec![self.callee.as_ref()];
+        expressions.extend(self.arguments.iter().map(|a| a.as_ref()));
+        parenthesize("call", expressions)
+    }
+
+    fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
+        let callee = self.callee.evaluate(environment)?;
+
+        let mut arguments: Vec<Box<dyn LiteralValue>> = Vec::new();
+        for argument in &self.arguments {
+            match argument.evaluate(environment)? {
+                Some(v) => arguments.push(v),
+                None => arguments.push(Box::new(NilLiteral)),
+            }
+        }
+
+        let function = match callee.as_ref().and_then(|c| c.as_callable()) {
+            Some(f) => f,
+            None => {
+                return Err(RuntimeError::new(
+                    self.paren.clone(),
+                    String::from("Can only call functions and classes."),
+                )
+                .with_kind(ErrorKind::TypeError))
+            }
+        };
+        if function.variadic() && arguments.len() < function.arity() {
+            return Err(RuntimeError::new(
+                self.paren.clone(),
+                format!(
+                    "Expected at least {} arguments but got {}.",
+                    function.arity(),
+                    arguments.len()
+                ),
+            )
+            .with_kind(ErrorKind::ArityMismatch));
         }
+        if !function.variadic() && arguments.len() != function.arity() {
+            return Err(RuntimeError::new(
+                self.paren.clone(),
+                format!(
+                    "Expected {} arguments but got {}.",
+                    function.arity(),
+                    arguments.len()
+                ),
+            )
+            .with_kind(ErrorKind::ArityMismatch));
+        }
+        limits::step(&self.paren)?;
+        interrupt::check(&self.paren)?;
+        if let Some(callee) = &callee {
+            hooks::call(callee.as_ref(), &arguments, &self.paren)?;
+        }
+        if self.tail {
+            if let Some(function) = function.as_lox_function() {
+                let tail_call = TailCall {
+                    function: function.clone(),
+                    arguments,
+                };
+                interrupt::replace_frame(self.callee.as_ref(), &self.paren);
+                return Err(RuntimeError::tail_call(self.paren.clone(), tail_call));
+            }
+        }
+        limits::enter_call(&self.paren)?;
+        interrupt::push_frame(self.callee.as_ref(), &self.paren);
+        let result = function.call(environment, arguments, &self.paren);
+        interrupt::pop_frame();
+        limits::exit_call();
+        result
+    }
+
+    fn mark_tail_call(&mut self) {
+        self.tail = true;
+    }
+
+    fn operands(&self) -> Vec<&dyn Expression> {
+        let mut operands = vec![self.callee.as_ref()];
+        operands.extend(self.arguments.iter().map(|a| a.as_ref()));
+        operands
     }
 }
 
-pub struct GroupingExpr {
-    expression: Box<dyn Expression>,
+define_ast! {
+    /// An anonymous function, `fun (a, b) { ... }`
+    pub struct FunctionExpr {
+        params: Vec<Token>,
+        body: Rc<Vec<Box<dyn Statement>>>,
+    } with {
+        doc: Option<Rc<str>> = None,
+    }
+}
+
+impl Expression for FunctionExpr {
+    fn accept(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|p| p.lexeme.clone())
+            .collect::<Vec<String>>();
+        let body = parenthesize_stmts("block", &self.body);
+        format!("(fun ({}) {})", params.join(" "), body)
+    }
+
+    fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
+        let function =
+            LoxFunction::new(self.params.clone(), self.body.clone(), environment.clone());
+        Ok(Some(Box::new(function.with_doc(self.doc.clone()))))
+    }
+}
+
+impl FunctionExpr {
+    /// Attaches the doc comment of the declaration the function is assigned in
+    pub fn with_doc(mut self, doc: Option<Rc<str>>) -> Self {
+        self.doc = doc;
+        self
+    }
+}
+
+define_ast! {
+    pub struct GroupingExpr {
+        expression: Box<dyn Expression>,
+    }
 }
 
 impl Expression for GroupingExpr {
     fn accept(&self) -> String {
-        parenthesize("group", vec![&self.expression])
+        parenthesize("group", vec![self.expression.as_ref()])
     }
 
     fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
         self.expression.evaluate(environment)
     }
 
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Grouping
+    fn operands(&self) -> Vec<&dyn Expression> {
+        vec![self.expression.as_ref()]
     }
+}
 
-    fn get_token(&self) -> Option<Token> {
-        None
+define_ast! {
+    /// `object[index]`, which gives the character at `index` of a string
+    pub struct IndexExpr {
+        object: Box<dyn Expression>,
+        bracket: Token,
+        index: Box<dyn Expression>,
     }
 }
 
-impl GroupingExpr {
-    pub fn new(expression: Box<dyn Expression>) -> Self {
-        Self { expression }
+impl Expression for IndexExpr {
+    fn accept(&self) -> String {
+        parenthesize("index", vec![self.object.as_ref(), self.index.as_ref()])
+    }
+
+    fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
+        let object = self.object.evaluate(environment)?;
+        let index = self.index.evaluate(environment)?;
+        let error = |message: String| RuntimeError::new(self.bracket.clone(), message);
+
+        let Some((object, length)) = object.and_then(|o| o.length().map(|length| (o, length)))
+        else {
+            return Err(error(String::from("Can only index strings and ranges."))
+                .with_kind(ErrorKind::TypeError));
+        };
+        let Some(index) = index
+            .and_then(|i| i.as_number())
+            .filter(|i| i.fract() == 0.0)
+        else {
+            return Err(error(String::from("Index must be a whole number."))
+                .with_kind(ErrorKind::TypeError));
+        };
+        if index < 0.0 || index >= length as f32 {
+            let index = stringify(Some(Box::new(NumberLiteral { value: index })));
+            return Err(error(format!(
+                "Index {index} is out of range for a {} of length {length}.",
+                object.type_name()
+            )));
+        }
+        Ok(object.index(index as usize))
+    }
+
+    fn operands(&self) -> Vec<&dyn Expression> {
+        vec![self.object.as_ref(), self.index.as_ref()]
     }
 }
 
-pub struct LiteralExpr {
-    value: Box<dyn LiteralValue>,
+define_ast! {
+    pub struct LiteralExpr {
+        value: Box<dyn LiteralValue>,
+    }
 }
 
 impl Expression for LiteralExpr {
@@ -241,104 +575,104 @@ impl Expression for LiteralExpr {
     fn evaluate(&self, _environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
         Ok(Some(self.value.clone()))
     }
+}
 
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Literal
+define_ast! {
+    /// Converts the value of its operand to a string, as used by string interpolation
+    pub struct StringifyExpr {
+        expression: Box<dyn Expression>,
     }
+}
 
-    fn get_token(&self) -> Option<Token> {
-        None
+impl Expression for StringifyExpr {
+    fn accept(&self) -> String {
+        parenthesize("str", vec![self.expression.as_ref()])
+    }
+
+    fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
+        let value = self.expression.evaluate(environment)?;
+        Ok(Some(Box::new(StringLiteral {
+            value: stringify(value),
+        })))
     }
-}
 
-impl LiteralExpr {
-    pub fn new(value: Box<dyn LiteralValue>) -> Self {
-        Self { value }
+    fn operands(&self) -> Vec<&dyn Expression> {
+        vec![self.expression.as_ref()]
     }
 }
 
-pub struct UnaryExpr {
-    operator: Token,
-    right: Box<dyn Expression>,
+define_ast! {
+    pub struct UnaryExpr {
+        operator: Token,
+        right: Box<dyn Expression>,
+    }
 }
 
 impl Expression for UnaryExpr {
     fn accept(&self) -> String {
-        parenthesize(&self.operator.lexeme, vec![&self.right])
+        parenthesize(&self.operator.lexeme, vec![self.right.as_ref()])
     }
 
     fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
-        if let Some(right) = self.right.evaluate(environment)? {
+        let right = self.right.evaluate(environment)?;
+        if self.operator.token_type == TokenType::Bang {
+            return Ok(Some(Box::new(BooleanLiteral {
+                value: !is_truthy(right.as_deref()),
+            })));
+        }
+        if let Some(right) = right {
             match self.operator.token_type {
                 TokenType::Minus => {
-                    if !(right.get_type() == LiteralType::NumberLiteral) {
-                        return Err(RuntimeError {
-                            token: self.operator.clone(),
-                            message: String::from("Operand must be a number."),
-                        });
-                    }
-                    let num_value: f32 = right
-                        .print_value()
-                        .parse()
-                        .expect("to be able to parse Number Literal to f32");
+                    let Some(num_value) = right.as_number() else {
+                        return Err(RuntimeError::new(
+                            self.operator.clone(),
+                            String::from("Operand must be a number."),
+                        )
+                        .with_kind(ErrorKind::TypeError));
+                    };
                     return Ok(Some(Box::new(NumberLiteral { value: -num_value })));
                 }
-                TokenType::Bang => {
-                    return Ok(Some(Box::new(BooleanLiteral {
-                        value: !is_truthy(right),
-                    })));
-                }
                 _ => {
-                    return Err(RuntimeError {
-                        token: self.operator.clone(),
-                        message: String::from("Operand must be a number."),
-                    })
+                    return Err(RuntimeError::new(
+                        self.operator.clone(),
+                        String::from("Operand must be a number."),
+                    )
+                    .with_kind(ErrorKind::TypeError))
                 }
             }
         }
-        Err(RuntimeError {
-            token: self.operator.clone(),
-            message: String::from("Expected value in unary expression"),
-        })
+        Err(RuntimeError::new(
+            self.operator.clone(),
+            String::from("Expected value in unary expression"),
+        ))
     }
 
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Unary
-    }
-
-    fn get_token(&self) -> Option<Token> {
-        Some(self.operator.clone())
+    fn operands(&self) -> Vec<&dyn Expression> {
+        vec![self.right.as_ref()]
     }
 }
 
-impl UnaryExpr {
-    pub fn new(operator: Token, right: Box<dyn Expression>) -> Self {
-        Self { operator, right }
+define_ast! {
+    pub struct VariableExpr {
+        name: Token,
     }
 }
-
-pub struct VariableExpr {
-    name: Token,
-}
 impl Expression for VariableExpr {
     fn accept(&self) -> String {
         self.name.to_string()
     }
 
-    fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
-        environment.get(self.name.clone())
-    }
-
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Variable
+    fn as_variable(&self) -> Option<&VariableExpr> {
+        Some(self)
     }
 
-    fn get_token(&self) -> Option<Token> {
-        Some(self.name.clone())
+    fn evaluate(&self, environment: &mut Environment) -> Result<Option<Box<dyn LiteralValue>>> {
+        environment.get(self.name.clone())
     }
 }
+
 impl VariableExpr {
-    pub fn new(name: Token) -> Self {
-        Self { name }
+    pub fn name(&self) -> &Token {
+        &self.name
     }
 }
@@ -0,0 +1,294 @@
+//! C ABI for embedding the interpreter as a scripting engine, enabled by the `ffi` feature.
+//! Build a shared library with `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//!
+//! ```c
+//! typedef enum { LOX_NIL, LOX_BOOLEAN, LOX_NUMBER, LOX_STRING } LoxValueType;
+//! typedef struct { LoxValueType value_type; bool boolean; double number; const char *string; } LoxValue;
+//! typedef int (*LoxNativeFn)(void *user_data, size_t argc, const LoxValue *argv, LoxValue *result);
+//!
+//! Lox *lox_new(void);
+//! void lox_free(Lox *lox);
+//! int lox_run(Lox *lox, const char *source);
+//! const char *lox_last_error(const Lox *lox);
+//! int lox_register_native(Lox *lox, const char *name, size_t arity, LoxNativeFn function, void *user_data);
+//! ```
+use crate::{
+    callable::NativeFunction,
+    environment::Environment,
+    expression::{ErrorKind, RuntimeError},
+    native::{define_arg_natives, define_capabilities, define_natives, Capability},
+    parse::Parser,
+    scan::Scanner,
+    token::{BooleanLiteral, LiteralType, LiteralValue, NumberLiteral, StringLiteral},
+};
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    ptr,
+    rc::Rc,
+};
+
+/// The type of a value passed between C and Lox
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LoxValueType {
+    Nil,
+    Boolean,
+    Number,
+    /// Also used for values C has no type for, like functions, as the text `print` shows
+    String,
+}
+
+/// A value passed between C and Lox. Only the field for its `value_type` is meaningful.
+#[repr(C)]
+pub struct LoxValue {
+    pub value_type: LoxValueType,
+    pub boolean: bool,
+    pub number: f64,
+    /// A NUL-terminated UTF-8 string
+    pub string: *const c_char,
+}
+
+/// The layout of `LoxValue` as the interpreter reads it back from C, which can't be
+/// trusted to write a valid `LoxValueType` or `bool`
+#[repr(C)]
+struct RawLoxValue {
+    value_type: c_int,
+    boolean: u8,
+    number: f64,
+    string: *const c_char,
+}
+
+/// A function implemented in C. It gets `argc` arguments in `argv` and writes its
+/// result into `result`, which starts out as nil. A string result only has to stay
+/// valid until the function returns to the interpreter, which copies it right away.
+/// Returning anything but 0 raises a runtime error, with the result string, if any,
+/// as the message.
+pub type LoxNativeFn = extern "C" fn(
+    user_data: *mut c_void,
+    argc: usize,
+    argv: *const LoxValue,
+    result: *mut LoxValue,
+) -> c_int;
+
+/// An interpreter whose global variables persist across calls to `lox_run`
+pub struct Lox {
+    environment: Environment,
+    last_error: Option<CString>,
+}
+
+/// Creates an interpreter with the standard natives. Free it with `lox_free`.
+#[no_mangle]
+pub extern "C" fn lox_new() -> *mut Lox {
+    let mut environment = Environment::new(None);
+    define_natives(&mut environment);
+    define_capabilities(&mut environment, &Capability::DEFAULT);
+    define_arg_natives(&mut environment, Vec::new());
+    Box::into_raw(Box::new(Lox {
+        environment,
+        last_error: None,
+    }))
+}
+
+/// # Safety
+/// `lox` must come from `lox_new` and not have been freed yet, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn lox_free(lox: *mut Lox) {
+    if !lox.is_null() {
+        drop(Box::from_raw(lox));
+    }
+}
+
+/// Runs a script, returning 0 on success, 65 if it doesn't scan or parse,
+/// 70 for a runtime error and the status passed to `exit()` if the script calls it.
+/// Errors are also printed to stderr.
+///
+/// # Safety
+/// `lox` must come from `lox_new`, and `source` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lox_run(lox: *mut Lox, source: *const c_char) -> c_int {
+    let Some(lox) = lox.as_mut() else {
+        return 70;
+    };
+    lox.last_error = None;
+    if source.is_null() {
+        lox.set_error("The source is null.");
+        return 65;
+    }
+    let Ok(source) = CStr::from_ptr(source).to_str() else {
+        lox.set_error("The source isn't valid UTF-8.");
+        return 65;
+    };
+
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    if scanner.has_error() {
+        lox.set_error("The source has scan errors.");
+        return 65;
+    }
+    let statements = match Parser::new(scanner.tokens).parse() {
+        Ok(statements) => statements,
+        Err(e) => {
+            lox.set_error(&e.to_string());
+            return 65;
+        }
+    };
+    for statement in statements {
+        match statement.evaluate(&mut lox.environment) {
+            Ok(_) => (),
+            Err(e) => match e.kind {
+                ErrorKind::Exit(code) => return code.into(),
+                _ => {
+                    e.report();
+                    lox.set_error(&e.to_string());
+                    return 70;
+                }
+            },
+        }
+    }
+    0
+}
+
+/// The error of the last `lox_run`, or null if it succeeded. The string is owned
+/// by the interpreter and stays valid until the next `lox_run` or `lox_free`.
+///
+/// # Safety
+/// `lox` must come from `lox_new` and not have been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn lox_last_error(lox: *const Lox) -> *const c_char {
+    match lox.as_ref().and_then(|lox| lox.last_error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Defines a global function `name` that calls `function` with `user_data`.
+/// Returns 0 on success and -1 if `name` isn't a valid string or `function` is null.
+/// A result whose `value_type` isn't one of `LoxValueType` raises a runtime error.
+///
+/// # Safety
+/// `lox` must come from `lox_new`, `name` must be a NUL-terminated string, and
+/// `user_data` must stay valid for as long as the interpreter may call `function`.
+#[no_mangle]
+pub unsafe extern "C" fn lox_register_native(
+    lox: *mut Lox,
+    name: *const c_char,
+    arity: usize,
+    function: Option<LoxNativeFn>,
+    user_data: *mut c_void,
+) -> c_int {
+    let (Some(lox), Some(function)) = (lox.as_mut(), function) else {
+        return -1;
+    };
+    if name.is_null() {
+        return -1;
+    }
+    let Ok(name) = CStr::from_ptr(name).to_str() else {
+        return -1;
+    };
+    let native_name = name.to_string();
+    let native = NativeFunction::new(
+        name,
+        arity,
+        Rc::new(move |_env, arguments, paren| {
+            // The strings in `argv` point into `strings`, which outlives the call
+            let strings = arguments
+                .iter()
+                .map(|value| to_c_string(&value.print_value()))
+                .collect::<Vec<CString>>();
+            let argv = arguments
+                .iter()
+                .zip(&strings)
+                .map(|(value, string)| to_lox_value(value.as_ref(), string))
+                .collect::<Vec<LoxValue>>();
+            let mut result = RawLoxValue {
+                value_type: LoxValueType::Nil as c_int,
+                boolean: 0,
+                number: 0.0,
+                string: ptr::null(),
+            };
+            let result_ptr = ptr::addr_of_mut!(result).cast::<LoxValue>();
+            let status = function(user_data, argv.len(), argv.as_ptr(), result_ptr);
+            // SAFETY: the native promised that a string result is valid until it returns
+            let Some(value) = (unsafe { from_lox_value(&result) }) else {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "{native_name}() returned a value of unknown type {}.",
+                        result.value_type
+                    ),
+                ));
+            };
+            if status != 0 {
+                let message = match &value {
+                    Some(value) if value.get_type() == LiteralType::StringLiteral => {
+                        value.print_value()
+                    }
+                    _ => format!("{native_name}() failed."),
+                };
+                return Err(RuntimeError::new(paren.clone(), message));
+            }
+            Ok(value)
+        }),
+    );
+    lox.environment
+        .define(native.name.clone(), Some(Box::new(native)));
+    0
+}
+
+impl Lox {
+    fn set_error(&mut self, message: &str) {
+        self.last_error = Some(to_c_string(message));
+    }
+}
+
+/// Converts text for C, cutting it off at the first NUL since C can't see past it anyway
+fn to_c_string(text: &str) -> CString {
+    let text = text.split('\0').next().unwrap_or_default();
+    CString::new(text).unwrap_or_default()
+}
+
+/// Converts a Lox value for C. `string` is the value's text, used for strings
+/// and for the types C has no equivalent of.
+fn to_lox_value(value: &dyn LiteralValue, string: &CString) -> LoxValue {
+    let mut lox_value = LoxValue {
+        value_type: LoxValueType::String,
+        boolean: false,
+        number: 0.0,
+        string: string.as_ptr(),
+    };
+    match value.get_type() {
+        LiteralType::NilLiteral => lox_value.value_type = LoxValueType::Nil,
+        LiteralType::BooleanLiteral => {
+            lox_value.value_type = LoxValueType::Boolean;
+            lox_value.boolean = value.as_bool().unwrap_or_default();
+        }
+        LiteralType::NumberLiteral => {
+            lox_value.value_type = LoxValueType::Number;
+            lox_value.number = value.as_number().map_or(f64::NAN, f64::from);
+        }
+        LiteralType::StringLiteral | LiteralType::FunctionLiteral | LiteralType::RangeLiteral => (),
+    }
+    lox_value
+}
+
+/// Converts a value returned from C into a Lox value, or `None` if its type is unknown
+///
+/// # Safety
+/// A string value must be null or point to a NUL-terminated string.
+unsafe fn from_lox_value(value: &RawLoxValue) -> Option<Option<Box<dyn LiteralValue>>> {
+    let value = match value.value_type {
+        t if t == LoxValueType::Nil as c_int => None,
+        t if t == LoxValueType::Boolean as c_int => Some(Box::new(BooleanLiteral {
+            value: value.boolean != 0,
+        }) as Box<dyn LiteralValue>),
+        t if t == LoxValueType::Number as c_int => Some(Box::new(NumberLiteral {
+            value: value.number as f32,
+        }) as Box<dyn LiteralValue>),
+        t if t == LoxValueType::String as c_int && value.string.is_null() => None,
+        t if t == LoxValueType::String as c_int => Some(Box::new(StringLiteral {
+            value: CStr::from_ptr(value.string).to_string_lossy().into_owned(),
+        }) as Box<dyn LiteralValue>),
+        _ => return None,
+    };
+    Some(value)
+}
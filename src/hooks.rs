@@ -0,0 +1,75 @@
+use crate::{
+    environment::Environment,
+    expression::RuntimeError,
+    statement::Statement,
+    token::{LiteralValue, Token},
+};
+use std::{cell::RefCell, rc::Rc};
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// Callbacks for applications embedding the interpreter, for tracing, enforcing
+/// policies or recording execution. Returning an error from a hook aborts the
+/// program with that error, like a runtime error raised by the script.
+pub trait Hooks {
+    /// Called before each statement of the program, a block or a function body
+    fn on_statement(&self, _statement: &dyn Statement) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called before a function is called, after its arguments are evaluated
+    fn on_call(
+        &self,
+        _callee: &dyn LiteralValue,
+        _arguments: &[Box<dyn LiteralValue>],
+        _paren: &Token,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called before a variable is defined or assigned, with `None` for nil
+    fn on_variable_write(&self, _name: &Token, _value: Option<&dyn LiteralValue>) -> Result<()> {
+        Ok(())
+    }
+}
+
+thread_local! {
+    static HOOKS: RefCell<Option<Rc<dyn Hooks>>> = const { RefCell::new(None) };
+}
+
+/// Makes `hooks` the ones notified from now on, returning those notified before so
+/// they can be put back once the program that registered these stops running
+pub fn set_hooks(hooks: Option<Rc<dyn Hooks>>) -> Option<Rc<dyn Hooks>> {
+    HOOKS.with(|h| h.replace(hooks))
+}
+
+/// The registered hooks, cloned out so a hook can run Lox code that triggers hooks itself
+fn hooks() -> Option<Rc<dyn Hooks>> {
+    HOOKS.with(|h| h.borrow().clone())
+}
+
+/// Runs a statement of a statement list, calling `on_statement` first
+pub fn execute(statement: &dyn Statement, env: &mut Environment) -> Result<()> {
+    if let Some(hooks) = hooks() {
+        hooks.on_statement(statement)?;
+    }
+    statement.evaluate(env)
+}
+
+pub fn call(
+    callee: &dyn LiteralValue,
+    arguments: &[Box<dyn LiteralValue>],
+    paren: &Token,
+) -> Result<()> {
+    match hooks() {
+        Some(hooks) => hooks.on_call(callee, arguments, paren),
+        None => Ok(()),
+    }
+}
+
+pub fn variable_write(name: &Token, value: Option<&dyn LiteralValue>) -> Result<()> {
+    match hooks() {
+        Some(hooks) => hooks.on_variable_write(name, value),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{
+    parse::{Parser, ParserError},
+    scan::Scanner,
+    statement::Statement,
+    token::Token,
+};
+use std::{fmt, ops::Range};
+
+/// Replaces the bytes in `range` of a document with `text`
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+pub enum DocumentError {
+    /// The edit's range is out of bounds or splits a character
+    InvalidEdit,
+    /// The source has scan errors, which have been reported
+    Scan,
+    /// The source has a parse error, which has been reported
+    Parse(ParserError),
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DocumentError::InvalidEdit => write!(f, "Invalid edit range"),
+            DocumentError::Scan => write!(f, "Could not scan the document"),
+            DocumentError::Parse(e) => write!(f, "Could not parse the document: {e}"),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, DocumentError>;
+
+/// Turns the lines and spans in a statement parsed by a `Document` into positions in
+/// the document. They are relative to the part of the document the statement was
+/// parsed from, so that edits before it only need to move its origin instead of
+/// parsing it again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Origin {
+    /// Added to relative line numbers
+    lines: isize,
+    /// Added to relative byte offsets
+    offset: isize,
+}
+
+impl Origin {
+    /// The position of a relative span in the document
+    pub fn span(&self, span: Range<usize>) -> Range<usize> {
+        self.offset(span.start)..self.offset(span.end)
+    }
+
+    /// The byte offset in the document of a relative offset
+    pub fn offset(&self, offset: usize) -> usize {
+        (offset as isize + self.offset) as usize
+    }
+
+    /// The number in the document of a relative line
+    pub fn line(&self, line: usize) -> usize {
+        (line as isize + self.lines) as usize
+    }
+
+    fn shift(&mut self, lines: isize, offset: isize) {
+        self.lines += lines;
+        self.offset += offset;
+    }
+}
+
+/// A top-level statement and the tokens it was parsed from
+struct Chunk {
+    tokens: Vec<Token>,
+    statement: Box<dyn Statement>,
+    origin: Origin,
+    first_line: usize,
+    last_line: usize,
+}
+
+/// A program that is kept parsed while it is being edited, for editor integrations.
+///
+/// An edit only re-scans the lines of the top-level statements it touches and
+/// re-parses those statements. Statements after it keep their AST, and only their
+/// `Origin` moves. If the edit leaves the document unparseable, the next edit parses
+/// the whole document again.
+pub struct Document {
+    source: String,
+    chunks: Vec<Chunk>,
+    /// Whether `chunks` is out of date because the last edit failed to parse
+    stale: bool,
+    /// Top-level statements parsed so far, counting ones parsed again after an edit
+    parsed: usize,
+}
+
+impl Document {
+    pub fn new(source: String) -> Result<Self> {
+        let mut document = Self {
+            source,
+            chunks: Vec::new(),
+            stale: true,
+            parsed: 0,
+        };
+        document.reparse_all()?;
+        Ok(document)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The top-level statements of the document, in order, with the origin their
+    /// lines and spans are relative to
+    pub fn statements(&self) -> impl Iterator<Item = (&dyn Statement, Origin)> {
+        self.chunks
+            .iter()
+            .map(|chunk| (chunk.statement.as_ref(), chunk.origin))
+    }
+
+    /// The tokens of every top-level statement, without the final EOF, with their
+    /// lines and offsets in the document
+    pub fn tokens(&self) -> impl Iterator<Item = Token> + '_ {
+        self.chunks.iter().flat_map(|chunk| {
+            chunk.tokens.iter().map(|token| {
+                let mut token = token.clone();
+                token.line = chunk.origin.line(token.line);
+                token.offset = chunk.origin.offset(token.offset);
+                token
+            })
+        })
+    }
+
+    /// How many top-level statements have been parsed since the document was
+    /// created, including each time one was parsed again after an edit
+    pub fn statements_parsed(&self) -> usize {
+        self.parsed
+    }
+
+    pub fn apply_edit(&mut self, edit: TextEdit) -> Result<()> {
+        let range = edit.range;
+        if range.start > range.end
+            || !self.source.is_char_boundary(range.start)
+            || !self.source.is_char_boundary(range.end)
+        {
+            return Err(DocumentError::InvalidEdit);
+        }
+
+        let edit_first_line = line_of(&self.source, range.start);
+        let edit_last_line = line_of(&self.source, range.end);
+        let delta = edit.text.matches('\n').count() as isize
+            - self.source[range.clone()].matches('\n').count() as isize;
+        let byte_delta = edit.text.len() as isize - range.len() as isize;
+        self.source.replace_range(range, &edit.text);
+        if self.stale {
+            return self.reparse_all();
+        }
+
+        // The statements on the edited lines, and any others sharing a line with them
+        let mut first = self
+            .chunks
+            .partition_point(|chunk| chunk.last_line < edit_first_line);
+        let mut end = self
+            .chunks
+            .partition_point(|chunk| chunk.first_line <= edit_last_line)
+            .max(first);
+        let mut lines = edit_first_line..=edit_last_line;
+        loop {
+            if first < end {
+                lines = *lines.start().min(&self.chunks[first].first_line)
+                    ..=*lines.end().max(&self.chunks[end - 1].last_line);
+            }
+            if first > 0 && self.chunks[first - 1].last_line >= *lines.start() {
+                first -= 1;
+            } else if end < self.chunks.len() && self.chunks[end].first_line <= *lines.end() {
+                end += 1;
+            } else {
+                break;
+            }
+        }
+
+        let new_last_line = (*lines.end() as isize + delta) as usize;
+        let region = line_range(&self.source, *lines.start(), new_last_line);
+        let rescanned =
+            match parse_chunks(&self.source[region.clone()], *lines.start(), region.start) {
+                Ok(chunks) => chunks,
+                Err(_) => return self.reparse_all(),
+            };
+
+        for chunk in &mut self.chunks[end..] {
+            chunk.origin.shift(delta, byte_delta);
+            chunk.first_line = (chunk.first_line as isize + delta) as usize;
+            chunk.last_line = (chunk.last_line as isize + delta) as usize;
+        }
+        self.parsed += rescanned.len();
+        self.chunks.splice(first..end, rescanned);
+        Ok(())
+    }
+
+    fn reparse_all(&mut self) -> Result<()> {
+        self.stale = true;
+        self.chunks = parse_chunks(&self.source, 1, 0)?;
+        self.parsed += self.chunks.len();
+        self.stale = false;
+        Ok(())
+    }
+}
+
+/// Scans and parses `source`, which starts on line `start_line` and at byte
+/// `start_offset` of the document
+fn parse_chunks(source: &str, start_line: usize, start_offset: usize) -> Result<Vec<Chunk>> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    if scanner.has_error() {
+        return Err(DocumentError::Scan);
+    }
+    let tokens = scanner.tokens;
+    let mut parser = Parser::new(tokens.clone());
+    let statements = parser.parse_spans().map_err(DocumentError::Parse)?;
+
+    let origin = Origin {
+        lines: start_line as isize - 1,
+        offset: start_offset as isize,
+    };
+    let chunks = statements
+        .into_iter()
+        .map(|(statement, span)| {
+            let tokens = tokens[span].to_vec();
+            let first_line = tokens.first().map_or(1, |t| t.line);
+            let last_line = tokens
+                .last()
+                .map_or(1, |t| t.line + t.lexeme.matches('\n').count());
+            Chunk {
+                tokens,
+                statement,
+                origin,
+                first_line: origin.line(first_line),
+                last_line: origin.line(last_line),
+            }
+        })
+        .collect();
+    Ok(chunks)
+}
+
+/// The 1-based line that byte `offset` is on
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+/// The bytes of lines `first..=last`, including the last line's newline
+fn line_range(source: &str, first: usize, last: usize) -> Range<usize> {
+    let line_starts = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect::<Vec<usize>>();
+    let start = line_starts.get(first - 1).copied().unwrap_or(source.len());
+    let end = line_starts.get(last).copied().unwrap_or(source.len());
+    start..end.max(start)
+}
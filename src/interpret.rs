@@ -1,54 +1,336 @@
-use crate::environment::Environment;
-use crate::expression::{Expression, RuntimeError};
+use crate::cache::{escape, unescape};
+use crate::environment::{self, Environment};
+use crate::expression::{self, ErrorKind, Expression, RuntimeError};
+use crate::hooks::{self, Hooks};
+use crate::limits;
+use crate::native::{
+    define_arg_natives, define_capabilities, define_capability_natives, define_natives, Capability,
+};
+use crate::parse::{Parser, ParserError};
+use crate::scan::{decode_source, ScanError, Scanner};
 use crate::statement::Statement;
-use crate::token::{LiteralType, LiteralValue};
+use crate::token::{
+    BooleanLiteral, LiteralType, LiteralValue, NilLiteral, NumberLiteral, StringLiteral, Token,
+};
+use crate::TokenType;
+use std::{fmt, fs, io, path::Path, rc::Rc};
 
 type Result<T> = std::result::Result<T, RuntimeError>;
 
+/// Helpers written in Lox that `run` loads before the script, unless `--no-prelude` is given
+pub const PRELUDE: &str = include_str!("prelude.lox");
+
 pub struct Interpreter {
     statements: Vec<Box<dyn Statement>>,
     environment: Environment,
+    keep_going: bool,
+    assertion_failures: usize,
+    budget: Option<limits::Budget>,
+    max_call_depth: usize,
+    hooks: Option<Rc<dyn Hooks>>,
+}
+/// A global scope with the natives a program gets by default, for `evaluate_expr`
+pub fn default_environment() -> Environment {
+    let mut environment = Environment::new(None);
+    define_natives(&mut environment);
+    define_capabilities(&mut environment, &Capability::DEFAULT);
+    define_arg_natives(&mut environment, Vec::new());
+    environment
 }
+
 impl Interpreter {
     pub fn new(statements: Vec<Box<dyn Statement>>) -> Self {
+        let environment = default_environment();
         Self {
             statements,
-            environment: Environment::new(None),
+            environment,
+            keep_going: false,
+            assertion_failures: 0,
+            budget: None,
+            max_call_depth: limits::MAX_CALL_DEPTH,
+            hooks: None,
         }
     }
 
+    /// When enabled, failing assertions are reported and counted
+    /// instead of aborting the program
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Passes command-line arguments to the program, available through `argc()` and `arg(i)`
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        define_arg_natives(&mut self.environment, args);
+        self
+    }
+
+    /// Makes the file system natives (`read_file`, `write_file`, `append_file`)
+    /// available to the program
+    pub fn with_fs_access(self, allowed: bool) -> Self {
+        self.with_capability(Capability::Fs, allowed)
+    }
+
+    /// Makes the environment variable natives (`getenv`, `setenv`) available to the program
+    pub fn with_env_access(self, allowed: bool) -> Self {
+        self.with_capability(Capability::Env, allowed)
+    }
+
+    /// Enables or disables the natives of one capability. Disabled natives raise
+    /// an error when called. Only `time` and `stdin` are enabled by default.
+    pub fn with_capability(mut self, capability: Capability, allowed: bool) -> Self {
+        define_capability_natives(&mut self.environment, capability, allowed);
+        self
+    }
+
+    /// Enables exactly the `allowed` capabilities and disables the rest
+    pub fn with_capabilities(mut self, allowed: &[Capability]) -> Self {
+        define_capabilities(&mut self.environment, allowed);
+        self
+    }
+
+    /// Registers callbacks that are notified as the program runs, replacing any
+    /// registered before. Only this interpreter's program notifies them.
+    pub fn with_hooks(mut self, hooks: Rc<dyn Hooks>) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Stops the program with a `BudgetExceeded` error once it has made `max_steps`
+    /// function calls and loop iterations or run for `max_millis` milliseconds, for
+    /// running untrusted scripts. `None` leaves that resource unlimited. The time
+    /// counts while the program runs, including while `sleep()` and `readline()` wait,
+    /// and what's left carries over to later `run_statements`.
+    pub fn with_limits(mut self, max_steps: Option<u64>, max_millis: Option<u64>) -> Self {
+        self.budget = limits::Budget::new(max_steps, max_millis);
+        self
+    }
+
+    /// Raises a `StackOverflow` error, which can be caught, when a call would make more
+    /// than `depth` calls in progress at once, instead of letting deep recursion
+    /// overflow the native stack. Defaults to `limits::MAX_CALL_DEPTH`; lower it when
+    /// running on a thread with a small stack.
+    pub fn with_max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    /// Warns that the program may be stuck in an infinite loop once it has made
+    /// `steps` calls and loop iterations in a row without printing anything or
+    /// defining a global variable. `None` turns the warning off.
+    pub fn with_stall_warning(self, steps: Option<u64>) -> Self {
+        limits::set_stall_warning(steps);
+        self
+    }
+
+    /// Makes dividing by zero a runtime error instead of giving infinity or NaN
+    pub fn with_strict_math(self, strict: bool) -> Self {
+        expression::set_strict_math(strict);
+        self
+    }
+
+    /// Runs the garbage collector before every new scope, to catch scopes
+    /// that are freed while still in use
+    pub fn with_gc_stress(self, stress: bool) -> Self {
+        environment::set_gc_stress(stress);
+        self
+    }
+
     pub fn interpret(&mut self) -> Result<()> {
+        let outer_hooks = hooks::set_hooks(self.hooks.clone());
+        let outer_budget = limits::start(self.budget);
+        let outer_max_call_depth = limits::set_max_call_depth(self.max_call_depth);
+        let result = self.execute_statements();
+        limits::set_max_call_depth(outer_max_call_depth);
+        self.budget = limits::finish(outer_budget);
+        hooks::set_hooks(outer_hooks);
+        result
+    }
+
+    fn execute_statements(&mut self) -> Result<()> {
         for s in self.statements.iter_mut() {
-            match s.evaluate(&mut self.environment) {
+            match hooks::execute(s.as_ref(), &mut self.environment) {
                 Ok(_) => (),
+                Err(e) if self.keep_going && e.kind == ErrorKind::Assertion => {
+                    e.report();
+                    self.assertion_failures += 1;
+                }
                 Err(e) => return Err(e),
             }
         }
         Ok(())
     }
+
+    /// Runs further statements against the globals the program left behind,
+    /// like the next file given to `run`
+    pub fn run_statements(&mut self, statements: Vec<Box<dyn Statement>>) -> Result<()> {
+        self.statements = statements;
+        self.interpret()
+    }
+
+    /// The global scope, where natives and top-level variables are defined
+    pub fn globals(&self) -> Environment {
+        self.environment.clone()
+    }
+
+    /// Writes the global variables holding nil, booleans, numbers or strings to `path`,
+    /// one per line, so a later session can pick up where this one left off.
+    /// Functions and ranges can't be written out and are skipped.
+    pub fn save_state(&self, path: &Path) -> io::Result<()> {
+        let mut out = String::new();
+        for name in self.environment.names() {
+            let value = match self.environment.get(identifier(&name)) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            let encoded = match &value {
+                None => String::from("nil"),
+                Some(v) => match v.get_type() {
+                    LiteralType::NilLiteral => String::from("nil"),
+                    LiteralType::BooleanLiteral => format!("boolean\t{}", v.print_value()),
+                    LiteralType::NumberLiteral => {
+                        let number = v.as_number().unwrap_or_default();
+                        format!("number\t{:08x}", number.to_bits())
+                    }
+                    LiteralType::StringLiteral => format!("string\t{}", escape(&v.print_value())),
+                    LiteralType::FunctionLiteral | LiteralType::RangeLiteral => continue,
+                },
+            };
+            out.push_str(&format!("{}\t{encoded}\n", escape(&name)));
+        }
+        fs::write(path, out)
+    }
+
+    /// Defines the global variables saved by `save_state`, replacing any with the same name
+    pub fn load_state(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+        for (number, line) in contents.lines().enumerate() {
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid state on line {}.", number + 1),
+                )
+            };
+            let mut fields = line.split('\t');
+            let name = fields.next().and_then(unescape).ok_or_else(invalid)?;
+            let value: Box<dyn LiteralValue> = match (fields.next(), fields.next()) {
+                (Some("nil"), None) => Box::new(NilLiteral),
+                (Some("boolean"), Some(b)) => Box::new(BooleanLiteral {
+                    value: b.parse().map_err(|_| invalid())?,
+                }),
+                (Some("number"), Some(bits)) => Box::new(NumberLiteral {
+                    value: f32::from_bits(u32::from_str_radix(bits, 16).map_err(|_| invalid())?),
+                }),
+                (Some("string"), Some(s)) => Box::new(StringLiteral {
+                    value: unescape(s).ok_or_else(invalid)?,
+                }),
+                _ => return Err(invalid()),
+            };
+            self.environment.define(name, Some(value));
+        }
+        Ok(())
+    }
+
+    pub fn assertion_failures(&self) -> usize {
+        self.assertion_failures
+    }
+}
+
+/// A token naming the variable `name`, for looking it up outside of a program
+fn identifier(name: &str) -> Token {
+    Token::new(TokenType::Identifier, name.to_string(), None, 0, 0)
 }
 
-pub fn is_truthy(expr: Box<dyn LiteralValue>) -> bool {
-    match expr.get_type() {
+/// Whether a value counts as true in a condition. Only nil and `false` are falsey,
+/// so `0`, `-0`, NaN and the empty string are all truthy. `None` is nil.
+pub fn is_truthy(value: Option<&dyn LiteralValue>) -> bool {
+    let Some(value) = value else {
+        return false;
+    };
+    match value.get_type() {
         LiteralType::NilLiteral => return false,
-        LiteralType::BooleanLiteral => {
-            let expr_val = expr.print_value();
-            match expr_val.as_ref() {
-                "false" => return false,
-                _ => return true,
+        LiteralType::BooleanLiteral => return value.as_bool().unwrap_or(true),
+        _ => return true,
+    }
+}
+
+/// Converts a value to the text `print` shows for it
+pub fn stringify(value: Option<Box<dyn LiteralValue>>) -> String {
+    match value {
+        Some(v) => {
+            let out = v.print_value();
+            // Whole numbers print without the `.0` their literal has, like jlox
+            if v.get_type() == LiteralType::NumberLiteral {
+                return match out.strip_suffix(".0") {
+                    Some(whole) => whole.to_string(),
+                    None => out,
+                };
             }
+            out
         }
-        _ => return true,
+        None => String::from("nil"),
     }
 }
 
-pub fn is_equal(left: Box<dyn LiteralValue>, right: Box<dyn LiteralValue>) -> bool {
-    let left_val = left.print_value();
-    let right_val = right.print_value();
-    &left_val == &right_val
+/// Converts a value to text that shows what kind of value it is, for debugging.
+/// Strings are quoted, with quotes, backslashes and control characters escaped,
+/// so `"1"` and `1` or `"nil"` and `nil` look different. Every other value looks
+/// like `print` shows it.
+pub fn repr(value: Option<Box<dyn LiteralValue>>) -> String {
+    match value {
+        Some(v) if v.get_type() == LiteralType::StringLiteral => {
+            let mut out = String::from("\"");
+            for c in v.print_value().chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+        value => stringify(value),
+    }
 }
 
-pub fn parenthesize(name: &str, expressions: Vec<&Box<dyn Expression>>) -> String {
+/// Whether `==` considers two values equal. `None` is nil.
+///
+/// Values of different types are never equal, so `1 == "1"` and `nil == false` are false.
+/// Numbers compare like floats do: `-0 == 0`, and NaN isn't equal to anything, not even
+/// itself. Functions are only equal to themselves, even if another does the same.
+pub fn is_equal(left: Option<&dyn LiteralValue>, right: Option<&dyn LiteralValue>) -> bool {
+    let type_of = |value: Option<&dyn LiteralValue>| match value {
+        Some(v) => v.get_type(),
+        None => LiteralType::NilLiteral,
+    };
+    if type_of(left) != type_of(right) {
+        return false;
+    }
+    match (left, right) {
+        (Some(l), Some(r)) => match l.get_type() {
+            LiteralType::NumberLiteral => l.as_number() == r.as_number(),
+            LiteralType::BooleanLiteral => l.as_bool() == r.as_bool(),
+            LiteralType::FunctionLiteral => {
+                let id = |v: &dyn LiteralValue| v.as_callable().map(|f| f.id());
+                id(l) == id(r)
+            }
+            // A string's printed form is the string, and a range's shows its bounds
+            LiteralType::StringLiteral | LiteralType::RangeLiteral => {
+                l.print_value() == r.print_value()
+            }
+            LiteralType::NilLiteral => true,
+        },
+        // Both are nil
+        _ => true,
+    }
+}
+
+pub fn parenthesize(name: &str, expressions: Vec<&dyn Expression>) -> String {
     let mut parsed = String::new();
     parsed.push('(');
     parsed.push_str(name);
@@ -62,33 +344,68 @@ pub fn parenthesize(name: &str, expressions: Vec<&Box<dyn Expression>>) -> Strin
     parsed
 }
 
-pub fn interpret_single_expr(
-    expr: Box<dyn Expression>,
-    environment: &mut Environment,
-) -> Result<()> {
-    let value = expr.evaluate(environment);
-    match value {
-        Ok(v) => {
-            if let Some(value) = v {
-                let expr_type = value.get_type();
-                let expr_value = value.print_value();
-                if expr_type == LiteralType::NumberLiteral {
-                    let out_num = expr_value
-                        .parse::<f32>()
-                        .expect("to be able to parse number expression to f32");
-                    println!("{}", out_num);
-                    return Ok(());
-                } else {
-                    println!("{}", expr_value);
-                    return Ok(());
-                }
-            } else {
-                return Ok(());
-            }
-        }
-        Err(e) => {
-            eprintln!("Error: {e}");
-            return Err(e);
+/// Why Lox source couldn't be run
+pub enum LoxError {
+    /// The file holding the source couldn't be read
+    Io(io::Error),
+    /// The source has scan errors, which have been reported
+    Scan(Vec<ScanError>),
+    /// The source has a parse error, which has been reported
+    Parse(ParserError),
+    /// Running the source failed. The error hasn't been reported.
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoxError::Scan(errors) => match errors.first() {
+                Some(e) => write!(f, "[line {}] Error: {}", e.line, e.message),
+                None => write!(f, "Could not scan the source"),
+            },
+            LoxError::Io(e) => write!(f, "Could not read the file: {e}"),
+            LoxError::Parse(e) => write!(f, "{e}"),
+            LoxError::Runtime(e) => write!(f, "{e}"),
         }
     }
 }
+
+fn scan(source: &str) -> std::result::Result<Vec<Token>, LoxError> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    if scanner.has_error() {
+        return Err(LoxError::Scan(scanner.errors));
+    }
+    Ok(scanner.tokens)
+}
+
+/// Scans and parses `source` as a single expression
+pub fn parse_expr(source: &str) -> std::result::Result<Box<dyn Expression>, LoxError> {
+    Parser::new(scan(source)?)
+        .parse_single_expr()
+        .map_err(LoxError::Parse)
+}
+
+/// Reads, scans, parses and runs the script at `path` with the default capabilities
+pub fn run_file(path: impl AsRef<Path>) -> std::result::Result<(), LoxError> {
+    let bytes = fs::read(path).map_err(LoxError::Io)?;
+    let source = decode_source(bytes, false)
+        .map_err(|e| LoxError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    let statements = Parser::new(scan(&source)?)
+        .parse()
+        .map_err(LoxError::Parse)?;
+    Interpreter::new(statements)
+        .interpret()
+        .map_err(LoxError::Runtime)
+}
+
+/// Scans, parses and evaluates `source` as a single expression in `environment`,
+/// usually from `default_environment`, returning its value for the caller to use
+/// instead of printing it
+pub fn evaluate_expr(
+    source: &str,
+    environment: &mut Environment,
+) -> std::result::Result<Option<Box<dyn LiteralValue>>, LoxError> {
+    let expr = parse_expr(source)?;
+    expr.evaluate(environment).map_err(LoxError::Runtime)
+}
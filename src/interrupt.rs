@@ -0,0 +1,103 @@
+use crate::{
+    expression::{ErrorKind, Expression, RuntimeError},
+    token::Token,
+};
+use std::{
+    cell::{Cell, RefCell},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// Set by the Ctrl-C handler and taken by the interpreter at the next call
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// A function call that hasn't returned yet
+struct Frame {
+    /// The variable the function was called through, if any
+    name: Option<String>,
+    line: usize,
+}
+
+thread_local! {
+    /// The calls in progress, outermost first. Only kept once the handler is installed.
+    static FRAMES: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes Ctrl-C stop the program with an `Interrupted` error showing where it was,
+/// instead of killing the process. A second Ctrl-C exits right away, for programs
+/// blocked outside of Lox code, like in `readline()`.
+pub fn install_handler() {
+    TRACKING.set(true);
+    #[cfg(unix)]
+    unsafe {
+        signal(SIGINT, on_sigint);
+    }
+}
+
+#[cfg(unix)]
+const SIGINT: std::ffi::c_int = 2;
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(
+        signum: std::ffi::c_int,
+        handler: extern "C" fn(std::ffi::c_int),
+    ) -> *const std::ffi::c_void;
+    fn _exit(status: std::ffi::c_int) -> !;
+}
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_signum: std::ffi::c_int) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        // SAFETY: `_exit` is async-signal-safe, unlike `std::process::exit`
+        unsafe { _exit(130) }
+    }
+}
+
+/// Records a call of `callee` made at `paren`
+pub fn push_frame(callee: &dyn Expression, paren: &Token) {
+    if TRACKING.get() {
+        let name = callee.as_variable().map(|v| v.name().lexeme.clone());
+        let frame = Frame {
+            name,
+            line: paren.line,
+        };
+        FRAMES.with(|frames| frames.borrow_mut().push(frame));
+    }
+}
+
+pub fn pop_frame() {
+    if TRACKING.get() {
+        FRAMES.with(|frames| frames.borrow_mut().pop());
+    }
+}
+
+/// Replaces the innermost call, which a tail call at `paren` runs in the place of
+pub fn replace_frame(callee: &dyn Expression, paren: &Token) {
+    pop_frame();
+    push_frame(callee, paren);
+}
+
+/// Fails with an `Interrupted` error at the call at `paren` if Ctrl-C was pressed,
+/// listing the calls in progress as notes, innermost first
+pub fn check(paren: &Token) -> Result<()> {
+    if !INTERRUPTED.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let notes = FRAMES.with(|frames| {
+        frames
+            .borrow()
+            .iter()
+            .rev()
+            .map(|frame| match &frame.name {
+                Some(name) => format!("in {name}(), called on line {}", frame.line),
+                None => format!("in a function called on line {}", frame.line),
+            })
+            .collect::<Vec<String>>()
+    });
+    let error = RuntimeError::new(paren.clone(), String::from("Stopped by Ctrl-C."))
+        .with_kind(ErrorKind::Interrupted);
+    Err(notes.into_iter().fold(error, RuntimeError::with_note))
+}
@@ -1,39 +1,89 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::collections::HashMap;
 
 use once_cell::sync::Lazy;
-use strum_macros::Display;
+use strum_macros::{Display, EnumString};
+
+use diagnostic::{Diagnostic, Severity};
+use parse::Parser;
+use scan::Scanner;
 
 pub mod ast;
+pub mod cache;
+pub mod callable;
+pub mod diagnostic;
 pub mod environment;
+pub mod explain;
 pub mod expression;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod hooks;
+pub mod incremental;
 pub mod interpret;
+pub mod interrupt;
+pub mod limits;
+pub mod native;
 pub mod parse;
+pub mod profile;
 pub mod scan;
 pub mod statement;
+pub mod stats;
+pub mod suggest;
 pub mod token;
+pub mod watch;
+
+/// Prints a scanner error and its location into stderr, quoting the offending source line
+pub fn report(line: usize, column: usize, code: &str, message: &str) {
+    diagnostic::emit(Diagnostic {
+        severity: Severity::Error,
+        code,
+        header: format!("[line {}] Error: {}", line, message),
+        message: message.to_string(),
+        source: diagnostic::current_source(),
+        line,
+        column,
+        width: 1,
+        notes: &[],
+    });
+}
 
-/// Prints an error message and the location into stderr
-pub fn report(line: usize, location: &str, message: &str) {
-    eprintln!("[line {}] Error{}: {}", line, location, message);
+/// Fuzzing entry point for the scanner. Must never panic, whatever the input.
+pub fn fuzz_scan(data: &[u8]) {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+    }
 }
 
-#[derive(Debug, Display, Copy, Clone, Eq, PartialEq)]
+/// Fuzzing entry point for the scanner and parser. Must never panic, whatever the input.
+pub fn fuzz_parse(data: &[u8]) {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        let mut parser = Parser::new(scanner.tokens);
+        let _ = parser.parse();
+    }
+}
+
+#[derive(Debug, Display, EnumString, Copy, Clone, Eq, PartialEq, Hash)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum TokenType {
     // Single-character tokens
-    LeftParen,  // (
-    RightParen, // )
-    LeftBrace,  // {
-    RightBrace, // }
-    Comma,      // ,
-    Dot,        // .
-    Minus,      // -
-    Plus,       // +
-    Semicolon,  // ;
-    Slash,      // /
-    Star,       // *
+    LeftParen,    // (
+    RightParen,   // )
+    LeftBrace,    // {
+    RightBrace,   // }
+    LeftBracket,  // [
+    RightBracket, // ]
+    Comma,        // ,
+    Dot,          // .
+    Minus,        // -
+    Plus,         // +
+    Semicolon,    // ;
+    Slash,        // /
+    Star,         // *
 
     // One or two-character tokens
+    Arrow,        // ->
     Bang,         // !
     BangEqual,    // !=
     Equal,        // =
@@ -47,44 +97,61 @@ pub enum TokenType {
     Identifier,
     String,
     Number,
+    /// The part of a string literal that precedes an interpolated `${expression}`
+    Interpolation,
+
+    // Trivia, only scanned in lossless mode
+    Whitespace,
+    Comment,
 
     // Keywords
     And,
+    Catch,
     Class,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
     Eof,
 }
 
-pub static KEYWORDS: Lazy<Mutex<HashMap<String, TokenType>>> = Lazy::new(|| {
+/// Reserved words and the token types they scan to
+pub static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
     let mut m = HashMap::new();
-    m.insert(String::from("and"), TokenType::And);
-    m.insert(String::from("class"), TokenType::Class);
-    m.insert(String::from("else"), TokenType::Else);
-    m.insert(String::from("false"), TokenType::False);
-    m.insert(String::from("fun"), TokenType::Fun);
-    m.insert(String::from("for"), TokenType::For);
-    m.insert(String::from("if"), TokenType::If);
-    m.insert(String::from("nil"), TokenType::Nil);
-    m.insert(String::from("or"), TokenType::Or);
-    m.insert(String::from("print"), TokenType::Print);
-    m.insert(String::from("return"), TokenType::Return);
-    m.insert(String::from("super"), TokenType::Super);
-    m.insert(String::from("this"), TokenType::This);
-    m.insert(String::from("true"), TokenType::True);
-    m.insert(String::from("var"), TokenType::Var);
-    m.insert(String::from("while"), TokenType::While);
-    Mutex::new(m)
+    m.insert("and", TokenType::And);
+    m.insert("catch", TokenType::Catch);
+    m.insert("class", TokenType::Class);
+    m.insert("else", TokenType::Else);
+    m.insert("false", TokenType::False);
+    m.insert("finally", TokenType::Finally);
+    m.insert("fun", TokenType::Fun);
+    m.insert("for", TokenType::For);
+    m.insert("if", TokenType::If);
+    m.insert("in", TokenType::In);
+    m.insert("nil", TokenType::Nil);
+    m.insert("or", TokenType::Or);
+    m.insert("print", TokenType::Print);
+    m.insert("return", TokenType::Return);
+    m.insert("super", TokenType::Super);
+    m.insert("this", TokenType::This);
+    m.insert("throw", TokenType::Throw);
+    m.insert("true", TokenType::True);
+    m.insert("try", TokenType::Try);
+    m.insert("var", TokenType::Var);
+    m.insert("while", TokenType::While);
+    m
 });
@@ -0,0 +1,209 @@
+use crate::{
+    diagnostic::{self, Diagnostic, Severity},
+    expression::{ErrorKind, RuntimeError},
+    token::Token,
+};
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// How much work a program may do before it's stopped, given to
+/// `Interpreter::with_limits`
+#[derive(Clone, Copy, Debug)]
+pub struct Budget {
+    steps_left: Option<u64>,
+    time_left: Option<Duration>,
+}
+
+impl Budget {
+    /// `max_steps` steps and `max_millis` milliseconds of running time, or None if
+    /// neither is limited
+    pub fn new(max_steps: Option<u64>, max_millis: Option<u64>) -> Option<Self> {
+        match (max_steps, max_millis) {
+            (None, None) => None,
+            _ => Some(Budget {
+                steps_left: max_steps,
+                time_left: max_millis.map(Duration::from_millis),
+            }),
+        }
+    }
+}
+
+/// The budget of the running program, whose time runs out at a fixed deadline
+#[derive(Clone, Copy)]
+struct Running {
+    steps_left: Option<u64>,
+    deadline: Option<Instant>,
+}
+
+impl Running {
+    fn remaining(self) -> Budget {
+        Budget {
+            steps_left: self.steps_left,
+            time_left: self
+                .deadline
+                .map(|d| d.saturating_duration_since(Instant::now())),
+        }
+    }
+}
+
+/// The budget that was being spent when `start` was called, which `finish` puts back
+pub struct Outer(Option<Running>);
+
+/// How many calls in a row a program may make before it fails with a stack overflow,
+/// unless `Interpreter::with_max_call_depth` says otherwise. Each call takes up to
+/// about 10 KiB of the native stack in debug builds, more if it nests deeply, so the
+/// thread running the interpreter needs a stack several times larger than that.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// The calls in progress, and how many there may be
+#[derive(Clone, Copy)]
+struct Calls {
+    depth: usize,
+    max: usize,
+}
+
+/// How long a program has gone without showing that it's getting anywhere
+#[derive(Clone, Copy)]
+struct Stall {
+    warn_after: u64,
+    steps: u64,
+}
+
+thread_local! {
+    static BUDGET: Cell<Option<Running>> = const { Cell::new(None) };
+    static STALL: Cell<Option<Stall>> = const { Cell::new(None) };
+    static CALLS: Cell<Calls> = const {
+        Cell::new(Calls {
+            depth: 0,
+            max: MAX_CALL_DEPTH,
+        })
+    };
+}
+
+/// Starts spending `budget` on the code run from now until `finish`. `None` leaves
+/// the program unlimited. Its time starts counting now.
+pub fn start(budget: Option<Budget>) -> Outer {
+    let running = budget.map(|budget| Running {
+        steps_left: budget.steps_left,
+        deadline: budget.time_left.map(|time| Instant::now() + time),
+    });
+    Outer(BUDGET.replace(running))
+}
+
+/// Stops spending the budget given to `start`, returning what's left of it, and
+/// goes back to the budget that was being spent before
+pub fn finish(outer: Outer) -> Option<Budget> {
+    BUDGET.replace(outer.0).map(Running::remaining)
+}
+
+/// Spends one step of the budget on a call or loop iteration at `token`.
+///
+/// Calls and iterations of `for` loops are the steps counted, since recursion and
+/// loops are the only ways a Lox program can keep running. Once the budget is spent
+/// every step fails, so a `catch` can clean up but not keep the program going.
+pub fn step(token: &Token) -> Result<()> {
+    if let Some(mut stall) = STALL.get() {
+        stall.steps += 1;
+        if stall.steps == stall.warn_after {
+            warn_stalled(token, stall.steps);
+        }
+        STALL.set(Some(stall));
+    }
+    let Some(mut budget) = BUDGET.get() else {
+        return Ok(());
+    };
+    let out_of_steps = budget.steps_left == Some(0);
+    let out_of_time = budget.deadline.is_some_and(|d| Instant::now() >= d);
+    if out_of_steps || out_of_time {
+        return Err(exceeded(token));
+    }
+    budget.steps_left = budget.steps_left.map(|steps| steps - 1);
+    BUDGET.set(Some(budget));
+    Ok(())
+}
+
+/// Sets how many calls in a row the program may make, returning the previous maximum
+pub fn set_max_call_depth(max: usize) -> usize {
+    let calls = CALLS.get();
+    CALLS.set(Calls { max, ..calls });
+    calls.max
+}
+
+/// Records the start of a call at `token`, or fails with a `StackOverflow` error if
+/// there are too many calls in progress already. Unlike running out of budget, that
+/// can be caught, since returning from the calls frees up the stack again.
+pub fn enter_call(token: &Token) -> Result<()> {
+    let calls = CALLS.get();
+    if calls.depth >= calls.max {
+        return Err(
+            RuntimeError::new(token.clone(), String::from("Stack overflow."))
+                .with_kind(ErrorKind::StackOverflow),
+        );
+    }
+    CALLS.set(Calls {
+        depth: calls.depth + 1,
+        ..calls
+    });
+    Ok(())
+}
+
+/// Records the end of a call started with `enter_call`
+pub fn exit_call() {
+    let calls = CALLS.get();
+    CALLS.set(Calls {
+        depth: calls.depth - 1,
+        ..calls
+    });
+}
+
+/// How long the running program has until its time runs out, if its time is limited.
+/// Natives that wait, like `sleep()`, must not wait any longer than that.
+pub fn time_left() -> Option<Duration> {
+    let deadline = BUDGET.get()?.deadline?;
+    Some(deadline.saturating_duration_since(Instant::now()))
+}
+
+/// The error raised at `token` once the budget is spent
+pub fn exceeded(token: &Token) -> RuntimeError {
+    RuntimeError::new(token.clone(), String::from("Execution budget exceeded."))
+        .with_kind(ErrorKind::BudgetExceeded)
+}
+
+/// Warns once the program has taken `steps` steps in a row without printing anything
+/// or defining a global variable, as it's then likely stuck in an infinite loop.
+/// `None` turns the warning off.
+pub fn set_stall_warning(steps: Option<u64>) {
+    STALL.set(steps.map(|warn_after| Stall {
+        warn_after,
+        steps: 0,
+    }));
+}
+
+/// Records that the program printed something or defined a global variable
+pub fn progress() {
+    if let Some(stall) = STALL.get() {
+        STALL.set(Some(Stall { steps: 0, ..stall }));
+    }
+}
+
+fn warn_stalled(paren: &Token, steps: u64) {
+    let message = format!(
+        "No output or new global variables in {steps} steps, this may be an infinite loop. \
+         Press Ctrl-C to stop it."
+    );
+    diagnostic::emit(Diagnostic {
+        severity: Severity::Warning,
+        code: "PossibleInfiniteLoop",
+        header: format!("[line {}] Warning: {message}", paren.line),
+        message,
+        source: paren.source,
+        line: paren.line,
+        column: paren.column,
+        width: diagnostic::token_width(paren),
+        notes: &[],
+    });
+}
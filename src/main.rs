@@ -1,30 +1,68 @@
-use clap::{Args, Parser, Subcommand};
-use std::{fs, process::ExitCode};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use std::{
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
 
 use codecrafters_interpreter::{
-    ast::print_expr,
-    environment::Environment,
-    expression::Expression,
-    interpret::{interpret_single_expr, Interpreter},
+    ast::{print_expr, print_resolutions, print_stmts},
+    cache,
+    diagnostic::{self, ErrorFormat},
+    explain::explain,
+    expression::{ErrorKind, Expression},
+    interpret::{default_environment, evaluate_expr, stringify, Interpreter, LoxError, PRELUDE},
+    interrupt,
+    native::Capability,
     parse,
-    scan::Scanner,
+    profile::Profiler,
+    scan::{decode_source, Scanner, TokenFormat},
     statement::Statement,
+    stats::{self, CountingAllocator},
     token::Token,
+    watch::{changed, modification_times},
 };
 
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Print errors without color, which is also the default when stderr isn't a terminal
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// How errors are printed to stderr
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Human)]
+    error_format: ErrorFormat,
+    /// Treat warnings as errors, so the program isn't run if there are any
+    #[arg(long, global = true)]
+    deny_warnings: bool,
+    /// Replace bytes of the script that aren't valid UTF-8 with U+FFFD instead of
+    /// refusing to read it
+    #[arg(long, global = true)]
+    lossy: bool,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
-    Tokenize(FilenameArg),
-    Parse(FilenameArg),
+    Tokenize(TokenizeArgs),
+    Parse(ParseArgs),
     Evaluate(FilenameArg),
-    Run(FilenameArg),
+    Run(RunArgs),
+    /// Scan and parse a program, reporting errors and warnings without running it
+    Check(FilenameArg),
+    /// Print every statement of a program with the tree it's parsed into and the
+    /// order its expressions are evaluated in, without running it
+    Explain(FilenameArg),
+    /// Scan and check a program, saving its tokens so `run` doesn't have to scan it again
+    Compile(CompileArgs),
 }
 
 #[derive(Args, Debug)]
@@ -32,80 +70,535 @@ struct FilenameArg {
     filename: String,
 }
 
+#[derive(Args, Debug)]
+struct TokenizeArgs {
+    filename: String,
+    /// How tokens are printed
+    #[arg(long, value_enum, default_value_t = TokenFormat::Text)]
+    format: TokenFormat,
+    /// Scan whitespace and comments too, and check that the tokens add up to the
+    /// source again instead of printing them
+    #[arg(long)]
+    roundtrip: bool,
+}
+
+#[derive(Args, Debug)]
+struct ParseArgs {
+    filename: String,
+    /// Parse a single expression instead of a whole program
+    #[arg(long)]
+    expr: bool,
+    /// Print every variable reference with the number of scopes up it refers to,
+    /// or `global`, instead of the syntax tree
+    #[arg(long, conflicts_with = "expr")]
+    dump_resolved: bool,
+}
+
+#[derive(Args, Debug)]
+struct CompileArgs {
+    filename: String,
+    /// Where to write the cache. `run` only looks for it next to the script,
+    /// which is also the default: `script.lox` is compiled to `script.loxc`.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// Scripts to run one after another, sharing their global variables
+    #[arg(required = true)]
+    filenames: Vec<String>,
+    /// Report failed assertions and keep running instead of aborting
+    #[arg(long)]
+    keep_going: bool,
+    /// Capabilities to allow besides `time` and `stdin`, like `--allow fs,env`
+    #[arg(long, value_delimiter = ',')]
+    allow: Vec<Capability>,
+    /// Allow the script to read and write files, like `--allow fs`
+    #[arg(long)]
+    allow_fs: bool,
+    /// Allow the script to read and set environment variables, like `--allow env`
+    #[arg(long)]
+    allow_env: bool,
+    /// Allow the script to run other programs, like `--allow exec`
+    #[arg(long)]
+    allow_exec: bool,
+    /// Print timings, counts and peak memory usage after running
+    #[arg(long)]
+    stats: bool,
+    /// Print the 20 source lines that took the longest to run, with execution counts
+    #[arg(long)]
+    profile: bool,
+    /// Raise an error when dividing by zero instead of giving `inf` or `nan`
+    #[arg(long)]
+    strict_math: bool,
+    /// Run the garbage collector before every new scope, for testing the collector
+    #[arg(long)]
+    gc_stress: bool,
+    /// Don't define the helpers of the standard prelude, like `map` and `compose`
+    #[arg(long)]
+    no_prelude: bool,
+    /// Run the scripts again whenever one of them changes. Only the files given
+    /// here are watched, not files they read while running.
+    #[arg(long)]
+    watch: bool,
+    /// Arguments passed to the script, given after `--`
+    #[arg(last = true)]
+    args: Vec<String>,
+}
+
+/// Stack size of the thread the commands run on, with room for `limits::MAX_CALL_DEPTH`
+/// calls of deeply nested code
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
 fn main() -> ExitCode {
+    // Deep recursion has to end in a `StackOverflow` error, before it would overflow
+    // the much smaller stack of the main thread
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_command)
+        .expect("to be able to start the interpreter thread")
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+fn run_command() -> ExitCode {
     let args = Cli::parse();
 
     let parse_err_exit_code: ExitCode = ExitCode::from(65);
     let runtime_err_exit_code: ExitCode = ExitCode::from(70);
 
+    diagnostic::set_color(!args.no_color && io::stderr().is_terminal());
+    diagnostic::set_format(args.error_format);
+
     match &args.command {
         Commands::Tokenize(f) => {
-            let file_contents =
-                fs::read_to_string(&f.filename).expect("unable to read the given file");
-            match tokenize(file_contents) {
-                Ok(scanner) => println!("{scanner}"),
-                Err(scanner) => {
-                    println!("{scanner}");
-                    return parse_err_exit_code;
-                }
+            let file_contents = match read_source(&f.filename, args.lossy) {
+                Ok(source) => source,
+                Err(status) => return ExitCode::from(status),
+            };
+            diagnostic::set_source(&f.filename, &file_contents);
+            if f.roundtrip {
+                return ExitCode::from(roundtrip(&file_contents));
+            }
+            let scanner = tokenize(file_contents);
+            println!("{}", scanner.render(f.format));
+            if scanner.has_error() {
+                return parse_err_exit_code;
             }
         }
         Commands::Parse(f) => {
-            let file_contents =
-                fs::read_to_string(&f.filename).expect("unable to read the given file");
-            match tokenize(file_contents) {
-                Ok(scanner) => match parse_print_single_expr(scanner.tokens) {
-                    Ok(expr) => print_expr(&expr),
+            let file_contents = match read_source(&f.filename, args.lossy) {
+                Ok(source) => source,
+                Err(status) => return ExitCode::from(status),
+            };
+            diagnostic::set_source(&f.filename, &file_contents);
+            let scanner = tokenize(file_contents);
+            if scanner.has_error() {
+                return parse_err_exit_code;
+            }
+            if f.expr {
+                match parse_print_single_expr(scanner.tokens) {
+                    Ok(_) if denied_warnings(args.deny_warnings) => return parse_err_exit_code,
+                    Ok(expr) => print_expr(expr.as_ref()),
                     Err(_) => return parse_err_exit_code,
-                },
-                Err(_) => return parse_err_exit_code,
+                }
+            } else if f.dump_resolved {
+                let mut parser = parse::Parser::new(scanner.tokens).with_resolutions();
+                match parser.parse() {
+                    Ok(_) if denied_warnings(args.deny_warnings) => return parse_err_exit_code,
+                    Ok(_) => print_resolutions(parser.resolutions()),
+                    Err(_) => return parse_err_exit_code,
+                }
+            } else {
+                match parse(scanner.tokens) {
+                    Ok(_) if denied_warnings(args.deny_warnings) => return parse_err_exit_code,
+                    Ok(stmts) => print_stmts(&stmts),
+                    Err(_) => return parse_err_exit_code,
+                }
             }
         }
         Commands::Evaluate(f) => {
-            let file_contents =
-                fs::read_to_string(&f.filename).expect("unable to read the given file");
-            match tokenize(file_contents) {
-                Ok(scanner) => match parse_print_single_expr(scanner.tokens) {
-                    Ok(expr) => {
-                        let mut environment = Environment::new(None);
-                        match interpret_single_expr(expr, &mut environment) {
-                            Ok(_) => return ExitCode::SUCCESS,
-                            Err(_) => return runtime_err_exit_code,
-                        }
+            let file_contents = match read_source(&f.filename, args.lossy) {
+                Ok(source) => source,
+                Err(status) => return ExitCode::from(status),
+            };
+            diagnostic::set_source(&f.filename, &file_contents);
+            let value = evaluate_expr(&file_contents, &mut default_environment());
+            // Warnings are only known once it's parsed, so a denied one stops it
+            // from being printed
+            if denied_warnings(args.deny_warnings) {
+                return parse_err_exit_code;
+            }
+            match value {
+                Ok(None) => (),
+                Ok(value) => println!("{}", stringify(value)),
+                Err(LoxError::Runtime(e)) => match e.kind {
+                    ErrorKind::Exit(code) => return ExitCode::from(code),
+                    _ => {
+                        e.report();
+                        return runtime_err_exit_code;
                     }
-                    Err(_) => return runtime_err_exit_code,
                 },
+                // Scan and parse errors have been reported already
                 Err(_) => return parse_err_exit_code,
             }
         }
-        Commands::Run(f) => {
-            let file_contents =
-                fs::read_to_string(&f.filename).expect("unable to read the given file");
-            match tokenize(file_contents) {
-                Ok(scanner) => match parse(scanner.tokens) {
-                    Ok(stmts) => {
-                        let mut interpreter = Interpreter::new(stmts);
-                        match interpreter.interpret() {
-                            Ok(_) => return ExitCode::SUCCESS,
-                            Err(_) => return runtime_err_exit_code,
-                        }
-                    }
-                    Err(_) => return parse_err_exit_code,
-                },
+        // Profiles are reported by line, which would be ambiguous across files
+        Commands::Run(f) if f.profile && f.filenames.len() > 1 => Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--profile can only be used when running a single file",
+            )
+            .exit(),
+        Commands::Run(f) if f.watch => watch(f, args.deny_warnings, args.lossy),
+        Commands::Run(f) => return ExitCode::from(run(f, args.deny_warnings, args.lossy)),
+        Commands::Check(f) => {
+            let file_contents = match read_source(&f.filename, args.lossy) {
+                Ok(source) => source,
+                Err(status) => return ExitCode::from(status),
+            };
+            diagnostic::set_source(&f.filename, &file_contents);
+            let scanner = tokenize(file_contents);
+            if scanner.has_error() {
+                return parse_err_exit_code;
+            }
+            match parse(scanner.tokens) {
+                Ok(_) if denied_warnings(args.deny_warnings) => return parse_err_exit_code,
+                Ok(_) => (),
                 Err(_) => return parse_err_exit_code,
             }
         }
+        Commands::Explain(f) => {
+            let file_contents = match read_source(&f.filename, args.lossy) {
+                Ok(source) => source,
+                Err(status) => return ExitCode::from(status),
+            };
+            diagnostic::set_source(&f.filename, &file_contents);
+            let scanner = tokenize(file_contents.clone());
+            if scanner.has_error() {
+                return parse_err_exit_code;
+            }
+            match parse(scanner.tokens) {
+                Ok(_) if denied_warnings(args.deny_warnings) => return parse_err_exit_code,
+                Ok(stmts) => print!("{}", explain(&file_contents, &stmts)),
+                Err(_) => return parse_err_exit_code,
+            }
+        }
+        Commands::Compile(f) => {
+            let file_contents = match read_source(&f.filename, args.lossy) {
+                Ok(source) => source,
+                Err(status) => return ExitCode::from(status),
+            };
+            diagnostic::set_source(&f.filename, &file_contents);
+            let scanner = tokenize(file_contents.clone());
+            if scanner.has_error() {
+                return parse_err_exit_code;
+            }
+            match parse(scanner.tokens.clone()) {
+                Ok(_) if denied_warnings(args.deny_warnings) => return parse_err_exit_code,
+                Ok(_) => (),
+                Err(_) => return parse_err_exit_code,
+            }
+            let output = f.output.clone().unwrap_or_else(|| cache_path(&f.filename));
+            if let Err(e) = cache::write(&output, &file_contents, &scanner.tokens) {
+                eprintln!("Can't write '{}': {e}.", output.display());
+                return ExitCode::from(74);
+            }
+        }
     }
     ExitCode::SUCCESS
 }
 
-fn tokenize(file_contents: String) -> Result<Scanner, Scanner> {
-    let mut scanner = Scanner::new(file_contents);
+/// Reads and decodes the script, or says why it can't and returns the exit status
+fn read_source(filename: &str, lossy: bool) -> Result<String, u8> {
+    let bytes = match fs::read(filename) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Can't read '{filename}': {e}.");
+            return Err(66);
+        }
+    };
+    decode_source(bytes, lossy).map_err(|e| {
+        let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+        let line = valid.iter().filter(|b| **b == b'\n').count() + 1;
+        eprintln!(
+            "[line {line}] Error: '{filename}' isn't valid UTF-8. \
+             Run with --lossy to replace the invalid bytes."
+        );
+        65
+    })
+}
+
+/// Where `compile` writes the tokens of a script by default, and where `run` looks for them
+fn cache_path(filename: &str) -> PathBuf {
+    Path::new(filename).with_extension("loxc")
+}
+
+impl RunArgs {
+    /// The capabilities the script is allowed, the defaults plus those asked for
+    fn capabilities(&self) -> Vec<Capability> {
+        let mut allowed = Capability::DEFAULT.to_vec();
+        allowed.extend(&self.allow);
+        if self.allow_fs {
+            allowed.push(Capability::Fs);
+        }
+        if self.allow_env {
+            allowed.push(Capability::Env);
+        }
+        if self.allow_exec {
+            allowed.push(Capability::Exec);
+        }
+        allowed
+    }
+}
+
+/// Scans, parses and runs the script, returning the process exit status
+fn run(f: &RunArgs, deny_warnings: bool, lossy: bool) -> u8 {
+    let mut run_stats = RunStats::default();
+    let status = run_phases(f, deny_warnings, lossy, &mut run_stats);
+    if f.stats {
+        run_stats.report();
+    }
+    status
+}
+
+fn run_phases(f: &RunArgs, deny_warnings: bool, lossy: bool, run_stats: &mut RunStats) -> u8 {
+    let parse_err_exit_code = 65;
+    let runtime_err_exit_code = 70;
+
+    diagnostic::reset_warnings();
+    let profiler = f.profile.then(|| Rc::new(Profiler::new()));
+
+    // Every file is parsed before any of them runs, so a syntax error anywhere
+    // stops the program before it has any effect
+    // Only the user's scripts are profiled
+    let mut sources = Vec::new();
+    if !f.no_prelude {
+        sources.push((String::from("<prelude>"), PRELUDE.to_string(), None));
+    }
+    for filename in &f.filenames {
+        match read_source(filename, lossy) {
+            Ok(source) => sources.push((filename.clone(), source, profiler.as_ref())),
+            Err(status) => return status,
+        }
+    }
+
+    let mut scripts = Vec::new();
+    for (filename, file_contents, profiler) in sources {
+        diagnostic::set_source(&filename, &file_contents);
+        match parse_script(&filename, &file_contents, profiler, run_stats) {
+            Some(stmts) => scripts.push((filename, file_contents, stmts)),
+            None => return parse_err_exit_code,
+        }
+    }
+    if denied_warnings(deny_warnings) {
+        return parse_err_exit_code;
+    }
+
+    let (start, environments) = (Instant::now(), stats::environments());
+    let mut interpreter = Interpreter::new(Vec::new())
+        .with_keep_going(f.keep_going)
+        .with_capabilities(&f.capabilities())
+        .with_args(f.args.clone())
+        .with_strict_math(f.strict_math)
+        .with_gc_stress(f.gc_stress)
+        .with_stall_warning(f.watch.then_some(STALL_WARNING_STEPS));
+    // Watch mode keeps running between scripts, where Ctrl-C has to quit right away
+    if !f.watch {
+        interrupt::install_handler();
+    }
+    let mut result = Ok(());
+    for (filename, file_contents, stmts) in &mut scripts {
+        diagnostic::set_source(filename, file_contents);
+        result = interpreter.run_statements(std::mem::take(stmts));
+        if result.is_err() {
+            break;
+        }
+    }
+    run_stats.execute = Some(start.elapsed());
+    run_stats.environments = stats::environments() - environments;
+
+    if f.keep_going {
+        report_assertions(interpreter.assertion_failures());
+    }
+    if let (Some(profiler), Some((_, file_contents, _))) = (&profiler, scripts.last()) {
+        profiler.report(file_contents, 20);
+    }
+    match result {
+        Ok(_) if interpreter.assertion_failures() > 0 => return runtime_err_exit_code,
+        Ok(_) => return 0,
+        Err(e) => match e.kind {
+            ErrorKind::Exit(code) => return code,
+            ErrorKind::Interrupted => {
+                e.report();
+                return 130;
+            }
+            _ => {
+                e.report();
+                return runtime_err_exit_code;
+            }
+        },
+    }
+}
+
+/// Scans and parses one script for `run`, adding to the stats. Returns `None`
+/// if it has errors, which have already been reported.
+fn parse_script(
+    filename: &str,
+    file_contents: &str,
+    profiler: Option<&Rc<Profiler>>,
+    run_stats: &mut RunStats,
+) -> Option<Vec<Box<dyn Statement>>> {
+    // A cache from `compile` is only used if it was compiled from the current source
+    let start = Instant::now();
+    let tokens = match cache::read(&cache_path(filename), file_contents) {
+        Some(tokens) => tokens,
+        None => {
+            let scanner = tokenize(file_contents.to_string());
+            if scanner.has_error() {
+                *run_stats.scan.get_or_insert(Duration::ZERO) += start.elapsed();
+                return None;
+            }
+            scanner.tokens
+        }
+    };
+    *run_stats.scan.get_or_insert(Duration::ZERO) += start.elapsed();
+    run_stats.tokens += tokens.len();
+
+    let (start, nodes) = (Instant::now(), stats::ast_nodes());
+    let mut parser = parse::Parser::new(tokens);
+    if let Some(profiler) = profiler {
+        parser = parser.with_profiler(profiler.clone());
+    }
+    let parsed = parser.parse();
+    *run_stats.parse.get_or_insert(Duration::ZERO) += start.elapsed();
+    run_stats.ast_nodes += stats::ast_nodes() - nodes;
+    parsed.ok()
+}
+
+/// What `run --stats` reports. Phases that didn't run are left out.
+#[derive(Default)]
+struct RunStats {
+    scan: Option<Duration>,
+    tokens: usize,
+    parse: Option<Duration>,
+    ast_nodes: usize,
+    execute: Option<Duration>,
+    environments: usize,
+}
+
+impl RunStats {
+    fn report(&self) {
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        eprintln!("--- stats");
+        if let Some(scan) = self.scan {
+            eprintln!(
+                "scan:        {:>9.3} ms  {} tokens",
+                millis(scan),
+                self.tokens
+            );
+        }
+        if let Some(parse) = self.parse {
+            eprintln!(
+                "parse:       {:>9.3} ms  {} AST nodes",
+                millis(parse),
+                self.ast_nodes
+            );
+        }
+        if let Some(execute) = self.execute {
+            eprintln!(
+                "execute:     {:>9.3} ms  {} environments created",
+                millis(execute),
+                self.environments
+            );
+        }
+        eprintln!(
+            "peak memory: {:>9.1} KiB",
+            stats::peak_bytes() as f64 / 1024.0
+        );
+    }
+}
+
+/// Calls and loop iterations without output or new global variables after which
+/// `--watch` warns that the script may be stuck, since it's left running while the
+/// script is edited
+const STALL_WARNING_STEPS: u64 = 10_000_000;
+
+/// Reruns the scripts every time one of them is modified, clearing the screen before
+/// each run. Only returns if a script can't be found at all.
+fn watch(f: &RunArgs, deny_warnings: bool, lossy: bool) {
+    let mut last_run = modification_times(&f.filenames);
+    if let Some(missing) = last_run.iter().position(Option::is_none) {
+        eprintln!("Can't watch '{}': no such file.", f.filenames[missing]);
+        return;
+    }
+    loop {
+        print!("\x1b[2J\x1b[H");
+        let _ = io::stdout().flush();
+        let status = run(f, deny_warnings, lossy);
+        eprintln!(
+            "[exited with status {status}, waiting for changes to {}]",
+            f.filenames.join(", ")
+        );
+
+        loop {
+            thread::sleep(Duration::from_millis(250));
+            let current = modification_times(&f.filenames);
+            if changed(&last_run, &current) {
+                last_run = current;
+                break;
+            }
+        }
+    }
+}
+
+/// Whether `--deny-warnings` turns the warnings emitted so far into a failure
+fn denied_warnings(deny: bool) -> bool {
+    let warnings = diagnostic::warning_count();
+    if deny && warnings > 0 {
+        let plural = if warnings == 1 { "" } else { "s" };
+        eprintln!("Aborting because of --deny-warnings ({warnings} warning{plural}).");
+        return true;
+    }
+    false
+}
+
+/// Prints the summary line for `run --keep-going`
+fn report_assertions(failures: usize) {
+    match failures {
+        0 => eprintln!("All assertions passed."),
+        1 => eprintln!("1 assertion failed."),
+        n => eprintln!("{n} assertions failed."),
+    }
+}
+
+/// Scans the source with trivia and checks that its tokens reproduce it exactly,
+/// returning the process exit status
+fn roundtrip(source: &str) -> u8 {
+    let mut scanner = Scanner::new(source.to_string()).with_trivia(true);
     scanner.scan_tokens();
-    if scanner.has_error {
-        return Err(scanner);
+    if scanner.has_error() {
+        return 65;
     }
-    Ok(scanner)
+    let text = scanner.source_text();
+    if text == source {
+        println!("Round trip OK: {} tokens.", scanner.tokens.len());
+        return 0;
+    }
+    let offset = text
+        .bytes()
+        .zip(source.bytes())
+        .position(|(a, b)| a != b)
+        .unwrap_or(text.len().min(source.len()));
+    eprintln!("Round trip failed: the tokens differ from the source at byte {offset}.");
+    70
+}
+
+fn tokenize(file_contents: String) -> Scanner {
+    let mut scanner = Scanner::new(file_contents);
+    scanner.scan_tokens();
+    scanner
 }
 
 fn parse_print_single_expr(tokens: Vec<Token>) -> Result<Box<dyn Expression>, parse::ParserError> {
@@ -0,0 +1,1345 @@
+use crate::{
+    callable::NativeFunction,
+    environment::{self, Environment},
+    expression::{ErrorKind, RuntimeError},
+    hooks,
+    interpret::{self, is_truthy, stringify},
+    interrupt, limits,
+    token::{
+        format_number, BooleanLiteral, LiteralType, LiteralValue, NilLiteral, NumberLiteral,
+        RangeLiteral, StringLiteral, Token,
+    },
+};
+use clap::ValueEnum;
+use std::{
+    env, fs,
+    io::{self, Write},
+    iter::Peekable,
+    ops::RangeInclusive,
+    process::Command,
+    rc::Rc,
+    str::Chars,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// A group of natives that reach outside the interpreter, which embedders and
+/// `run --allow` enable individually to sandbox scripts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Capability {
+    /// `read_file`, `write_file` and `append_file`
+    Fs,
+    /// `getenv` and `setenv`
+    Env,
+    /// `clock`, `now_millis`, `now` and `sleep`
+    Time,
+    /// `readline` and `read_number`
+    Stdin,
+    /// `exec`
+    Exec,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::Fs,
+        Capability::Env,
+        Capability::Time,
+        Capability::Stdin,
+        Capability::Exec,
+    ];
+
+    /// The capabilities scripts have unless the embedder says otherwise
+    pub const DEFAULT: [Capability; 2] = [Capability::Time, Capability::Stdin];
+
+    /// The name `--allow` takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Fs => "fs",
+            Capability::Env => "env",
+            Capability::Time => "time",
+            Capability::Stdin => "stdin",
+            Capability::Exec => "exec",
+        }
+    }
+
+    fn natives(&self) -> Vec<NativeFunction> {
+        match self {
+            Capability::Fs => vec![read_file(), write_file(), append_file()],
+            Capability::Env => vec![getenv(), setenv()],
+            Capability::Time => {
+                let start = Instant::now();
+                vec![clock(start), now_millis(start), now(), sleep()]
+            }
+            Capability::Stdin => vec![readline(), read_number()],
+            Capability::Exec => vec![exec()],
+        }
+    }
+}
+
+/// Defines the natives that need no capability in the given (global) environment
+pub fn define_natives(env: &mut Environment) {
+    let natives = vec![
+        assert(),
+        collect_garbage(),
+        doc(),
+        ends_with(),
+        exit(),
+        filter(),
+        sort(),
+        format(),
+        format_time(),
+        globals(),
+        len(),
+        parse_int(),
+        parse_number(),
+        range(),
+        reduce(),
+        replace(),
+        repr(),
+        starts_with(),
+        to_fixed(),
+        to_hex(),
+        to_number(),
+        to_string(),
+        trim(),
+        type_of(),
+        write(),
+    ];
+    for native in natives {
+        env.define(native.name.clone(), Some(Box::new(native)));
+    }
+}
+
+/// Defines the natives of `capability`. When it isn't `allowed` they are still
+/// defined, but raise an error saying so instead of doing anything.
+pub fn define_capability_natives(env: &mut Environment, capability: Capability, allowed: bool) {
+    for native in capability.natives() {
+        let native = if allowed {
+            native
+        } else {
+            disabled(native, capability)
+        };
+        env.define(native.name.clone(), Some(Box::new(native)));
+    }
+}
+
+/// Defines the natives of every capability, enabling the `allowed` ones
+pub fn define_capabilities(env: &mut Environment, allowed: &[Capability]) {
+    for capability in Capability::ALL {
+        define_capability_natives(env, capability, allowed.contains(&capability));
+    }
+}
+
+/// Replaces a native with one that takes the same arguments but always fails
+fn disabled(native: NativeFunction, capability: Capability) -> NativeFunction {
+    let message = format!(
+        "{}() is disabled because the '{}' capability isn't allowed.",
+        native.name,
+        capability.name()
+    );
+    NativeFunction {
+        function: Rc::new(move |_env, _arguments, paren| {
+            Err(RuntimeError::new(paren.clone(), message.clone()))
+        }),
+        ..native
+    }
+}
+
+/// Defines `argc()` and `arg(i)`, which give the script access to its command-line arguments
+pub fn define_arg_natives(env: &mut Environment, args: Vec<String>) {
+    let args = Rc::new(args);
+    let natives = vec![argc(args.clone()), arg(args)];
+    for native in natives {
+        env.define(native.name.clone(), Some(Box::new(native)));
+    }
+}
+
+/// Returns the contents of a string argument, or a runtime error naming the native
+fn expect_string(value: &dyn LiteralValue, native: &str, paren: &Token) -> Result<String> {
+    if value.get_type() != LiteralType::StringLiteral {
+        return Err(RuntimeError::new(
+            paren.clone(),
+            format!("{native}() expects a string but got {}.", value.type_name()),
+        )
+        .with_kind(ErrorKind::TypeError));
+    }
+    Ok(value.print_value())
+}
+
+/// Returns the value of a number argument, or a runtime error naming the native
+fn expect_number(value: &dyn LiteralValue, native: &str, paren: &Token) -> Result<f32> {
+    value.as_number().ok_or_else(|| {
+        RuntimeError::new(
+            paren.clone(),
+            format!("{native}() expects a number but got {}.", value.type_name()),
+        )
+        .with_kind(ErrorKind::TypeError)
+    })
+}
+
+/// Returns a whole number argument within `range`, or a runtime error naming the
+/// native and the parameter
+fn expect_whole_number(
+    value: &dyn LiteralValue,
+    range: RangeInclusive<u32>,
+    native: &str,
+    parameter: &str,
+    paren: &Token,
+) -> Result<u32> {
+    match value.as_number() {
+        Some(n) if n >= 0.0 && n.fract() == 0.0 && range.contains(&(n as u32)) => Ok(n as u32),
+        _ => Err(RuntimeError::new(
+            paren.clone(),
+            format!(
+                "{native}() expects {parameter} to be a whole number from {} to {} but got {}.",
+                range.start(),
+                range.end(),
+                stringify(Some(value.clone_box()))
+            ),
+        )),
+    }
+}
+
+/// Checks that a function argument can be called with `arity` arguments, or returns a
+/// runtime error naming the native
+fn expect_callback(
+    value: &dyn LiteralValue,
+    arity: usize,
+    native: &str,
+    paren: &Token,
+) -> Result<()> {
+    let Some(function) = value.as_callable() else {
+        return Err(RuntimeError::new(
+            paren.clone(),
+            format!(
+                "{native}() expects a function but got {}.",
+                value.type_name()
+            ),
+        )
+        .with_kind(ErrorKind::TypeError));
+    };
+    let takes = |n: usize| function.arity() == n || (function.variadic() && function.arity() <= n);
+    if !takes(arity) {
+        return Err(RuntimeError::new(
+            paren.clone(),
+            format!(
+                "{native}() calls its function with {arity} arguments, but it takes {}.",
+                function.arity()
+            ),
+        )
+        .with_kind(ErrorKind::ArityMismatch));
+    }
+    Ok(())
+}
+
+/// Calls a function passed to a native and checked by `expect_callback`, like a call
+/// written in Lox: it counts against the step limit, stops on Ctrl-C and is seen by
+/// the `on_call` hook
+fn call_back(
+    function: &dyn LiteralValue,
+    env: &mut Environment,
+    arguments: Vec<Box<dyn LiteralValue>>,
+    paren: &Token,
+) -> Result<Box<dyn LiteralValue>> {
+    let callable = function
+        .as_callable()
+        .expect("callbacks are checked by expect_callback");
+    limits::step(paren)?;
+    interrupt::check(paren)?;
+    hooks::call(function, &arguments, paren)?;
+    limits::enter_call(paren)?;
+    let result = callable.call(env, arguments, paren);
+    limits::exit_call();
+    Ok(result?.unwrap_or_else(|| Box::new(NilLiteral)))
+}
+
+/// The number written in `text`, ignoring surrounding whitespace
+fn number_from_text(text: &str) -> Option<f32> {
+    text.trim().parse().ok()
+}
+
+/// Lines of stdin, read by a thread of their own so that waiting for one can time out
+static STDIN_LINES: OnceLock<Mutex<Receiver<io::Result<String>>>> = OnceLock::new();
+
+/// Starts reading stdin on another thread, sending each line with its line ending
+/// and then an empty string at the end of input
+fn stdin_lines() -> Mutex<Receiver<io::Result<String>>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        let mut line = String::new();
+        let result = io::stdin().read_line(&mut line).map(|_| line);
+        let done = !matches!(&result, Ok(line) if !line.is_empty());
+        if sender.send(result).is_err() || done {
+            break;
+        }
+    });
+    Mutex::new(receiver)
+}
+
+/// Reads one line from stdin without its line ending, or None at the end of input.
+/// When the program's time is limited, it stops waiting once the time runs out.
+fn read_stdin_line(paren: &Token) -> Result<Option<String>> {
+    let time_left = limits::time_left();
+    let read = match (time_left, STDIN_LINES.get()) {
+        // Once a thread reads stdin, every line has to come from it to keep their order
+        (None, None) => {
+            let mut line = String::new();
+            io::stdin().read_line(&mut line).map(|_| line)
+        }
+        _ => {
+            let lines = STDIN_LINES
+                .get_or_init(stdin_lines)
+                .lock()
+                .expect("stdin lock poisoned");
+            let received = match time_left {
+                Some(time_left) => lines.recv_timeout(time_left),
+                None => lines.recv().map_err(RecvTimeoutError::from),
+            };
+            match received {
+                Ok(read) => read,
+                Err(RecvTimeoutError::Timeout) => return Err(limits::exceeded(paren)),
+                // The thread stops at the end of input
+                Err(RecvTimeoutError::Disconnected) => Ok(String::new()),
+            }
+        }
+    };
+    match read {
+        Ok(line) if line.is_empty() => Ok(None),
+        Ok(mut line) => {
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            Ok(Some(line))
+        }
+        Err(e) => Err(RuntimeError::new(
+            paren.clone(),
+            format!("Could not read from stdin: {e}"),
+        )),
+    }
+}
+
+/// `assert(condition, message)` raises a runtime error with `message`
+/// if `condition` is falsey
+fn assert() -> NativeFunction {
+    NativeFunction::new(
+        "assert",
+        2,
+        Rc::new(|_env, arguments, paren| {
+            let mut arguments = arguments.into_iter();
+            let condition = arguments.next().expect("arity is checked by the caller");
+            let message = arguments.next().expect("arity is checked by the caller");
+            if is_truthy(Some(condition.as_ref())) {
+                return Ok(None);
+            }
+            Err(RuntimeError::new(paren.clone(), stringify(Some(message)))
+                .with_kind(ErrorKind::Assertion))
+        }),
+    )
+}
+
+/// `repr(value)` returns the value as text that shows its type, with strings quoted
+fn repr() -> NativeFunction {
+    NativeFunction::new(
+        "repr",
+        1,
+        Rc::new(|_env, arguments, _paren| {
+            let value = arguments.into_iter().next();
+            Ok(Some(Box::new(StringLiteral {
+                value: interpret::repr(value),
+            })))
+        }),
+    )
+}
+
+/// `len(string)` returns the number of characters (graphemes) in the string
+fn len() -> NativeFunction {
+    NativeFunction::new(
+        "len",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let value = &arguments[0];
+            let Some(length) = value.length() else {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "len() expects a string or range but got {}.",
+                        value.type_name()
+                    ),
+                )
+                .with_kind(ErrorKind::TypeError));
+            };
+            Ok(Some(Box::new(NumberLiteral {
+                value: length as f32,
+            })))
+        }),
+    )
+}
+
+/// `filter(keep, string)` returns the characters of the string that `keep(character)`
+/// returns a truthy value for
+fn filter() -> NativeFunction {
+    NativeFunction::new(
+        "filter",
+        2,
+        Rc::new(|env, arguments, paren| {
+            let keep = arguments[0].as_ref();
+            expect_callback(keep, 1, "filter", paren)?;
+            let text = expect_string(arguments[1].as_ref(), "filter", paren)?;
+            let mut kept = String::new();
+            for character in text.graphemes(true) {
+                let value: Box<dyn LiteralValue> = Box::new(StringLiteral {
+                    value: character.to_string(),
+                });
+                if is_truthy(Some(call_back(keep, env, vec![value], paren)?.as_ref())) {
+                    kept.push_str(character);
+                }
+            }
+            Ok(Some(Box::new(StringLiteral { value: kept })))
+        }),
+    )
+}
+
+/// `sort(string, cmp)` returns the characters of the string in the order given by
+/// `cmp(a, b)`, which returns a negative number if `a` goes before `b`, a positive one
+/// if it goes after, and 0 if either order will do. Equal characters keep their order.
+fn sort() -> NativeFunction {
+    NativeFunction::new(
+        "sort",
+        2,
+        Rc::new(|env, arguments, paren| {
+            let text = expect_string(arguments[0].as_ref(), "sort", paren)?;
+            let cmp = arguments[1].as_ref();
+            expect_callback(cmp, 2, "sort", paren)?;
+            let mut goes_after = |a: &str, b: &str| -> Result<bool> {
+                let pair: Vec<Box<dyn LiteralValue>> = vec![
+                    Box::new(StringLiteral {
+                        value: a.to_string(),
+                    }),
+                    Box::new(StringLiteral {
+                        value: b.to_string(),
+                    }),
+                ];
+                let order = call_back(cmp, env, pair, paren)?;
+                match order.as_number() {
+                    Some(order) => Ok(order > 0.0),
+                    None => Err(RuntimeError::new(
+                        paren.clone(),
+                        format!(
+                            "sort() expects its function to return a number but got {}.",
+                            order.type_name()
+                        ),
+                    )
+                    .with_kind(ErrorKind::TypeError)),
+                }
+            };
+            let characters = text.graphemes(true).collect::<Vec<&str>>();
+            let sorted = merge_sort(characters, &mut goes_after)?;
+            Ok(Some(Box::new(StringLiteral {
+                value: sorted.concat(),
+            })))
+        }),
+    )
+}
+
+/// A stable merge sort whose comparison can fail. Unlike `slice::sort_by` it doesn't
+/// need the order to be consistent, which a function written in Lox can't promise.
+fn merge_sort<'a>(
+    items: Vec<&'a str>,
+    goes_after: &mut dyn FnMut(&str, &str) -> Result<bool>,
+) -> Result<Vec<&'a str>> {
+    if items.len() < 2 {
+        return Ok(items);
+    }
+    let mut left = items;
+    let right = left.split_off(left.len() / 2);
+    let left = merge_sort(left, goes_after)?;
+    let right = merge_sort(right, goes_after)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if goes_after(l, r)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// `parse_int(string, radix)` returns the whole number written in the string in base
+/// `radix`, from 2 to 36, or nil if it isn't one. Letters stand for the digits after 9
+/// in either case, and a leading sign and surrounding whitespace are allowed.
+fn parse_int() -> NativeFunction {
+    NativeFunction::new(
+        "parse_int",
+        2,
+        Rc::new(|_env, arguments, paren| {
+            let text = expect_string(arguments[0].as_ref(), "parse_int", paren)?;
+            let radix =
+                expect_whole_number(arguments[1].as_ref(), 2..=36, "parse_int", "radix", paren)?;
+            Ok(i128::from_str_radix(text.trim(), radix)
+                .ok()
+                .map(|n| Box::new(NumberLiteral { value: n as f32 }) as Box<dyn LiteralValue>))
+        }),
+    )
+}
+
+/// `to_fixed(number, digits)` returns the number with exactly `digits` digits after
+/// the point, from 0 to 20, rounding half to even. NaN and the infinities give `nan`,
+/// `inf` and `-inf`.
+fn to_fixed() -> NativeFunction {
+    NativeFunction::new(
+        "to_fixed",
+        2,
+        Rc::new(|_env, arguments, paren| {
+            let number = expect_number(arguments[0].as_ref(), "to_fixed", paren)?;
+            let digits =
+                expect_whole_number(arguments[1].as_ref(), 0..=20, "to_fixed", "digits", paren)?;
+            let value = match number.is_finite() {
+                true => format!("{number:.0$}", digits as usize),
+                false => format_number(number),
+            };
+            Ok(Some(Box::new(StringLiteral { value })))
+        }),
+    )
+}
+
+/// `to_hex(number)` returns a whole number in lowercase hexadecimal, with a `-` in
+/// front if it is negative
+fn to_hex() -> NativeFunction {
+    NativeFunction::new(
+        "to_hex",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let number = expect_number(arguments[0].as_ref(), "to_hex", paren)?;
+            if !number.is_finite() || number.fract() != 0.0 {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "to_hex() expects a whole number but got {}.",
+                        stringify(Some(arguments[0].clone()))
+                    ),
+                )
+                .with_kind(ErrorKind::TypeError));
+            }
+            // Every finite f32 fits, the largest being just under 2^128
+            let sign = if number < 0.0 { "-" } else { "" };
+            Ok(Some(Box::new(StringLiteral {
+                value: format!("{sign}{:x}", number.abs() as u128),
+            })))
+        }),
+    )
+}
+
+/// `range(end)`, `range(start, end)` and `range(start, end, step)` return the numbers
+/// from `start` (0 by default) up to but not including `end`, `step` (1 by default)
+/// apart, to iterate over with `for` or index
+fn range() -> NativeFunction {
+    NativeFunction::new(
+        "range",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let mut numbers = Vec::new();
+            for argument in &arguments {
+                let message = match argument.as_number() {
+                    Some(number) if number.is_finite() => {
+                        numbers.push(number);
+                        continue;
+                    }
+                    Some(number) => format!("range() can't count with {}.", format_number(number)),
+                    None => format!("range() expects numbers but got {}.", argument.type_name()),
+                };
+                return Err(
+                    RuntimeError::new(paren.clone(), message).with_kind(ErrorKind::TypeError)
+                );
+            }
+            let (start, end, step) = match numbers[..] {
+                [end] => (0.0, end, 1.0),
+                [start, end] => (start, end, 1.0),
+                [start, end, step] => (start, end, step),
+                _ => {
+                    return Err(RuntimeError::new(
+                        paren.clone(),
+                        format!("Expected at most 3 arguments but got {}.", numbers.len()),
+                    )
+                    .with_kind(ErrorKind::ArityMismatch))
+                }
+            };
+            if step == 0.0 {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    String::from("range() can't count in steps of 0."),
+                ));
+            }
+            Ok(Some(Box::new(RangeLiteral { start, end, step })))
+        }),
+    )
+    .variadic()
+}
+
+/// `reduce(fn, initial, collection)` combines the items of a string or range into one
+/// value: starting from `initial`, every item is passed to `fn(combined, item)`, and
+/// what it returns is combined with the next
+fn reduce() -> NativeFunction {
+    NativeFunction::new(
+        "reduce",
+        3,
+        Rc::new(|env, arguments, paren| {
+            let mut arguments = arguments.into_iter();
+            let function = arguments.next().expect("arity is checked by the caller");
+            let mut combined = arguments.next().expect("arity is checked by the caller");
+            let collection = arguments.next().expect("arity is checked by the caller");
+            expect_callback(function.as_ref(), 2, "reduce", paren)?;
+            let Some(items) = collection.iterate() else {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "reduce() expects a string or range but got {}.",
+                        collection.type_name()
+                    ),
+                )
+                .with_kind(ErrorKind::TypeError));
+            };
+            for item in items {
+                combined = call_back(function.as_ref(), env, vec![combined, item], paren)?;
+            }
+            Ok(Some(combined))
+        }),
+    )
+}
+
+/// `parse_number(string)` returns the number written in the string, or nil if
+/// it isn't one, so input can be checked without catching an error
+fn parse_number() -> NativeFunction {
+    NativeFunction::new(
+        "parse_number",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let text = expect_string(arguments[0].as_ref(), "parse_number", paren)?;
+            Ok(number_from_text(&text)
+                .map(|value| Box::new(NumberLiteral { value }) as Box<dyn LiteralValue>))
+        }),
+    )
+}
+
+/// `to_number(value)` converts numbers, strings that hold a number and booleans
+/// (to 1 or 0) to a number, and returns nil for anything else
+fn to_number() -> NativeFunction {
+    NativeFunction::new(
+        "to_number",
+        1,
+        Rc::new(|_env, arguments, _paren| {
+            let value = &arguments[0];
+            let number = match value.get_type() {
+                LiteralType::NumberLiteral => value.as_number(),
+                LiteralType::StringLiteral => number_from_text(&value.print_value()),
+                LiteralType::BooleanLiteral => {
+                    Some(f32::from(u8::from(is_truthy(Some(value.as_ref())))))
+                }
+                _ => None,
+            };
+            Ok(number.map(|value| Box::new(NumberLiteral { value }) as Box<dyn LiteralValue>))
+        }),
+    )
+}
+
+/// `to_string(value)` returns the value as `print` shows it
+fn to_string() -> NativeFunction {
+    NativeFunction::new(
+        "to_string",
+        1,
+        Rc::new(|_env, arguments, _paren| {
+            let value = arguments.into_iter().next();
+            Ok(Some(Box::new(StringLiteral {
+                value: stringify(value),
+            })))
+        }),
+    )
+}
+
+/// `trim(string)` returns the string without whitespace at either end
+fn trim() -> NativeFunction {
+    NativeFunction::new(
+        "trim",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let text = expect_string(arguments[0].as_ref(), "trim", paren)?;
+            Ok(Some(Box::new(StringLiteral {
+                value: text.trim().to_string(),
+            })))
+        }),
+    )
+}
+
+/// `starts_with(string, prefix)` returns whether the string begins with `prefix`
+fn starts_with() -> NativeFunction {
+    NativeFunction::new(
+        "starts_with",
+        2,
+        Rc::new(|_env, arguments, paren| {
+            let text = expect_string(arguments[0].as_ref(), "starts_with", paren)?;
+            let prefix = expect_string(arguments[1].as_ref(), "starts_with", paren)?;
+            Ok(Some(Box::new(BooleanLiteral {
+                value: text.starts_with(&prefix),
+            })))
+        }),
+    )
+}
+
+/// `ends_with(string, suffix)` returns whether the string ends with `suffix`
+fn ends_with() -> NativeFunction {
+    NativeFunction::new(
+        "ends_with",
+        2,
+        Rc::new(|_env, arguments, paren| {
+            let text = expect_string(arguments[0].as_ref(), "ends_with", paren)?;
+            let suffix = expect_string(arguments[1].as_ref(), "ends_with", paren)?;
+            Ok(Some(Box::new(BooleanLiteral {
+                value: text.ends_with(&suffix),
+            })))
+        }),
+    )
+}
+
+/// `replace(string, from, to)` returns the string with every occurrence of `from`
+/// replaced by `to`, scanning left to right without overlaps
+fn replace() -> NativeFunction {
+    NativeFunction::new(
+        "replace",
+        3,
+        Rc::new(|_env, arguments, paren| {
+            let text = expect_string(arguments[0].as_ref(), "replace", paren)?;
+            let from = expect_string(arguments[1].as_ref(), "replace", paren)?;
+            let to = expect_string(arguments[2].as_ref(), "replace", paren)?;
+            if from.is_empty() {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    String::from("replace() can't replace an empty string."),
+                ));
+            }
+            Ok(Some(Box::new(StringLiteral {
+                value: text.replace(&from, &to),
+            })))
+        }),
+    )
+}
+
+/// `type(value)` returns the name of the value's type, like "number" or "function"
+fn type_of() -> NativeFunction {
+    NativeFunction::new(
+        "type",
+        1,
+        Rc::new(|_env, arguments, _paren| {
+            let value = &arguments[0];
+            Ok(Some(Box::new(StringLiteral {
+                value: value.type_name(),
+            })))
+        }),
+    )
+}
+
+/// `globals()` returns the names of all global variables, including natives,
+/// one per line in alphabetical order
+fn globals() -> NativeFunction {
+    NativeFunction::new(
+        "globals",
+        0,
+        Rc::new(|env, _arguments, _paren| {
+            let mut global = env.clone();
+            while let Some(enclosing) = global.enclosing() {
+                global = enclosing;
+            }
+            Ok(Some(Box::new(StringLiteral {
+                value: global.names().join("\n"),
+            })))
+        }),
+    )
+}
+
+/// `doc(fn)` returns the `///` comment above the declaration of a function, or nil
+fn doc() -> NativeFunction {
+    NativeFunction::new(
+        "doc",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let Some(function) = arguments[0].as_callable() else {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "doc() expects a function but got {}.",
+                        arguments[0].type_name()
+                    ),
+                )
+                .with_kind(ErrorKind::TypeError));
+            };
+            Ok(function.doc().map(|doc| {
+                Box::new(StringLiteral {
+                    value: doc.to_string(),
+                }) as Box<dyn LiteralValue>
+            }))
+        }),
+    )
+}
+
+/// `exit(code)` stops the program with the given process exit status,
+/// running any `finally` blocks on the way out
+fn exit() -> NativeFunction {
+    NativeFunction::new(
+        "exit",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let code = &arguments[0];
+            let status = code.as_number().unwrap_or(-1.0);
+            if code.get_type() != LiteralType::NumberLiteral
+                || status.fract() != 0.0
+                || !(0.0..=255.0).contains(&status)
+            {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "exit() expects an integer between 0 and 255 but got {}.",
+                        stringify(Some(code.clone()))
+                    ),
+                ));
+            }
+            Err(RuntimeError::new(paren.clone(), String::from("exit"))
+                .with_kind(ErrorKind::Exit(status as u8)))
+        }),
+    )
+}
+
+/// `write(value)` prints a value like `print` does, but without a newline
+fn write() -> NativeFunction {
+    NativeFunction::new(
+        "write",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let mut stdout = io::stdout();
+            let text = stringify(arguments.into_iter().next());
+            match stdout
+                .write_all(text.as_bytes())
+                .and_then(|_| stdout.flush())
+            {
+                Ok(_) => {
+                    limits::progress();
+                    Ok(None)
+                }
+                Err(e) => Err(RuntimeError::new(
+                    paren.clone(),
+                    format!("Could not write to stdout: {e}"),
+                )),
+            }
+        }),
+    )
+}
+
+/// `format(fmt, ...)` returns `fmt` with each specifier replaced by the next argument,
+/// like C's `printf`. Supports `%s` for any value, `%d` for a number without its
+/// fraction, `%f` for a number with 6 or `%.Nf` with N decimals, and `%%`. A width
+/// like `%5d` pads on the left and `%-5s` on the right.
+fn format() -> NativeFunction {
+    NativeFunction::new(
+        "format",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let fmt = expect_string(arguments[0].as_ref(), "format", paren)?;
+            match sprintf(&fmt, &arguments[1..]) {
+                Ok(value) => Ok(Some(Box::new(StringLiteral { value }))),
+                Err(message) => Err(RuntimeError::new(paren.clone(), message)),
+            }
+        }),
+    )
+    .variadic()
+}
+
+/// Formats `arguments` according to `fmt` for `format()`, or returns the error message
+fn sprintf(fmt: &str, arguments: &[Box<dyn LiteralValue>]) -> std::result::Result<String, String> {
+    let mut output = String::new();
+    let mut arguments = arguments.iter();
+    let mut used = 0;
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        if chars.next_if_eq(&'%').is_some() {
+            output.push('%');
+            continue;
+        }
+
+        let left_align = chars.next_if_eq(&'-').is_some();
+        let width = format_count(&mut chars, "width")?;
+        let mut precision = None;
+        if chars.next_if_eq(&'.').is_some() {
+            precision = Some(format_count(&mut chars, "precision")?);
+        }
+
+        let specifier = chars.next();
+        let number = |value: &dyn LiteralValue| match value.as_number() {
+            Some(number) => Ok(number),
+            None => Err(format!(
+                "format() expects a number for '%{}' but got {}.",
+                specifier.unwrap_or_default(),
+                value.type_name()
+            )),
+        };
+        let text = match specifier {
+            Some(spec @ ('s' | 'd' | 'f')) => {
+                let Some(argument) = arguments.next() else {
+                    return Err(format!(
+                        "format() has more specifiers than the {used} arguments it got."
+                    ));
+                };
+                used += 1;
+                match spec {
+                    's' => stringify(Some(argument.clone())),
+                    // Adding zero turns `-0` into `0`
+                    'd' => format!("{}", number(argument.as_ref())?.trunc() + 0.0),
+                    _ => format!("{:.*}", precision.unwrap_or(6), number(argument.as_ref())?),
+                }
+            }
+            Some(other) => {
+                return Err(format!(
+                    "format() doesn't support the '%{other}' specifier."
+                ))
+            }
+            None => return Err(String::from("format() expects a specifier after '%'.")),
+        };
+        match left_align {
+            true => output.push_str(&format!("{text:<width$}")),
+            false => output.push_str(&format!("{text:>width$}")),
+        }
+    }
+    if arguments.next().is_some() {
+        return Err(format!(
+            "format() got more arguments than the {used} specifiers it has."
+        ));
+    }
+    Ok(output)
+}
+
+/// The largest width or precision `format()` takes, so a typo can't allocate gigabytes
+const MAX_FORMAT_COUNT: usize = 1000;
+
+/// Reads the digits of a width or precision in a `format()` specifier
+fn format_count(chars: &mut Peekable<Chars>, what: &str) -> std::result::Result<usize, String> {
+    let mut count: usize = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        count = count
+            .checked_mul(10)
+            .and_then(|count| count.checked_add(digit as usize))
+            .filter(|count| *count <= MAX_FORMAT_COUNT)
+            .ok_or_else(|| format!("format() doesn't support a {what} over {MAX_FORMAT_COUNT}."))?;
+        chars.next();
+    }
+    Ok(count)
+}
+
+/// `clock()` returns the seconds elapsed since the interpreter started. Like
+/// `now_millis()` it counts from startup, so short timings don't round to 0.
+fn clock(start: Instant) -> NativeFunction {
+    NativeFunction::new(
+        "clock",
+        0,
+        Rc::new(move |_env, _arguments, _paren| {
+            Ok(Some(Box::new(NumberLiteral {
+                value: start.elapsed().as_secs_f32(),
+            })))
+        }),
+    )
+}
+
+/// `collect_garbage()` frees scopes that are only kept alive by reference cycles
+/// and returns how many it freed
+fn collect_garbage() -> NativeFunction {
+    NativeFunction::new(
+        "collect_garbage",
+        0,
+        Rc::new(|_env, _arguments, _paren| {
+            Ok(Some(Box::new(NumberLiteral {
+                value: environment::collect_garbage() as f32,
+            })))
+        }),
+    )
+}
+
+/// `now_millis()` returns the milliseconds elapsed since the interpreter started.
+/// Numbers are single precision, so this counts from startup rather than the epoch
+/// to stay exact enough for timing code.
+fn now_millis(start: Instant) -> NativeFunction {
+    NativeFunction::new(
+        "now_millis",
+        0,
+        Rc::new(move |_env, _arguments, _paren| {
+            Ok(Some(Box::new(NumberLiteral {
+                value: start.elapsed().as_millis() as f32,
+            })))
+        }),
+    )
+}
+
+/// `now()` returns the current Unix time in seconds, for `format_time()`. Numbers are
+/// single precision, which only holds today's timestamps to a multiple of 128 seconds,
+/// so this is good for dates and rough times of day but not for timing code.
+fn now() -> NativeFunction {
+    NativeFunction::new(
+        "now",
+        0,
+        Rc::new(|_env, _arguments, _paren| {
+            // Times before the epoch are negative
+            let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(since) => since.as_secs_f64(),
+                Err(e) => -e.duration().as_secs_f64(),
+            };
+            Ok(Some(Box::new(NumberLiteral {
+                value: seconds as f32,
+            })))
+        }),
+    )
+}
+
+/// `sleep(ms)` pauses the program for the given number of milliseconds
+fn sleep() -> NativeFunction {
+    NativeFunction::new(
+        "sleep",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let ms = &arguments[0];
+            // NaN isn't non-negative either
+            let Some(millis) = ms.as_number().filter(|millis| *millis >= 0.0) else {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "sleep() expects a non-negative number but got {}.",
+                        stringify(Some(ms.clone()))
+                    ),
+                ));
+            };
+            let Ok(duration) = Duration::try_from_secs_f32(millis / 1000.0) else {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "sleep() can't wait for {} milliseconds.",
+                        stringify(Some(ms.clone()))
+                    ),
+                ));
+            };
+            if let Some(left) = limits::time_left().filter(|left| duration > *left) {
+                thread::sleep(left);
+                return Err(limits::exceeded(paren));
+            }
+            thread::sleep(duration);
+            Ok(None)
+        }),
+    )
+}
+
+/// `format_time(epoch, fmt)` formats a Unix timestamp in seconds, like `now()` returns, as UTC.
+/// Supports `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%F` (`%Y-%m-%d`), `%T` (`%H:%M:%S`) and `%%`.
+fn format_time() -> NativeFunction {
+    NativeFunction::new(
+        "format_time",
+        2,
+        Rc::new(|_env, arguments, paren| {
+            let epoch = &arguments[0];
+            let seconds = epoch.as_number().unwrap_or(f32::NAN);
+            if epoch.get_type() != LiteralType::NumberLiteral || !seconds.is_finite() {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "format_time() expects a number of seconds but got {}.",
+                        stringify(Some(epoch.clone()))
+                    ),
+                ));
+            }
+            let fmt = expect_string(arguments[1].as_ref(), "format_time", paren)?;
+            match strftime(seconds.floor() as i64, &fmt) {
+                Ok(value) => Ok(Some(Box::new(StringLiteral { value }))),
+                Err(specifier) => Err(RuntimeError::new(
+                    paren.clone(),
+                    format!("format_time() doesn't support the '%{specifier}' specifier."),
+                )),
+            }
+        }),
+    )
+}
+
+/// Formats `epoch` seconds as UTC according to `fmt`,
+/// or returns the first unsupported specifier
+fn strftime(epoch: i64, fmt: &str) -> std::result::Result<String, String> {
+    let (year, month, day) = civil_from_days(epoch.div_euclid(86_400));
+    let seconds_of_day = epoch.rem_euclid(86_400);
+    let (hour, minute, second) = (
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+    );
+
+    let mut output = String::new();
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => output.push_str(&format!("{year:04}")),
+            Some('m') => output.push_str(&format!("{month:02}")),
+            Some('d') => output.push_str(&format!("{day:02}")),
+            Some('H') => output.push_str(&format!("{hour:02}")),
+            Some('M') => output.push_str(&format!("{minute:02}")),
+            Some('S') => output.push_str(&format!("{second:02}")),
+            Some('F') => output.push_str(&format!("{year:04}-{month:02}-{day:02}")),
+            Some('T') => output.push_str(&format!("{hour:02}:{minute:02}:{second:02}")),
+            Some('%') => output.push('%'),
+            Some(other) => return Err(other.to_string()),
+            None => return Err(String::new()),
+        }
+    }
+    Ok(output)
+}
+
+/// Converts days since 1970-01-01 into a (year, month, day) date in the proleptic
+/// Gregorian calendar, using Howard Hinnant's `civil_from_days` algorithm
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// `readline()` returns the next line of stdin as a string, or nil at the end of input
+fn readline() -> NativeFunction {
+    NativeFunction::new(
+        "readline",
+        0,
+        Rc::new(|_env, _arguments, paren| match read_stdin_line(paren)? {
+            Some(value) => Ok(Some(Box::new(StringLiteral { value }))),
+            None => Ok(None),
+        }),
+    )
+}
+
+/// `read_number()` reads the next line of stdin as a number, or returns nil at the end of input
+fn read_number() -> NativeFunction {
+    NativeFunction::new(
+        "read_number",
+        0,
+        Rc::new(|_env, _arguments, paren| {
+            let line = match read_stdin_line(paren)? {
+                Some(line) => line,
+                None => return Ok(None),
+            };
+            match number_from_text(&line) {
+                Some(value) => Ok(Some(Box::new(NumberLiteral { value }))),
+                None => Err(RuntimeError::new(
+                    paren.clone(),
+                    format!("read_number() could not parse '{line}' as a number."),
+                )),
+            }
+        }),
+    )
+}
+
+/// `read_file(path)` returns the contents of the file at `path` as a string
+fn read_file() -> NativeFunction {
+    NativeFunction::new(
+        "read_file",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let path = expect_string(arguments[0].as_ref(), "read_file", paren)?;
+            match fs::read_to_string(&path) {
+                Ok(value) => Ok(Some(Box::new(StringLiteral { value }))),
+                Err(e) => Err(RuntimeError::new(
+                    paren.clone(),
+                    format!("Could not read '{path}': {e}"),
+                )),
+            }
+        }),
+    )
+}
+
+/// `write_file(path, contents)` replaces the file at `path` with `contents`
+fn write_file() -> NativeFunction {
+    NativeFunction::new(
+        "write_file",
+        2,
+        Rc::new(|_env, arguments, paren| {
+            let path = expect_string(arguments[0].as_ref(), "write_file", paren)?;
+            let contents = expect_string(arguments[1].as_ref(), "write_file", paren)?;
+            match fs::write(&path, contents) {
+                Ok(_) => Ok(None),
+                Err(e) => Err(RuntimeError::new(
+                    paren.clone(),
+                    format!("Could not write '{path}': {e}"),
+                )),
+            }
+        }),
+    )
+}
+
+/// `append_file(path, contents)` adds `contents` to the end of the file at `path`,
+/// creating it if it doesn't exist
+fn append_file() -> NativeFunction {
+    NativeFunction::new(
+        "append_file",
+        2,
+        Rc::new(|_env, arguments, paren| {
+            let path = expect_string(arguments[0].as_ref(), "append_file", paren)?;
+            let contents = expect_string(arguments[1].as_ref(), "append_file", paren)?;
+            let result = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(contents.as_bytes()));
+            match result {
+                Ok(_) => Ok(None),
+                Err(e) => Err(RuntimeError::new(
+                    paren.clone(),
+                    format!("Could not append to '{path}': {e}"),
+                )),
+            }
+        }),
+    )
+}
+
+/// `argc()` returns the number of command-line arguments passed to the script
+fn argc(args: Rc<Vec<String>>) -> NativeFunction {
+    NativeFunction::new(
+        "argc",
+        0,
+        Rc::new(move |_env, _arguments, _paren| {
+            Ok(Some(Box::new(NumberLiteral {
+                value: args.len() as f32,
+            })))
+        }),
+    )
+}
+
+/// `arg(i)` returns the command-line argument at index `i`, or nil if there is none
+fn arg(args: Rc<Vec<String>>) -> NativeFunction {
+    NativeFunction::new(
+        "arg",
+        1,
+        Rc::new(move |_env, arguments, paren| {
+            let index = &arguments[0];
+            let position = index.as_number().unwrap_or(-1.0);
+            if index.get_type() != LiteralType::NumberLiteral
+                || position.fract() != 0.0
+                || position < 0.0
+            {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!(
+                        "arg() expects a non-negative integer but got {}.",
+                        stringify(Some(index.clone()))
+                    ),
+                ));
+            }
+            match args.get(position as usize) {
+                Some(value) => Ok(Some(Box::new(StringLiteral {
+                    value: value.clone(),
+                }))),
+                None => Ok(None),
+            }
+        }),
+    )
+}
+
+/// `getenv(name)` returns the value of an environment variable, or nil if it isn't set
+fn getenv() -> NativeFunction {
+    NativeFunction::new(
+        "getenv",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let name = expect_string(arguments[0].as_ref(), "getenv", paren)?;
+            match env::var(name) {
+                Ok(value) => Ok(Some(Box::new(StringLiteral { value }))),
+                Err(_) => Ok(None),
+            }
+        }),
+    )
+}
+
+/// `exec(program, args...)` runs `program` with each of the other arguments as one
+/// of its arguments, and returns what it printed to stdout. There are no maps to
+/// return its exit status and stderr in, so those are only seen if it fails: a
+/// non-zero exit status is raised as a runtime error quoting what it printed to stderr.
+fn exec() -> NativeFunction {
+    NativeFunction::new(
+        "exec",
+        1,
+        Rc::new(|_env, arguments, paren| {
+            let program = expect_string(arguments[0].as_ref(), "exec", paren)?;
+            let args = arguments[1..]
+                .iter()
+                .map(|argument| expect_string(argument.as_ref(), "exec", paren))
+                .collect::<Result<Vec<String>>>()?;
+            let output = match Command::new(&program).args(args).output() {
+                Ok(output) => output,
+                Err(e) => {
+                    return Err(RuntimeError::new(
+                        paren.clone(),
+                        format!("Could not run '{program}': {e}"),
+                    ))
+                }
+            };
+            if !output.status.success() {
+                let status = match output.status.code() {
+                    Some(code) => format!("exited with status {code}"),
+                    None => String::from("was killed by a signal"),
+                };
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let message = match stderr.trim_end() {
+                    "" => format!("'{program}' {status}."),
+                    stderr => format!("'{program}' {status}: {stderr}"),
+                };
+                return Err(RuntimeError::new(paren.clone(), message));
+            }
+            Ok(Some(Box::new(StringLiteral {
+                value: String::from_utf8_lossy(&output.stdout).into_owned(),
+            })))
+        }),
+    )
+    .variadic()
+}
+
+/// `setenv(name, value)` sets an environment variable for this process and its children
+fn setenv() -> NativeFunction {
+    NativeFunction::new(
+        "setenv",
+        2,
+        Rc::new(|_env, arguments, paren| {
+            let name = expect_string(arguments[0].as_ref(), "setenv", paren)?;
+            let value = expect_string(arguments[1].as_ref(), "setenv", paren)?;
+            if name.is_empty() || name.contains(['=', '\0']) || value.contains('\0') {
+                return Err(RuntimeError::new(
+                    paren.clone(),
+                    format!("setenv() can't set the variable '{name}'."),
+                ));
+            }
+            env::set_var(name, value);
+            Ok(None)
+        }),
+    )
+}
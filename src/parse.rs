@@ -1,106 +1,472 @@
+use crate::ast::Node;
 use crate::expression::{
-    AssignExpr, BinaryExpr, Expression, ExpressionType, GroupingExpr, LiteralExpr, UnaryExpr,
-    VariableExpr,
+    AssignExpr, BinaryExpr, CallExpr, Expression, FunctionExpr, GroupingExpr, IndexExpr,
+    LiteralExpr, StringifyExpr, UnaryExpr, VariableExpr,
+};
+use crate::profile::{ProfiledStmt, Profiler};
+use crate::statement::{
+    BlockStmt, ExpressionStmt, ForEachStmt, PrintStmt, ReturnStmt, Statement, ThrowStmt, TryStmt,
+    VarStmt,
 };
-use crate::statement::{BlockStmt, ExpressionStmt, PrintStmt, Statement, VarStmt};
 use crate::token::{BooleanLiteral, NilLiteral, Token};
-use crate::TokenType;
-use std::fmt;
+use crate::{
+    diagnostic::{self, Diagnostic, Severity},
+    TokenType,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    ops::Range,
+    rc::Rc,
+    slice,
+};
 
 type Result<T> = std::result::Result<T, ParserError>;
 
+/// A top-level statement and the range of tokens it was parsed from
+pub type Spanned = (Box<dyn Statement>, Range<usize>);
+
+/// The name and handler of a `catch` clause
+type Catch = Option<(Token, Box<dyn Statement>)>;
+
+/// A variable reference and the scope it refers to, counted in scopes up from the
+/// reference, or `None` for a global. Recorded for `parse --dump-resolved`.
+pub struct Resolution {
+    pub name: Token,
+    pub depth: Option<usize>,
+}
+
+/// A local variable declared in an enclosing scope
+struct Local {
+    line: usize,
+    /// False while its initializer is parsed, which still sees the outer variable of that name
+    initialized: bool,
+}
+
+/// How deeply statements and expressions may nest before the parser gives up,
+/// which keeps the recursive descent (and evaluation) from overflowing the stack
+const MAX_NESTING_DEPTH: usize = 200;
+
 pub enum ParserError {
     UndisclosedDelimiter(Token),
     ExpectExpression(Token),
     UnexpectedToken(Token),
     NoSemicolon(Token),
     InvalidAssignmentTarget(Token),
+    MissingCatchOrFinally(Token),
+    TooManyArguments(Token),
+    TooDeeplyNested(Token),
+    TooManyParameters(Token),
+    ReturnOutsideFunction(Token),
+    /// A local variable declared again in the same scope, with the line of its first declaration
+    AlreadyDeclared(Token, usize),
 }
 
 impl fmt::Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let t = self.token();
         match self {
-            Self::UndisclosedDelimiter(t) => match t.token_type {
-                TokenType::Eof => write!(f, "at end: Undisclosed delimiter"),
-                _ => write!(f, "at {}: Undisclosed delimiter", t.to_string()),
-            },
-            Self::ExpectExpression(t) => match t.token_type {
-                TokenType::Eof => write!(f, "at end: Expected expression"),
-                _ => write!(f, "at {}: Expected expression", t.to_string()),
-            },
-            Self::UnexpectedToken(t) => match t.token_type {
-                TokenType::Eof => write!(f, "at end: Unexpected token"),
-                _ => write!(f, "at {}: Unexpected token", t.to_string()),
-            },
-            Self::NoSemicolon(t) => match t.token_type {
-                TokenType::Eof => write!(f, "at end: Missing semicolon"),
-                _ => write!(f, "Missing semicolon after {}", t.to_string()),
-            },
-            ParserError::InvalidAssignmentTarget(t) => match t.token_type {
-                TokenType::Eof => write!(f, "at end: Invalid assignment target"),
-                _ => write!(f, "at {}: Invalid assignment target", t.to_string()),
+            Self::NoSemicolon(_) if t.token_type != TokenType::Eof => {
+                write!(f, "Missing semicolon after {}", t)
+            }
+            Self::ReturnOutsideFunction(_) => write!(f, "at {}: {}", t, self.description()),
+            _ => match t.token_type {
+                TokenType::Eof => write!(f, "at end: {}", self.description()),
+                _ => write!(f, "at {}: {}", t, self.description()),
             },
         }
     }
 }
 
+impl ParserError {
+    /// The token the error was found at
+    pub fn token(&self) -> &Token {
+        match self {
+            Self::UndisclosedDelimiter(t)
+            | Self::ExpectExpression(t)
+            | Self::UnexpectedToken(t)
+            | Self::NoSemicolon(t)
+            | Self::InvalidAssignmentTarget(t)
+            | Self::MissingCatchOrFinally(t)
+            | Self::TooManyArguments(t)
+            | Self::TooDeeplyNested(t)
+            | Self::TooManyParameters(t)
+            | Self::ReturnOutsideFunction(t)
+            | Self::AlreadyDeclared(t, _) => t,
+        }
+    }
+
+    /// The stable name diagnostics report for this kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UndisclosedDelimiter(_) => "UndisclosedDelimiter",
+            Self::ExpectExpression(_) => "ExpectExpression",
+            Self::UnexpectedToken(_) => "UnexpectedToken",
+            Self::NoSemicolon(_) => "NoSemicolon",
+            Self::InvalidAssignmentTarget(_) => "InvalidAssignmentTarget",
+            Self::MissingCatchOrFinally(_) => "MissingCatchOrFinally",
+            Self::TooManyArguments(_) => "TooManyArguments",
+            Self::TooDeeplyNested(_) => "TooDeeplyNested",
+            Self::TooManyParameters(_) => "TooManyParameters",
+            Self::ReturnOutsideFunction(_) => "ReturnOutsideFunction",
+            Self::AlreadyDeclared(..) => "AlreadyDeclared",
+        }
+    }
+
+    /// What went wrong, without the location
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::UndisclosedDelimiter(_) => "Undisclosed delimiter",
+            Self::ExpectExpression(_) => "Expected expression",
+            Self::UnexpectedToken(_) => "Unexpected token",
+            Self::NoSemicolon(_) => "Missing semicolon",
+            Self::InvalidAssignmentTarget(_) => "Invalid assignment target",
+            Self::MissingCatchOrFinally(_) => "Expected 'catch' or 'finally' after try block",
+            Self::TooManyArguments(_) => "Can't have more than 255 arguments",
+            Self::TooDeeplyNested(_) => "Too deeply nested",
+            Self::TooManyParameters(_) => "Can't have more than 255 parameters",
+            Self::ReturnOutsideFunction(_) => "Can't return from top-level code",
+            Self::AlreadyDeclared(..) => "Already a variable with this name in this scope",
+        }
+    }
+
+    /// Extra lines of context printed below the message
+    pub fn notes(&self) -> Vec<String> {
+        match self {
+            Self::AlreadyDeclared(t, line) => {
+                vec![format!("'{}' was first declared on line {line}.", t.lexeme)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Prints the error to stderr, quoting the line it was found on
+    pub fn report(&self) {
+        let token = self.token();
+        let notes = self.notes();
+        diagnostic::emit(Diagnostic {
+            severity: Severity::Error,
+            code: self.code(),
+            header: format!("Error: {self}"),
+            message: self.description().to_string(),
+            source: token.source,
+            line: token.line,
+            column: token.column,
+            width: diagnostic::token_width(token),
+            notes: &notes,
+        });
+    }
+}
+
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Box<dyn Iterator<Item = Token>>,
+    /// The current token and any tokens after it that were looked ahead at
+    lookahead: VecDeque<Token>,
+    /// The last token consumed, once there is one
+    previous: Option<Token>,
+    /// How many tokens have been consumed
     current: usize,
+    depth: usize,
+    /// How many function bodies enclose the code being parsed
+    function_depth: usize,
+    /// How many `try` bodies and `catch` clauses of the innermost function enclose
+    /// the code being parsed. A `return` inside them isn't in tail position.
+    try_depth: usize,
+    /// The local variables declared in each enclosing scope, innermost last.
+    /// Globals may be redeclared, like in jlox, so they aren't tracked.
+    scopes: Vec<HashMap<String, Local>>,
+    /// Every variable reference parsed so far, when asked for with `with_resolutions`
+    resolutions: Option<Vec<Resolution>>,
+    /// Set for `run --profile`, which wraps every declaration to be timed
+    profiler: Option<Rc<Profiler>>,
+    /// The doc comment of the `var` declaration whose initializer is being parsed,
+    /// taken by the function expression that the initializer starts with
+    pending_doc: Option<Rc<str>>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+    /// Parses the given tokens, which are pulled as they're needed. They're expected
+    /// to end with `Eof`, which is assumed if they don't.
+    pub fn new(tokens: impl IntoIterator<Item = Token> + 'static) -> Self {
+        Self {
+            tokens: Box::new(tokens.into_iter()),
+            lookahead: VecDeque::new(),
+            previous: None,
+            current: 0,
+            depth: 0,
+            function_depth: 0,
+            try_depth: 0,
+            scopes: Vec::new(),
+            resolutions: None,
+            profiler: None,
+            pending_doc: None,
+        }
+    }
+
+    /// Records the scope every variable reference refers to, for `resolutions`
+    pub fn with_resolutions(mut self) -> Self {
+        self.resolutions = Some(Vec::new());
+        self
+    }
+
+    /// The variable references parsed so far, in source order, if `with_resolutions` was used
+    pub fn resolutions(&self) -> &[Resolution] {
+        self.resolutions.as_deref().unwrap_or_default()
+    }
+
+    pub fn with_profiler(mut self, profiler: Rc<Profiler>) -> Self {
+        self.profiler = Some(profiler);
+        self
     }
 
-    /// Parses and prints a single expression
+    /// Parses and prints a single expression, which has to make up the whole input
     /// Left in for legacy tests
     pub fn parse_single_expr(&mut self) -> Result<Box<dyn Expression>> {
-        match self.expression() {
+        let result = self.expression().and_then(|expr| match self.is_at_end() {
+            true => Ok(expr),
+            false => Err(ParserError::UnexpectedToken(self.peek().clone())),
+        });
+        match result {
             Ok(expr) => return Ok(expr),
             Err(e) => {
-                eprintln!("Error: {}", e);
+                e.report();
                 return Err(e);
             }
         }
     }
 
     pub fn parse(&mut self) -> Result<Vec<Box<dyn Statement>>> {
+        let statements = self.parse_spans()?;
+        Ok(statements.into_iter().map(|(stmt, _)| stmt).collect())
+    }
+
+    /// Like `parse`, but also returns the range of tokens each statement was parsed from
+    pub fn parse_spans(&mut self) -> Result<Vec<Spanned>> {
         let mut statements = Vec::new();
+        let mut spans = Vec::new();
         while !self.is_at_end() {
+            self.warn_if_unreachable(&statements);
+            let start = self.current;
             match self.declaration() {
                 Ok(stmt) => {
                     statements.push(stmt);
+                    spans.push(start..self.current);
                 }
                 Err(e) => {
-                    eprintln!("Error: {e}");
+                    e.report();
                     return Err(e);
                 }
             }
         }
-        Ok(statements)
+        Ok(statements.into_iter().zip(spans).collect())
     }
 
     fn statement(&mut self) -> Result<Box<dyn Statement>> {
+        let start = self.peek().offset;
+        let stmt = self.nested(Self::statement_inner)?;
+        Ok(self.spanned(start, stmt))
+    }
+
+    fn statement_inner(&mut self) -> Result<Box<dyn Statement>> {
+        if self.match_tokens(vec![TokenType::For]) {
+            return self.for_statement();
+        }
         if self.match_tokens(vec![TokenType::Print]) {
             return self.print_statement();
         }
+        if self.match_tokens(vec![TokenType::Return]) {
+            return self.return_statement();
+        }
+        if self.match_tokens(vec![TokenType::Throw]) {
+            return self.throw_statement();
+        }
+        if self.match_tokens(vec![TokenType::Try]) {
+            return self.try_statement();
+        }
         if self.match_tokens(vec![TokenType::LeftBrace]) {
             return self.block();
         }
         self.expression_statement()
     }
 
+    /// Parses a block after its opening brace
     fn block(&mut self) -> Result<Box<dyn Statement>> {
+        let start = self.previous().offset;
+        let stmts = self.scoped(&[], Self::block_statements)?;
+        Ok(self.spanned(start, Box::new(BlockStmt::new(stmts))))
+    }
+
+    /// Runs `f` in a new local scope that starts out with `params` declared
+    fn scoped<T>(&mut self, params: &[Token], f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.scopes.push(HashMap::new());
+        let result = params
+            .iter()
+            .try_for_each(|param| {
+                self.declare(param)?;
+                self.define(param);
+                Ok(())
+            })
+            .and_then(|_| f(self));
+        self.scopes.pop();
+        result
+    }
+
+    /// Records a variable declared in the innermost local scope, failing if that
+    /// scope already has one with the same name
+    fn declare(&mut self, name: &Token) -> Result<()> {
+        let Some(scope) = self.scopes.last_mut() else {
+            return Ok(());
+        };
+        if let Some(local) = scope.get(&name.lexeme) {
+            return Err(ParserError::AlreadyDeclared(name.clone(), local.line));
+        }
+        let local = Local {
+            line: name.line,
+            initialized: false,
+        };
+        scope.insert(name.lexeme.clone(), local);
+        Ok(())
+    }
+
+    /// Marks a declared variable as initialized, so references see it from now on
+    fn define(&mut self, name: &Token) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name.lexeme))
+        {
+            local.initialized = true;
+        }
+    }
+
+    /// Records which scope a variable reference refers to, like the interpreter
+    /// will find it when looking the name up
+    fn resolve(&mut self, name: &Token) {
+        let Some(resolutions) = &mut self.resolutions else {
+            return;
+        };
+        // Expressions only open scopes for functions, so an uninitialized variable
+        // is only seen from inside a function, which runs after it's initialized
+        let depth = self
+            .scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                let local = scope.get(&name.lexeme)?;
+                (local.initialized || depth > 0).then_some(depth)
+            });
+        resolutions.push(Resolution {
+            name: name.clone(),
+            depth,
+        });
+    }
+
+    /// Parses the statements of a block up to and including its closing brace
+    fn block_statements(&mut self) -> Result<Vec<Box<dyn Statement>>> {
         let mut stmts: Vec<Box<dyn Statement>> = Vec::new();
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            self.warn_if_unreachable(&stmts);
             stmts.push(self.declaration()?);
         }
 
         self.consume(TokenType::RightBrace)?;
-        Ok(Box::new(BlockStmt::new(stmts)))
+        Ok(stmts)
+    }
+
+    /// Warns about the upcoming statement if the one before it always leaves the block
+    fn warn_if_unreachable(&mut self, stmts: &[Box<dyn Statement>]) {
+        let unreachable = stmts.last().is_some_and(|s| s.always_exits());
+        if !unreachable {
+            return;
+        }
+        let token = self.peek().clone();
+        diagnostic::emit(Diagnostic {
+            severity: Severity::Warning,
+            code: "UnreachableCode",
+            header: format!("[line {}] Warning: Unreachable code.", token.line),
+            message: String::from("Unreachable code."),
+            source: token.source,
+            line: token.line,
+            column: token.column,
+            width: diagnostic::token_width(&token),
+            notes: &[],
+        });
+    }
+
+    fn return_statement(&mut self) -> Result<Box<dyn Statement>> {
+        let keyword = self.previous().clone();
+        if self.function_depth == 0 {
+            return Err(ParserError::ReturnOutsideFunction(keyword));
+        }
+        let mut value = None;
+        if !self.check(TokenType::Semicolon) {
+            let mut expr = self.expression()?;
+            if self.try_depth == 0 {
+                expr.mark_tail_call();
+            }
+            value = Some(expr);
+        }
+        self.consume(TokenType::Semicolon)?;
+        Ok(Box::new(ReturnStmt::new(keyword, value)))
+    }
+
+    /// Parses `for (item in collection) body`
+    fn for_statement(&mut self) -> Result<Box<dyn Statement>> {
+        self.consume(TokenType::LeftParen)?;
+        let item = self.consume(TokenType::Identifier)?;
+        self.consume(TokenType::In)?;
+        let collection = self.expression()?;
+        self.consume(TokenType::RightParen)?;
+        // Every iteration runs the body in a scope holding the loop variable
+        let body = self.scoped(slice::from_ref(&item), Self::statement)?;
+        Ok(Box::new(ForEachStmt::new(item, collection, body)))
+    }
+
+    fn throw_statement(&mut self) -> Result<Box<dyn Statement>> {
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon)?;
+        Ok(Box::new(ThrowStmt::new(keyword, value)))
+    }
+
+    /// Parses `try { } catch (name) { } finally { }`, where at least one of
+    /// the `catch` and `finally` clauses has to be present
+    fn try_statement(&mut self) -> Result<Box<dyn Statement>> {
+        self.try_depth += 1;
+        let body_and_catch = self.try_body_and_catch();
+        self.try_depth -= 1;
+        let (body, catch) = body_and_catch?;
+
+        let mut finally = None;
+        if self.match_tokens(vec![TokenType::Finally]) {
+            self.consume(TokenType::LeftBrace)?;
+            finally = Some(self.block()?);
+        }
+
+        if catch.is_none() && finally.is_none() {
+            return Err(ParserError::MissingCatchOrFinally(self.peek().clone()));
+        }
+        Ok(Box::new(TryStmt::new(body, catch, finally)))
+    }
+
+    /// Parses the body of a `try` and its optional `catch` clause
+    fn try_body_and_catch(&mut self) -> Result<(Box<dyn Statement>, Catch)> {
+        self.consume(TokenType::LeftBrace)?;
+        let body = self.block()?;
+
+        let mut catch = None;
+        if self.match_tokens(vec![TokenType::Catch]) {
+            self.consume(TokenType::LeftParen)?;
+            let name = self.consume(TokenType::Identifier)?;
+            self.consume(TokenType::RightParen)?;
+            self.consume(TokenType::LeftBrace)?;
+            // The handler runs in a scope holding the caught value
+            let handler = self.scoped(slice::from_ref(&name), Self::block)?;
+            catch = Some((name, handler));
+        }
+        Ok((body, catch))
     }
 
     fn print_statement(&mut self) -> Result<Box<dyn Statement>> {
@@ -116,23 +482,50 @@ impl Parser {
     }
 
     fn expression(&mut self) -> Result<Box<dyn Expression>> {
-        self.assignment()
+        self.nested(Self::comma)
+    }
+
+    /// Parses an expression without the comma operator, for where commas
+    /// separate things, like the arguments of a call
+    fn single_expression(&mut self) -> Result<Box<dyn Expression>> {
+        self.nested(Self::assignment)
+    }
+
+    /// Parses `a, b, c`, which evaluates every operand and yields the last
+    fn comma(&mut self) -> Result<Box<dyn Expression>> {
+        let start = self.peek().offset;
+        let mut expr = self.assignment()?;
+
+        while self.match_tokens(vec![TokenType::Comma]) {
+            let operator = self.previous().clone();
+            let right = self.assignment()?;
+            expr = self.spanned(start, Box::new(BinaryExpr::new(expr, operator, right)));
+        }
+        Ok(expr)
+    }
+
+    /// Runs `f` one nesting level deeper, failing once `MAX_NESTING_DEPTH` is exceeded
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(ParserError::TooDeeplyNested(self.peek().clone()));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
     }
 
     fn assignment(&mut self) -> Result<Box<dyn Expression>> {
+        let start = self.peek().offset;
         let expr = self.equality()?;
 
         if self.match_tokens(vec![TokenType::Equal]) {
-            let equals = self.previous();
-            let value = self.assignment()?;
-
-            if expr.get_type() == ExpressionType::Variable {
-                if let Some(name) = expr.get_token() {
-                    return Ok(Box::new(AssignExpr::new(name, value)));
-                } else {
-                    // Verified correct type above, so should never be reached
-                    panic!("Expected variable expression to contain a token");
-                }
+            let equals = self.previous().clone();
+            let value = self.nested(Self::assignment)?;
+
+            if let Some(variable) = expr.as_variable() {
+                let name = variable.name().clone();
+                return Ok(self.spanned(start, Box::new(AssignExpr::new(name, value))));
             }
             return Err(ParserError::InvalidAssignmentTarget(equals));
         }
@@ -140,17 +533,19 @@ impl Parser {
     }
 
     fn equality(&mut self) -> Result<Box<dyn Expression>> {
+        let start = self.peek().offset;
         let mut expr = self.comparison()?;
 
         while self.match_tokens(vec![TokenType::BangEqual, TokenType::EqualEqual]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.comparison()?;
-            expr = Box::new(BinaryExpr::new(expr, operator, right));
+            expr = self.spanned(start, Box::new(BinaryExpr::new(expr, operator, right)));
         }
         Ok(expr)
     }
 
     fn comparison(&mut self) -> Result<Box<dyn Expression>> {
+        let start = self.peek().offset;
         let mut expr = self.term()?;
 
         while self.match_tokens(vec![
@@ -159,45 +554,91 @@ impl Parser {
             TokenType::Less,
             TokenType::LessEqual,
         ]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.term()?;
-            expr = Box::new(BinaryExpr::new(expr, operator, right));
+            expr = self.spanned(start, Box::new(BinaryExpr::new(expr, operator, right)));
         }
         Ok(expr)
     }
 
     fn term(&mut self) -> Result<Box<dyn Expression>> {
+        let start = self.peek().offset;
         let mut expr = self.factor()?;
 
         while self.match_tokens(vec![TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.factor()?;
-            expr = Box::new(BinaryExpr::new(expr, operator, right));
+            expr = self.spanned(start, Box::new(BinaryExpr::new(expr, operator, right)));
         }
         Ok(expr)
     }
 
     fn factor(&mut self) -> Result<Box<dyn Expression>> {
+        let start = self.peek().offset;
         let mut expr = self.unary()?;
 
         while self.match_tokens(vec![TokenType::Slash, TokenType::Star]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.unary()?;
-            expr = Box::new(BinaryExpr::new(expr, operator, right));
+            expr = self.spanned(start, Box::new(BinaryExpr::new(expr, operator, right)));
         }
         Ok(expr)
     }
 
     fn unary(&mut self) -> Result<Box<dyn Expression>> {
         if self.match_tokens(vec![TokenType::Bang, TokenType::Minus]) {
-            let operator = self.previous();
-            let right = self.unary()?;
-            return Ok(Box::new(UnaryExpr::new(operator, right)));
+            let operator = self.previous().clone();
+            let right = self.nested(Self::unary)?;
+            let start = operator.offset;
+            return Ok(self.spanned(start, Box::new(UnaryExpr::new(operator, right))));
         }
-        self.primary()
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Box<dyn Expression>> {
+        let start = self.peek().offset;
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_tokens(vec![TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_tokens(vec![TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket)?;
+                expr = Box::new(IndexExpr::new(expr, bracket, index));
+            } else {
+                break;
+            }
+            expr.set_span(start..self.end_offset());
+        }
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Box<dyn Expression>) -> Result<Box<dyn Expression>> {
+        let mut arguments = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    return Err(ParserError::TooManyArguments(self.peek().clone()));
+                }
+                arguments.push(self.single_expression()?);
+                if !self.match_tokens(vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume(TokenType::RightParen)?;
+        Ok(Box::new(CallExpr::new(callee, paren, arguments)))
     }
 
     fn primary(&mut self) -> Result<Box<dyn Expression>> {
+        let start = self.peek().offset;
+        let expr = self.primary_inner()?;
+        Ok(self.spanned(start, expr))
+    }
+
+    fn primary_inner(&mut self) -> Result<Box<dyn Expression>> {
         if self.match_tokens(vec![TokenType::False]) {
             return Ok(Box::new(LiteralExpr::new(Box::new(BooleanLiteral {
                 value: false,
@@ -212,13 +653,24 @@ impl Parser {
             return Ok(Box::new(LiteralExpr::new(Box::new(NilLiteral))));
         }
         if self.match_tokens(vec![TokenType::Number, TokenType::String]) {
-            if let Some(l) = self.previous().literal {
+            if let Some(l) = self.previous().literal.clone() {
                 return Ok(Box::new(LiteralExpr::new(l)));
             }
-            // return Err(ParserError::UnexpectedToken(self.peek()));
+            // return Err(ParserError::UnexpectedToken(self.peek().clone()));
+        }
+        if self.match_tokens(vec![TokenType::Interpolation]) {
+            return self.interpolation();
+        }
+        if self.match_tokens(vec![TokenType::Fun]) {
+            return self.function_expression();
         }
         if self.match_tokens(vec![TokenType::Identifier]) {
-            return Ok(Box::new(VariableExpr::new(self.previous())));
+            let name = self.previous().clone();
+            self.resolve(&name);
+            return Ok(Box::new(VariableExpr::new(name)));
+        }
+        if self.is_arrow_lambda() {
+            return self.arrow_lambda();
         }
         if self.match_tokens(vec![TokenType::LeftParen]) {
             let expr = self.expression()?;
@@ -227,7 +679,134 @@ impl Parser {
                 Err(e) => Err(e),
             };
         }
-        Err(ParserError::UnexpectedToken(self.peek()))
+        Err(ParserError::UnexpectedToken(self.peek().clone()))
+    }
+
+    /// Parses the parameters and body of an anonymous function after its `fun` keyword
+    fn function_expression(&mut self) -> Result<Box<dyn Expression>> {
+        self.consume(TokenType::LeftParen)?;
+        let params = self.parameters()?;
+        self.consume(TokenType::LeftBrace)?;
+
+        let doc = self.pending_doc.take();
+        let try_depth = std::mem::take(&mut self.try_depth);
+        self.function_depth += 1;
+        let body = self.scoped(&params, Self::block_statements);
+        self.function_depth -= 1;
+        self.try_depth = try_depth;
+
+        Ok(Box::new(
+            FunctionExpr::new(params, Rc::new(body?)).with_doc(doc),
+        ))
+    }
+
+    /// Parses a comma-separated parameter list up to and including the closing parenthesis
+    fn parameters(&mut self) -> Result<Vec<Token>> {
+        let mut params = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    return Err(ParserError::TooManyParameters(self.peek().clone()));
+                }
+                params.push(self.consume(TokenType::Identifier)?);
+                if !self.match_tokens(vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen)?;
+        Ok(params)
+    }
+
+    /// Looks ahead for `(a, b) ->` to tell an arrow lambda apart from a grouping
+    fn is_arrow_lambda(&mut self) -> bool {
+        if !self.check(TokenType::LeftParen) {
+            return false;
+        }
+        let mut token_type = |i: usize| self.lookahead(i).token_type;
+
+        let mut i = 1;
+        if token_type(i) != TokenType::RightParen {
+            loop {
+                if token_type(i) != TokenType::Identifier {
+                    return false;
+                }
+                i += 1;
+                match token_type(i) {
+                    TokenType::Comma => i += 1,
+                    TokenType::RightParen => break,
+                    _ => return false,
+                }
+            }
+        }
+        token_type(i + 1) == TokenType::Arrow
+    }
+
+    /// Desugars `(a, b) -> expression` into a function that returns the expression
+    fn arrow_lambda(&mut self) -> Result<Box<dyn Expression>> {
+        let doc = self.pending_doc.take();
+        self.consume(TokenType::LeftParen)?;
+        let params = self.parameters()?;
+        let arrow = self.consume(TokenType::Arrow)?;
+        let mut value = self.scoped(&params, Self::single_expression)?;
+        value.mark_tail_call();
+
+        let span = value.span();
+        let mut stmt = Box::new(ReturnStmt::new(arrow, Some(value)));
+        stmt.set_span(span);
+        let body: Vec<Box<dyn Statement>> = vec![stmt];
+        Ok(Box::new(
+            FunctionExpr::new(params, Rc::new(body)).with_doc(doc),
+        ))
+    }
+
+    /// Desugars an interpolated string into the concatenation of its parts,
+    /// with every interpolated expression converted to a string
+    fn interpolation(&mut self) -> Result<Box<dyn Expression>> {
+        let start = self.previous().clone();
+        let mut expr = self.string_part(&start);
+        loop {
+            let value = self.expression()?;
+            let span = value.span();
+            let mut value = Box::new(StringifyExpr::new(value));
+            value.set_span(span);
+            expr = self.concatenate(expr, value, &start);
+
+            if self.match_tokens(vec![TokenType::Interpolation]) {
+                let part = self.string_part(self.previous());
+                expr = self.concatenate(expr, part, &start);
+                continue;
+            }
+            let end = self.consume(TokenType::String)?;
+            let part = self.string_part(&end);
+            return Ok(self.concatenate(expr, part, &start));
+        }
+    }
+
+    fn string_part(&self, token: &Token) -> Box<dyn Expression> {
+        let value = match &token.literal {
+            Some(l) => l.clone(),
+            None => Box::new(NilLiteral),
+        };
+        let mut part = Box::new(LiteralExpr::new(value));
+        part.set_span(token.offset..token.offset + token.lexeme.len());
+        part
+    }
+
+    fn concatenate(
+        &self,
+        left: Box<dyn Expression>,
+        right: Box<dyn Expression>,
+        start: &Token,
+    ) -> Box<dyn Expression> {
+        let plus = Token::new(
+            TokenType::Plus,
+            String::from("+"),
+            None,
+            start.line,
+            start.column,
+        );
+        self.spanned(start.offset, Box::new(BinaryExpr::new(left, plus, right)))
     }
 
     /// Looks for a closing delimiter and returns an Err if it doesn't find it
@@ -236,9 +815,9 @@ impl Parser {
             return Ok(self.advance());
         }
         if token_type == TokenType::Semicolon {
-            return Err(ParserError::NoSemicolon(self.peek()));
+            return Err(ParserError::NoSemicolon(self.peek().clone()));
         }
-        Err(ParserError::UndisclosedDelimiter(self.peek()))
+        Err(ParserError::UndisclosedDelimiter(self.peek().clone()))
     }
 
     fn match_tokens(&mut self, types: Vec<TokenType>) -> bool {
@@ -251,32 +830,67 @@ impl Parser {
         false
     }
 
-    fn check(&self, token_type: TokenType) -> bool {
+    fn check(&mut self, token_type: TokenType) -> bool {
         if self.is_at_end() {
             return false;
         }
-        let p = self.peek();
-        let out = p.token_type == token_type;
-        out
+        self.peek().token_type == token_type
     }
 
     fn advance(&mut self) -> Token {
         if !self.is_at_end() {
+            self.previous = self.lookahead.pop_front();
             self.current += 1;
         }
-        self.previous()
+        self.previous().clone()
     }
 
-    fn is_at_end(&self) -> bool {
+    fn is_at_end(&mut self) -> bool {
         self.peek().token_type == TokenType::Eof
     }
 
-    fn peek(&self) -> Token {
-        self.tokens[self.current].clone()
+    fn peek(&mut self) -> &Token {
+        self.lookahead(0)
     }
 
-    fn previous(&self) -> Token {
-        self.tokens[self.current - 1].clone()
+    /// The token `distance` tokens after the current one, pulling tokens as needed
+    fn lookahead(&mut self, distance: usize) -> &Token {
+        while self.lookahead.len() <= distance {
+            let token = match self.lookahead.back() {
+                Some(last) if last.token_type == TokenType::Eof => last.clone(),
+                _ => self.tokens.next().unwrap_or_else(|| self.end_of_input()),
+            };
+            self.lookahead.push_back(token);
+        }
+        &self.lookahead[distance]
+    }
+
+    /// The `Eof` token for tokens that run out without one, just after the last token
+    fn end_of_input(&self) -> Token {
+        let (line, column) = match self.lookahead.back().or(self.previous.as_ref()) {
+            Some(last) => (last.line, last.column + last.lexeme.chars().count()),
+            None => (1, 1),
+        };
+        Token::new(TokenType::Eof, String::new(), None, line, column)
+    }
+
+    fn previous(&self) -> &Token {
+        self.previous
+            .as_ref()
+            .expect("a token to have been consumed")
+    }
+
+    /// The byte offset just past the last consumed token
+    fn end_offset(&self) -> usize {
+        self.previous
+            .as_ref()
+            .map_or(0, |token| token.offset + token.lexeme.len())
+    }
+
+    /// Gives `node` the span from byte `start` to the end of the last consumed token
+    fn spanned<N: Node + ?Sized>(&self, start: usize, mut node: Box<N>) -> Box<N> {
+        node.set_span(start..self.end_offset());
+        node
     }
 
     fn synchronize(&mut self) {
@@ -304,30 +918,41 @@ impl Parser {
     }
 
     fn declaration(&mut self) -> Result<Box<dyn Statement>> {
-        if self.match_tokens(vec![TokenType::Var]) {
-            match self.var_declaration() {
-                Ok(stmt) => return Ok(stmt),
+        let line = self.peek().line;
+        let start = self.peek().offset;
+        let stmt = if self.match_tokens(vec![TokenType::Var]) {
+            let stmt = self.var_declaration()?;
+            self.spanned(start, stmt)
+        } else {
+            match self.statement() {
+                Ok(stmt) => stmt,
                 Err(e) => {
+                    self.synchronize();
                     return Err(e);
                 }
             }
-        }
-        match self.statement() {
-            Ok(stmt) => return Ok(stmt),
-            Err(e) => {
-                self.synchronize();
-                return Err(e);
-            }
+        };
+        match &self.profiler {
+            Some(profiler) => Ok(Box::new(ProfiledStmt::new(stmt, line, profiler.clone()))),
+            None => Ok(stmt),
         }
     }
 
     fn var_declaration(&mut self) -> Result<Box<dyn Statement>> {
+        let doc = self.previous().doc.clone();
         match self.consume(TokenType::Identifier) {
             Ok(t) => {
+                self.declare(&t)?;
                 let mut initializer: Option<Box<dyn Expression>> = None;
                 if self.match_tokens(vec![TokenType::Equal]) {
-                    initializer = Some(self.expression()?);
+                    if self.check(TokenType::Fun) || self.is_arrow_lambda() {
+                        self.pending_doc = doc;
+                    }
+                    let value = self.expression();
+                    self.pending_doc = None;
+                    initializer = Some(value?);
                 }
+                self.define(&t);
                 match self.consume(TokenType::Semicolon) {
                     Ok(_) => (),
                     Err(e) => return Err(e),
@@ -0,0 +1,135 @@
+use crate::{
+    ast::Node,
+    environment::Environment,
+    expression::{Expression, RuntimeError},
+    statement::{Statement, StatementType},
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ops::Range,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// How often the statements on a line ran, and how long they took
+#[derive(Default, Clone, Copy)]
+struct LineProfile {
+    executions: u64,
+    total: Duration,
+}
+
+/// Collects execution counts and times per source line for `run --profile`
+#[derive(Default)]
+pub struct Profiler {
+    lines: RefCell<HashMap<usize, LineProfile>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, line: usize, elapsed: Duration) {
+        let mut lines = self.lines.borrow_mut();
+        let profile = lines.entry(line).or_default();
+        profile.executions += 1;
+        profile.total += elapsed;
+    }
+
+    /// How many times statements on `line` ran
+    pub fn executions(&self, line: usize) -> u64 {
+        self.lines.borrow().get(&line).map_or(0, |p| p.executions)
+    }
+
+    /// Prints the `limit` lines that took the longest, quoting them from `source`.
+    /// Times include everything a statement ran, like the blocks and functions it called.
+    pub fn report(&self, source: &str, limit: usize) {
+        let source_lines = source.lines().collect::<Vec<&str>>();
+        let mut hot_spots = self
+            .lines
+            .borrow()
+            .iter()
+            .map(|(line, profile)| (*line, *profile))
+            .collect::<Vec<(usize, LineProfile)>>();
+        hot_spots.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(&b.0)));
+
+        eprintln!("--- profile");
+        eprintln!(
+            "{:>6}  {:>10}  {:>12}  source",
+            "line", "executions", "total ms"
+        );
+        for (line, profile) in hot_spots.into_iter().take(limit) {
+            let text = source_lines.get(line - 1).map_or("", |l| l.trim());
+            eprintln!(
+                "{:>6}  {:>10}  {:>12.3}  {}",
+                line,
+                profile.executions,
+                profile.total.as_secs_f64() * 1000.0,
+                text
+            );
+        }
+    }
+}
+
+/// Wraps a statement to record each of its executions with the profiler
+pub struct ProfiledStmt {
+    inner: Box<dyn Statement>,
+    line: usize,
+    profiler: Rc<Profiler>,
+}
+
+impl Node for ProfiledStmt {
+    fn span(&self) -> Range<usize> {
+        self.inner.span()
+    }
+
+    fn set_span(&mut self, span: Range<usize>) {
+        self.inner.set_span(span);
+    }
+}
+
+impl Statement for ProfiledStmt {
+    fn accept(&self) -> String {
+        self.inner.accept()
+    }
+
+    fn evaluate(&self, env: &mut Environment) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.evaluate(env);
+        self.profiler.record(self.line, start.elapsed());
+        result
+    }
+
+    fn get_type(&self) -> StatementType {
+        self.inner.get_type()
+    }
+
+    fn always_exits(&self) -> bool {
+        self.inner.always_exits()
+    }
+
+    fn expressions(&self) -> Vec<&dyn Expression> {
+        self.inner.expressions()
+    }
+
+    fn statements(&self) -> Vec<&dyn Statement> {
+        self.inner.statements()
+    }
+
+    fn dbg(&self) -> String {
+        self.inner.dbg()
+    }
+}
+
+impl ProfiledStmt {
+    pub fn new(inner: Box<dyn Statement>, line: usize, profiler: Rc<Profiler>) -> Self {
+        Self {
+            inner,
+            line,
+            profiler,
+        }
+    }
+}
@@ -1,15 +1,33 @@
 use crate::token::{LiteralValue, NumberLiteral, StringLiteral, Token};
-use crate::{report, TokenType, KEYWORDS};
+use crate::{cache, diagnostic::json_string, report, TokenType, KEYWORDS};
+use clap::ValueEnum;
 use regex::Regex;
-use std::fmt;
+use std::{fmt, rc::Rc, string::FromUtf8Error};
 use unicode_segmentation::UnicodeSegmentation;
 
 type Result<T> = std::result::Result<T, UnexpectedCharacterError>;
 
+/// Decodes the bytes of a source file, dropping the byte order mark some editors
+/// start UTF-8 files with. Invalid UTF-8 is an error, unless `lossy` replaces it
+/// with U+FFFD.
+pub fn decode_source(bytes: Vec<u8>, lossy: bool) -> std::result::Result<String, FromUtf8Error> {
+    let source = match String::from_utf8(bytes) {
+        Ok(source) => source,
+        Err(e) if lossy => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        Err(e) => return Err(e),
+    };
+    match source.strip_prefix('\u{feff}') {
+        Some(rest) => Ok(rest.to_string()),
+        None => Ok(source),
+    }
+}
+
 #[derive(Debug)]
 enum UnexpectedCharacterError {
     UnknownCharacter(String),
     UnterminatedStringLiteral,
+    InvalidNumberLiteral(String),
+    NumberLiteralTooLarge(String),
 }
 
 impl fmt::Display for UnexpectedCharacterError {
@@ -21,17 +39,90 @@ impl fmt::Display for UnexpectedCharacterError {
             UnexpectedCharacterError::UnterminatedStringLiteral => {
                 write!(f, "Unterminated string.")
             }
+            UnexpectedCharacterError::InvalidNumberLiteral(n) => {
+                write!(f, "Invalid number literal: {}", &n)
+            }
+            UnexpectedCharacterError::NumberLiteralTooLarge(n) => {
+                write!(f, "Number literal too large: {}", &n)
+            }
         }
     }
 }
 
+impl UnexpectedCharacterError {
+    /// The stable name diagnostics report for this kind of error
+    fn code(&self) -> &'static str {
+        match self {
+            UnexpectedCharacterError::UnknownCharacter(_) => "UnknownCharacter",
+            UnexpectedCharacterError::UnterminatedStringLiteral => "UnterminatedString",
+            UnexpectedCharacterError::InvalidNumberLiteral(_) => "InvalidNumberLiteral",
+            UnexpectedCharacterError::NumberLiteralTooLarge(_) => "NumberLiteralTooLarge",
+        }
+    }
+}
+
+/// A lexical error and the position of the character it starts at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanError {
+    pub line: usize,
+    pub column: usize,
+    /// Stable name of the kind of error, like `UnknownCharacter`
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ScanError {
+    /// Prints the error to stderr, quoting the line it occurred on
+    pub fn report(&self) {
+        report(self.line, self.column, self.code, &self.message);
+    }
+}
+
+/// A string interpolation that hasn't been closed yet
+struct Interpolation {
+    /// How many braces inside the interpolated expression are still open
+    depth: usize,
+    /// Where the part of the string before it starts
+    line: usize,
+    column: usize,
+}
+
+/// How `tokenize` prints tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TokenFormat {
+    /// `TYPE lexeme literal`, the format the Codecrafters tests expect
+    #[default]
+    Text,
+    /// Tab-separated line, column, type, lexeme and literal, with tabs and
+    /// newlines in lexemes escaped
+    Tsv,
+    /// A JSON array of tokens, each with the start and end of its span
+    Json,
+}
+
 pub struct Scanner {
     graphemes: Vec<String>,
     pub tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
-    pub has_error: bool,
+    /// Line of the character at `start`, which differs from `line` for multi-line strings
+    start_line: usize,
+    /// Every lexical error found, in the order of the source
+    pub errors: Vec<ScanError>,
+    /// Whether whitespace and comments are kept as tokens
+    trivia: bool,
+    /// Every string interpolation that is currently open, innermost last
+    interpolations: Vec<Interpolation>,
+    /// Index of the first character of every line, so line text and columns
+    /// can be found without searching the source
+    line_starts: Vec<usize>,
+    /// The number of the first line of the source
+    first_line: usize,
+    /// Byte offset of every character in the source, and of its end
+    offsets: Vec<usize>,
+    /// Whether the `Eof` token has been produced
+    finished: bool,
 }
 
 impl Scanner {
@@ -40,30 +131,149 @@ impl Scanner {
             .graphemes(true)
             .map(|g| g.to_string())
             .collect::<Vec<String>>();
+        let line_starts = std::iter::once(0)
+            .chain(
+                graphemes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, g)| *g == "\n")
+                    .map(|(newline, _)| newline + 1),
+            )
+            .collect();
+        let offsets = std::iter::once(0)
+            .chain(graphemes.iter().scan(0, |offset, g| {
+                *offset += g.len();
+                Some(*offset)
+            }))
+            .collect();
         Self {
             graphemes,
             tokens: vec![],
             start: 0,
             current: 0,
             line: 1,
-            has_error: false,
+            start_line: 1,
+            errors: Vec::new(),
+            trivia: false,
+            interpolations: Vec::new(),
+            line_starts,
+            first_line: 1,
+            offsets,
+            finished: false,
         }
     }
 
+    /// Numbers lines from `line` instead of 1, for scanning part of a larger source
+    /// that starts at the beginning of that line
+    pub fn with_start_line(mut self, line: usize) -> Self {
+        self.line = line;
+        self.start_line = line;
+        self.first_line = line;
+        self
+    }
+
+    /// Counts byte offsets from `offset` instead of 0, for scanning part of a larger
+    /// source that starts at that offset
+    pub fn with_start_offset(mut self, offset: usize) -> Self {
+        for start in &mut self.offsets {
+            *start += offset;
+        }
+        self
+    }
+
+    /// Keeps whitespace and comments as `Whitespace` and `Comment` tokens, so that
+    /// concatenating the lexemes of all tokens gives back the source exactly.
+    /// The parser doesn't understand these tokens, so this is for tooling only.
+    pub fn with_trivia(mut self, trivia: bool) -> Self {
+        self.trivia = trivia;
+        self
+    }
+
+    /// Concatenates the lexemes of all tokens, which is the source if it was
+    /// scanned with trivia and without errors
+    pub fn source_text(&self) -> String {
+        self.tokens.iter().map(|t| t.lexeme.as_str()).collect()
+    }
+
+    /// Scans the whole source into `tokens`. Scanning carries on past lexical errors,
+    /// which are collected in `errors` and reported together once it's done.
     pub fn scan_tokens(&mut self) {
-        while !self.is_at_end() {
-            self.start = self.current;
-            match self.scan_token() {
-                Ok(_) => (),
-                Err(e) => {
-                    self.has_error = true;
-                    report(self.line, "", &e.to_string());
-                }
+        let (mut tokens, mut errors) = (Vec::new(), Vec::new());
+        for item in self.by_ref() {
+            match item {
+                Ok(token) => tokens.push(token),
+                Err(e) => errors.push(e),
             }
         }
+        self.tokens = tokens;
+        self.errors.append(&mut errors);
+        self.errors.sort_by_key(|e| (e.line, e.column));
+
+        for error in &self.errors {
+            error.report();
+        }
+    }
 
-        let eof_token = Token::new(TokenType::Eof, String::new(), None, self.line);
-        self.tokens.push(eof_token);
+    pub fn has_error(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    fn error(line: usize, column: usize, error: UnexpectedCharacterError) -> ScanError {
+        ScanError {
+            line,
+            column,
+            code: error.code(),
+            message: error.to_string(),
+        }
+    }
+
+    /// The text of the given line of the source, without its newline
+    pub fn line_content(&self, line: usize) -> Option<String> {
+        let index = line.checked_sub(self.first_line)?;
+        let start = *self.line_starts.get(index)?;
+        let end = match self.line_starts.get(index + 1) {
+            Some(next) => next - 1,
+            None => self.graphemes.len(),
+        };
+        Some(self.graphemes[start..end].concat())
+    }
+
+    /// Index of the first character of the line the character at `index` is on
+    fn line_start(&self, index: usize) -> usize {
+        let line = self.line_starts.partition_point(|&start| start <= index);
+        self.line_starts[line - 1]
+    }
+
+    /// The column of the character at `index`, counted from the start of its line
+    fn column(&self, index: usize) -> usize {
+        index - self.line_start(index) + 1
+    }
+
+    /// The `///` comment lines directly above the token at `start`, without the slashes,
+    /// if the token is the first on its line
+    fn doc_comment(&self) -> Option<Rc<str>> {
+        let mut start = self.line_start(self.start);
+        if !self.graphemes[start..self.start].concat().trim().is_empty() {
+            return None;
+        }
+
+        let mut lines = Vec::new();
+        while start > 0 {
+            let previous = self.line_start(start - 1);
+            let line = self.graphemes[previous..start - 1].concat();
+            match line.trim().strip_prefix("///") {
+                Some(text) if !text.starts_with('/') => {
+                    lines.push(text.strip_prefix(' ').unwrap_or(text).to_string())
+                }
+                _ => break,
+            }
+            start = previous;
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(Rc::from(lines.join("\n")))
     }
 
     /// Returns true if the current character is the last one in self.source
@@ -78,16 +288,42 @@ impl Scanner {
             // Single-character tokens
             "(" => Ok(self.add_token(TokenType::LeftParen)),
             ")" => Ok(self.add_token(TokenType::RightParen)),
-            "{" => Ok(self.add_token(TokenType::LeftBrace)),
-            "}" => Ok(self.add_token(TokenType::RightBrace)),
+            "{" => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.depth += 1;
+                }
+                Ok(self.add_token(TokenType::LeftBrace))
+            }
+            "}" => match self.interpolations.last_mut() {
+                // Closes an interpolation, so the surrounding string continues
+                Some(Interpolation { depth: 0, .. }) => {
+                    self.interpolations.pop();
+                    self.string()
+                }
+                Some(interpolation) => {
+                    interpolation.depth -= 1;
+                    Ok(self.add_token(TokenType::RightBrace))
+                }
+                None => Ok(self.add_token(TokenType::RightBrace)),
+            },
+            "[" => Ok(self.add_token(TokenType::LeftBracket)),
+            "]" => Ok(self.add_token(TokenType::RightBracket)),
             "," => Ok(self.add_token(TokenType::Comma)),
             "." => Ok(self.add_token(TokenType::Dot)),
-            "-" => Ok(self.add_token(TokenType::Minus)),
+
             "+" => Ok(self.add_token(TokenType::Plus)),
             ";" => Ok(self.add_token(TokenType::Semicolon)),
             "*" => Ok(self.add_token(TokenType::Star)),
 
             // Operators can potentially have multiple characters
+            "-" => {
+                let t = if self.match_next(">") {
+                    TokenType::Arrow
+                } else {
+                    TokenType::Minus
+                };
+                return Ok(self.add_token(t));
+            }
             "!" => {
                 let t = if self.match_next("=") {
                     TokenType::BangEqual
@@ -125,7 +361,7 @@ impl Scanner {
                     while self.peek() != "\n" && !self.is_at_end() {
                         self.advance();
                     }
-                    Ok(())
+                    Ok(self.add_trivia(TokenType::Comment))
                 } else {
                     Ok(self.add_token(TokenType::Slash))
                 };
@@ -144,10 +380,13 @@ impl Scanner {
             },
 
             // Newlines
-            "\n" => Ok(self.line += 1),
+            "\n" => {
+                self.line += 1;
+                Ok(self.add_trivia(TokenType::Whitespace))
+            }
 
             // Ignore whitespace
-            " " | "\r" | "\t" => Ok(()),
+            " " | "\r" | "\t" => Ok(self.add_trivia(TokenType::Whitespace)),
 
             _ => {
                 // We assume that every alphabetic character starts an identifier
@@ -197,91 +436,160 @@ impl Scanner {
 
     /// Returns the character two positions ahead, if there is one
     fn peek_next(&self) -> &str {
-        if self.is_at_end() {
-            return "\0";
-        }
-        if self.current < self.graphemes.len() {
-            return &self.graphemes[self.current + 1];
+        self.peek_at(1)
+    }
+
+    /// Returns the character `offset` positions after the upcoming one, if there is one
+    fn peek_at(&self, offset: usize) -> &str {
+        match self.graphemes.get(self.current + offset) {
+            Some(g) => g,
+            None => "\0",
         }
-        return "\0";
     }
 
     fn add_token(&mut self, token_type: TokenType) {
         self.add_literal_token(token_type, None);
     }
 
+    /// Adds whitespace or a comment if trivia is kept, merging whitespace into
+    /// the whitespace right before it
+    fn add_trivia(&mut self, token_type: TokenType) {
+        if !self.trivia {
+            return;
+        }
+        match self.tokens.last_mut() {
+            Some(last)
+                if token_type == TokenType::Whitespace
+                    && last.token_type == TokenType::Whitespace =>
+            {
+                last.lexeme
+                    .push_str(&self.graphemes[self.start..self.current].concat());
+            }
+            _ => self.add_token(token_type),
+        }
+    }
+
     fn add_literal_token(&mut self, token_type: TokenType, literal: Option<Box<dyn LiteralValue>>) {
         // Parse lexeme from source
         let text = self.graphemes[self.start..self.current].concat();
-        self.tokens
-            .push(Token::new(token_type, text, literal, self.line));
+        let mut token = Token::new(
+            token_type,
+            text,
+            literal,
+            self.start_line,
+            self.column(self.start),
+        );
+        token.offset = self.offsets[self.start];
+        self.tokens.push(token);
     }
 
+    /// Scans a string literal, or the rest of one after an interpolated expression.
+    /// `${` starts an interpolated expression and `\$` produces a literal `$`.
     fn string(&mut self) -> Result<()> {
-        let mut lines: usize = 0;
+        let mut value = String::new();
 
-        // While we haven't reached the closing " or the end of the line, advance
+        // While we haven't reached the closing " or the end of the file, advance.
+        // The token is recorded at the line the literal starts on.
         while self.peek() != "\"" && !self.is_at_end() {
             if self.peek() == "\n" {
-                lines += 1;
-                self.line += lines;
+                self.line += 1;
+            }
+            if self.peek() == "\\" && self.peek_next() == "$" {
+                self.advance();
+            } else if self.peek() == "$" && self.peek_next() == "{" {
+                // The part before the interpolation becomes its own token,
+                // the expression is scanned as regular tokens
+                self.advance();
+                self.advance();
+                self.interpolations.push(Interpolation {
+                    depth: 0,
+                    line: self.start_line,
+                    column: self.column(self.start),
+                });
+                let literal = StringLiteral { value };
+                self.add_literal_token(TokenType::Interpolation, Some(Box::new(literal)));
+                return Ok(());
+            }
+            if let Some(c) = self.advance() {
+                value.push_str(c);
             }
-            self.advance();
         }
 
         // If we reach the end of the file before finding the closing ",
         // the literal is unterminated.
         if self.is_at_end() {
-            self.line -= lines;
             return Err(UnexpectedCharacterError::UnterminatedStringLiteral);
         }
 
         // Advance to the closing "
         self.advance();
 
-        // Parse the string literals value from source
-        let literal = StringLiteral {
-            value: self.graphemes[self.start + 1..self.current - 1].concat(),
-        };
-
+        let literal = StringLiteral { value };
         self.add_literal_token(TokenType::String, Some(Box::new(literal)));
         Ok(())
     }
 
     fn number(&mut self) -> Result<()> {
-        // Keep parsing while the next character is numeric
-        while is_digit(self.peek()) {
-            self.advance();
-        }
+        self.digits();
 
         // If the next character is a decimal point AND the character after that is numeric,
         // keep parsing
         if self.peek() == "." && is_digit(self.peek_next()) {
             self.advance();
-            while is_digit(self.peek()) {
-                self.advance();
+            self.digits();
+        }
+
+        // An exponent is an `e` or `E`, an optional sign and at least one digit
+        if self.peek() == "e" || self.peek() == "E" {
+            let has_sign = self.peek_next() == "+" || self.peek_next() == "-";
+            let exponent_start = if has_sign { 2 } else { 1 };
+            if is_digit(self.peek_at(exponent_start)) {
+                for _ in 0..exponent_start {
+                    self.advance();
+                }
+                self.digits();
             }
         }
 
+        // The lexeme keeps its digit separators, the value doesn't
         let value_str = self.graphemes[self.start..self.current].concat();
-        let literal = NumberLiteral {
-            value: value_str
-                .parse()
-                .expect("to be able to parse number literal value to number"),
+        let value: f32 = match value_str.replace('_', "").parse() {
+            Ok(value) => value,
+            Err(_) => return Err(UnexpectedCharacterError::InvalidNumberLiteral(value_str)),
         };
+        // Literals beyond the range of a number parse to infinity,
+        // which we reject instead of silently changing the value
+        if value.is_infinite() {
+            return Err(UnexpectedCharacterError::NumberLiteralTooLarge(value_str));
+        }
+        let literal = NumberLiteral { value };
 
         self.add_literal_token(TokenType::Number, Some(Box::new(literal)));
         Ok(())
     }
 
+    /// Consumes a run of digits, which may be separated by single underscores
+    fn digits(&mut self) {
+        while is_digit(self.peek()) || (self.peek() == "_" && is_digit(self.peek_next())) {
+            self.advance();
+        }
+    }
+
     fn identifier(&mut self) -> Result<()> {
         // Keep parsing while the next character is alphanumeric or an underscore _
         while is_alphabetic(self.peek()) || is_digit(self.peek()) || self.peek() == "_" {
             self.advance();
         }
         let value_str = self.graphemes[self.start..self.current].concat();
-        if let Some(identifier_type) = KEYWORDS.lock().unwrap().get(value_str.as_str()) {
-            self.add_token(identifier_type.clone());
+        if let Some(identifier_type) = KEYWORDS.get(value_str.as_str()) {
+            self.add_token(*identifier_type);
+            // Declarations keep the doc comment above them, for `doc(fn)`
+            if *identifier_type == TokenType::Var {
+                let doc = self.doc_comment();
+                if let Some(token) = self.tokens.last_mut() {
+                    token.doc = doc;
+                }
+            }
             return Ok(());
         } else {
             self.add_token(TokenType::Identifier);
@@ -290,10 +598,127 @@ impl Scanner {
     }
 }
 
+/// Scans tokens lazily, one at a time, ending with `Eof`. Errors are produced
+/// in place of the token they were found at, and aren't reported or kept in `errors`.
+impl Iterator for Scanner {
+    type Item = std::result::Result<Token, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Whitespace trivia is held back while more of it may be merged into it
+            if let Some(token) = self.tokens.first() {
+                let mergeable = token.token_type == TokenType::Whitespace && !self.is_at_end();
+                if self.tokens.len() > 1 || !mergeable {
+                    return Some(Ok(self.tokens.remove(0)));
+                }
+            }
+
+            if self.is_at_end() {
+                // Reaching the end inside an interpolation means its string was never closed
+                if !self.interpolations.is_empty() {
+                    let interpolation = self.interpolations.remove(0);
+                    let e = UnexpectedCharacterError::UnterminatedStringLiteral;
+                    return Some(Err(Self::error(
+                        interpolation.line,
+                        interpolation.column,
+                        e,
+                    )));
+                }
+                if self.finished {
+                    return None;
+                }
+                self.finished = true;
+                let mut eof = Token::new(
+                    TokenType::Eof,
+                    String::new(),
+                    None,
+                    self.line,
+                    self.column(self.current),
+                );
+                eof.offset = self.offsets[self.current];
+                return Some(Ok(eof));
+            }
+
+            self.start = self.current;
+            self.start_line = self.line;
+            if let Err(e) = self.scan_token() {
+                let column = self.column(self.start);
+                return Some(Err(Self::error(self.start_line, column, e)));
+            }
+        }
+    }
+}
+
+impl Scanner {
+    /// The scanned tokens, one per line, in the given format.
+    /// Only the text format ends with a newline, like `Display`.
+    pub fn render(&self, format: TokenFormat) -> String {
+        let literal = |t: &Token| t.literal.as_ref().map(|l| l.print_value());
+        match format {
+            TokenFormat::Text => self.to_string(),
+            TokenFormat::Tsv => self
+                .tokens
+                .iter()
+                .map(|t| {
+                    let literal = literal(t).unwrap_or_else(|| String::from("null"));
+                    format!(
+                        "{}\t{}\t{}\t{}\t{}",
+                        t.line,
+                        t.column,
+                        t.token_type,
+                        cache::escape(&t.lexeme),
+                        cache::escape(&literal)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            TokenFormat::Json => {
+                let tokens = self
+                    .tokens
+                    .iter()
+                    .map(|t| {
+                        let literal = match literal(t) {
+                            Some(n) if t.token_type == TokenType::Number => n,
+                            Some(s) => json_string(&s),
+                            None => String::from("null"),
+                        };
+                        let (end_line, end_column) = span_end(t);
+                        format!(
+                            "{{\"type\":{},\"lexeme\":{},\"literal\":{},\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}}}}",
+                            json_string(&t.token_type.to_string()),
+                            json_string(&t.lexeme),
+                            literal,
+                            t.line,
+                            t.column,
+                            end_line,
+                            end_column
+                        )
+                    })
+                    .collect::<Vec<String>>();
+                format!("[\n{}\n]", tokens.join(",\n"))
+            }
+        }
+    }
+}
+
+/// The line and column just past the end of the token's lexeme
+fn span_end(token: &Token) -> (usize, usize) {
+    match token.lexeme.rsplit_once('\n') {
+        Some((before, last)) => (
+            token.line + before.matches('\n').count() + 1,
+            last.graphemes(true).count() + 1,
+        ),
+        None => (
+            token.line,
+            token.column + token.lexeme.graphemes(true).count(),
+        ),
+    }
+}
+
 impl fmt::Display for Scanner {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for t in &self.tokens {
-            write!(f, "{}\n", t)?;
+            writeln!(f, "{}", t)?;
         }
         Ok(())
     }
@@ -1,7 +1,11 @@
 use crate::{
+    ast::{define_ast, Node},
     environment::Environment,
-    expression::{Expression, RuntimeError},
-    token::{LiteralType, Token},
+    expression::{ErrorKind, Expression, RuntimeError},
+    hooks,
+    interpret::stringify,
+    interrupt, limits,
+    token::{LiteralValue, NilLiteral, StringLiteral, Token},
 };
 
 type Result<T> = std::result::Result<T, RuntimeError>;
@@ -12,18 +16,57 @@ pub enum StatementType {
     Print,
     Var,
     Block,
+    ForEach,
+    Return,
+    Throw,
+    Try,
 }
 
-pub trait Statement {
+pub trait Statement: Node {
+    /// Renders the statement as an S-expression, like `Expression::accept`
+    fn accept(&self) -> String;
     fn evaluate(&self, env: &mut Environment) -> Result<()>;
     fn get_type(&self) -> StatementType;
     fn dbg(&self) -> String;
+
+    /// Whether running the statement never continues with the next one,
+    /// because it always returns or throws
+    fn always_exits(&self) -> bool {
+        false
+    }
+
+    /// The expressions the statement evaluates itself, in source order
+    fn expressions(&self) -> Vec<&dyn Expression> {
+        Vec::new()
+    }
+
+    /// The statements nested in this one, in source order
+    fn statements(&self) -> Vec<&dyn Statement> {
+        Vec::new()
+    }
+}
+
+/// Renders `name` followed by every statement, in parentheses
+pub fn parenthesize_stmts(name: &str, stmts: &[Box<dyn Statement>]) -> String {
+    let mut parsed = format!("({name}");
+    for stmt in stmts {
+        parsed.push(' ');
+        parsed.push_str(&stmt.accept());
+    }
+    parsed.push(')');
+    parsed
 }
 
-pub struct ExpressionStmt {
-    value: Box<dyn Expression>,
+define_ast! {
+    pub struct ExpressionStmt {
+        value: Box<dyn Expression>,
+    }
 }
 impl Statement for ExpressionStmt {
+    fn accept(&self) -> String {
+        format!("(; {})", self.value.accept())
+    }
+
     fn evaluate(&self, env: &mut Environment) -> Result<()> {
         match self.value.evaluate(env) {
             Ok(_) => return Ok(()),
@@ -35,42 +78,32 @@ impl Statement for ExpressionStmt {
         StatementType::Expression
     }
 
+    fn expressions(&self) -> Vec<&dyn Expression> {
+        vec![self.value.as_ref()]
+    }
+
     fn dbg(&self) -> String {
         format!("Expression statement with value {}", self.value.accept())
     }
 }
-impl ExpressionStmt {
-    pub fn new(value: Box<dyn Expression>) -> Self {
-        Self { value }
-    }
-}
 
-pub struct PrintStmt {
-    value: Box<dyn Expression>,
+define_ast! {
+    pub struct PrintStmt {
+        value: Box<dyn Expression>,
+    }
 }
 impl Statement for PrintStmt {
+    fn accept(&self) -> String {
+        format!("(print {})", self.value.accept())
+    }
+
     fn evaluate(&self, env: &mut Environment) -> Result<()> {
         match self.value.evaluate(env) {
             Ok(v) => {
-                if let Some(parsed) = v {
-                    let out = parsed.print_value();
-                    if parsed.get_type() == LiteralType::NumberLiteral {
-                        let n = out
-                            .parse::<f32>()
-                            .expect("to be able to parse number literal to f32");
-                        println!("{n}");
-                    } else {
-                        println!("{out}");
-                    }
-                } else {
-                    println!("nil");
-                    return Ok(());
-                }
-            }
-            Err(e) => {
-                eprintln!("Error while evaluating print statement: {e}");
-                return Err(e);
+                println!("{}", stringify(v));
+                limits::progress();
             }
+            Err(e) => return Err(e),
         }
         Ok(())
     }
@@ -79,31 +112,41 @@ impl Statement for PrintStmt {
         StatementType::Print
     }
 
+    fn expressions(&self) -> Vec<&dyn Expression> {
+        vec![self.value.as_ref()]
+    }
+
     fn dbg(&self) -> String {
         format!("Print statement with value {}", self.value.accept())
     }
 }
-impl PrintStmt {
-    pub fn new(value: Box<dyn Expression>) -> Self {
-        Self { value }
-    }
-}
 
-pub struct VarStmt {
-    name: Token,
-    initializer: Option<Box<dyn Expression>>,
+define_ast! {
+    pub struct VarStmt {
+        name: Token,
+        initializer: Option<Box<dyn Expression>>,
+    }
 }
 impl Statement for VarStmt {
+    fn accept(&self) -> String {
+        match &self.initializer {
+            Some(i) => format!("(var {} = {})", self.name.lexeme, i.accept()),
+            None => format!("(var {})", self.name.lexeme),
+        }
+    }
+
     fn evaluate(&self, env: &mut Environment) -> Result<()> {
         if let Some(initializer) = &self.initializer {
             match initializer.evaluate(env) {
                 Ok(value) => {
+                    hooks::variable_write(&self.name, value.as_deref())?;
                     env.define(self.name.lexeme.clone(), value);
                     return Ok(());
                 }
                 Err(e) => return Err(e),
             }
         } else {
+            hooks::variable_write(&self.name, None)?;
             env.define(self.name.lexeme.clone(), None);
             Ok(())
         }
@@ -113,41 +156,35 @@ impl Statement for VarStmt {
         StatementType::Var
     }
 
+    fn expressions(&self) -> Vec<&dyn Expression> {
+        self.initializer.iter().map(|i| i.as_ref()).collect()
+    }
+
     fn dbg(&self) -> String {
         let v = if let Some(i) = &self.initializer {
             i.accept()
         } else {
             String::from("null")
         };
-        format!("name: {}, initializer: {}", self.name.to_string(), v)
-    }
-}
-impl VarStmt {
-    pub fn new(name: Token, initializer: Option<Box<dyn Expression>>) -> Self {
-        Self { name, initializer }
+        format!("name: {}, initializer: {}", self.name, v)
     }
 }
 
-pub struct BlockStmt {
-    stmts: Vec<Box<dyn Statement>>,
+define_ast! {
+    pub struct BlockStmt {
+        stmts: Vec<Box<dyn Statement>>,
+    }
 }
 impl Statement for BlockStmt {
+    fn accept(&self) -> String {
+        parenthesize_stmts("block", &self.stmts)
+    }
+
     fn evaluate(&self, env: &mut Environment) -> Result<()> {
-        let previous = env.clone();
-        let mut enclosing = Environment::new(Some(Box::new(env.clone())));
+        let mut enclosing = Environment::new(Some(env.clone()));
         for s in &self.stmts {
-            match s.evaluate(&mut enclosing) {
-                Ok(_) => (),
-                Err(e) => {
-                    env.revert_to(&previous);
-                    return Err(e);
-                }
-            }
+            hooks::execute(s.as_ref(), &mut enclosing)?;
         }
-        let outer = enclosing
-            .enclosing()
-            .expect("expected enclosing environment");
-        env.revert_to(outer);
         Ok(())
     }
 
@@ -155,6 +192,14 @@ impl Statement for BlockStmt {
         StatementType::Block
     }
 
+    fn statements(&self) -> Vec<&dyn Statement> {
+        self.stmts.iter().map(|s| s.as_ref()).collect()
+    }
+
+    fn always_exits(&self) -> bool {
+        self.stmts.iter().any(|s| s.always_exits())
+    }
+
     fn dbg(&self) -> String {
         let mut o = String::new();
         for s in &self.stmts {
@@ -163,8 +208,235 @@ impl Statement for BlockStmt {
         o
     }
 }
-impl BlockStmt {
-    pub fn new(stmts: Vec<Box<dyn Statement>>) -> Self {
-        Self { stmts }
+
+define_ast! {
+    pub struct ForEachStmt {
+        item: Token,
+        collection: Box<dyn Expression>,
+        body: Box<dyn Statement>,
+    }
+}
+impl Statement for ForEachStmt {
+    fn accept(&self) -> String {
+        format!(
+            "(for {} in {} {})",
+            self.item.lexeme,
+            self.collection.accept(),
+            self.body.accept()
+        )
+    }
+
+    fn evaluate(&self, env: &mut Environment) -> Result<()> {
+        let collection = match self.collection.evaluate(env)? {
+            Some(c) => c,
+            None => {
+                return Err(RuntimeError::new(
+                    self.item.clone(),
+                    String::from("Can only iterate over strings and ranges."),
+                )
+                .with_kind(ErrorKind::TypeError))
+            }
+        };
+        let values = match collection.iterate() {
+            Some(values) => values,
+            None => {
+                return Err(RuntimeError::new(
+                    self.item.clone(),
+                    String::from("Can only iterate over strings and ranges."),
+                )
+                .with_kind(ErrorKind::TypeError))
+            }
+        };
+
+        // Every iteration gets a fresh scope holding the loop variable
+        for value in values {
+            limits::step(&self.item)?;
+            interrupt::check(&self.item)?;
+            let mut enclosing = Environment::new(Some(env.clone()));
+            enclosing.define(self.item.lexeme.clone(), Some(value));
+            hooks::execute(self.body.as_ref(), &mut enclosing)?;
+        }
+        Ok(())
+    }
+
+    fn get_type(&self) -> StatementType {
+        StatementType::ForEach
+    }
+
+    fn expressions(&self) -> Vec<&dyn Expression> {
+        vec![self.collection.as_ref()]
+    }
+
+    fn statements(&self) -> Vec<&dyn Statement> {
+        vec![self.body.as_ref()]
+    }
+
+    fn dbg(&self) -> String {
+        format!(
+            "Foreach statement binding {} over {}: {}",
+            self.item,
+            self.collection.accept(),
+            self.body.dbg()
+        )
+    }
+}
+
+define_ast! {
+    pub struct ReturnStmt {
+        keyword: Token,
+        value: Option<Box<dyn Expression>>,
+    }
+}
+impl Statement for ReturnStmt {
+    fn accept(&self) -> String {
+        match &self.value {
+            Some(v) => format!("(return {})", v.accept()),
+            None => String::from("(return)"),
+        }
+    }
+
+    fn evaluate(&self, env: &mut Environment) -> Result<()> {
+        let value = match &self.value {
+            Some(v) => v.evaluate(env)?,
+            None => None,
+        };
+        Err(RuntimeError {
+            value,
+            ..RuntimeError::new(self.keyword.clone(), String::from("return"))
+                .with_kind(ErrorKind::Return)
+        })
+    }
+
+    fn get_type(&self) -> StatementType {
+        StatementType::Return
+    }
+
+    fn expressions(&self) -> Vec<&dyn Expression> {
+        self.value.iter().map(|v| v.as_ref()).collect()
+    }
+
+    fn always_exits(&self) -> bool {
+        true
+    }
+
+    fn dbg(&self) -> String {
+        match &self.value {
+            Some(v) => format!("Return statement with value {}", v.accept()),
+            None => String::from("Return statement"),
+        }
+    }
+}
+
+define_ast! {
+    pub struct ThrowStmt {
+        keyword: Token,
+        value: Box<dyn Expression>,
+    }
+}
+impl Statement for ThrowStmt {
+    fn accept(&self) -> String {
+        format!("(throw {})", self.value.accept())
+    }
+
+    fn evaluate(&self, env: &mut Environment) -> Result<()> {
+        let value: Box<dyn LiteralValue> = match self.value.evaluate(env)? {
+            Some(v) => v,
+            None => Box::new(NilLiteral),
+        };
+        Err(RuntimeError::thrown(self.keyword.clone(), value))
+    }
+
+    fn get_type(&self) -> StatementType {
+        StatementType::Throw
+    }
+
+    fn expressions(&self) -> Vec<&dyn Expression> {
+        vec![self.value.as_ref()]
+    }
+
+    fn always_exits(&self) -> bool {
+        true
+    }
+
+    fn dbg(&self) -> String {
+        format!("Throw statement with value {}", self.value.accept())
+    }
+}
+
+define_ast! {
+    pub struct TryStmt {
+        body: Box<dyn Statement>,
+        catch: Option<(Token, Box<dyn Statement>)>,
+        finally: Option<Box<dyn Statement>>,
+    }
+}
+impl Statement for TryStmt {
+    fn accept(&self) -> String {
+        let mut o = format!("(try {}", self.body.accept());
+        if let Some((name, handler)) = &self.catch {
+            o.push_str(&format!(" catch {} {}", name.lexeme, handler.accept()));
+        }
+        if let Some(finally) = &self.finally {
+            o.push_str(&format!(" finally {}", finally.accept()));
+        }
+        o.push(')');
+        o
+    }
+
+    fn evaluate(&self, env: &mut Environment) -> Result<()> {
+        let mut result = self.body.evaluate(env);
+
+        if let (Err(e), Some((name, handler))) = (&result, &self.catch) {
+            // `return` and `exit()` unwind through `try` without being caught
+            if !e.kind.is_unwind() {
+                // Thrown values are bound as-is, other runtime errors as their message
+                let caught: Box<dyn LiteralValue> = match &e.value {
+                    Some(v) => v.clone(),
+                    None => Box::new(StringLiteral {
+                        value: e.message.clone(),
+                    }),
+                };
+                let mut enclosing = Environment::new(Some(env.clone()));
+                enclosing.define(name.lexeme.clone(), Some(caught));
+                result = handler.evaluate(&mut enclosing);
+            }
+        }
+
+        if let Some(finally) = &self.finally {
+            finally.evaluate(env)?;
+        }
+        result
+    }
+
+    fn get_type(&self) -> StatementType {
+        StatementType::Try
+    }
+
+    fn statements(&self) -> Vec<&dyn Statement> {
+        let mut statements = vec![self.body.as_ref()];
+        statements.extend(self.catch.iter().map(|(_, handler)| handler.as_ref()));
+        statements.extend(self.finally.iter().map(|f| f.as_ref()));
+        statements
+    }
+
+    /// A `catch` may handle whatever made the body exit, so it has to exit as well
+    fn always_exits(&self) -> bool {
+        let catch_exits = match &self.catch {
+            Some((_, handler)) => handler.always_exits(),
+            None => true,
+        };
+        let finally_exits = self.finally.as_ref().is_some_and(|f| f.always_exits());
+        (self.body.always_exits() && catch_exits) || finally_exits
+    }
+
+    fn dbg(&self) -> String {
+        let mut o = format!("Try statement: {}", self.body.dbg());
+        if let Some((name, handler)) = &self.catch {
+            o.push_str(&format!(" catch {}: {}", name, handler.dbg()));
+        }
+        if let Some(finally) = &self.finally {
+            o.push_str(&format!(" finally: {}", finally.dbg()));
+        }
+        o
     }
 }
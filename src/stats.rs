@@ -0,0 +1,54 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Counters reported by `run --stats`. They only ever grow, so reading them
+/// before and after a phase gives the amount of work that phase did.
+static AST_NODES: AtomicUsize = AtomicUsize::new(0);
+static ENVIRONMENTS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Called whenever an expression or statement node is created
+pub fn record_node() {
+    AST_NODES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Called whenever a new scope is created
+pub fn record_environment() {
+    ENVIRONMENTS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn ast_nodes() -> usize {
+    AST_NODES.load(Ordering::Relaxed)
+}
+
+pub fn environments() -> usize {
+    ENVIRONMENTS.load(Ordering::Relaxed)
+}
+
+/// The most heap memory in use at any one time, if the binary installed `CountingAllocator`
+pub fn peak_bytes() -> usize {
+    PEAK_BYTES.load(Ordering::Relaxed)
+}
+
+/// Wraps the system allocator to keep track of the peak heap usage.
+/// Binaries opt in with `#[global_allocator]`.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+            PEAK_BYTES.fetch_max(allocated + layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
@@ -1,9 +1,61 @@
-use crate::TokenType;
-use std::fmt;
+use crate::{
+    callable::Callable,
+    diagnostic::{self, SourceId},
+    environment::Environment,
+    TokenType,
+};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+use unicode_segmentation::UnicodeSegmentation;
 
 pub trait LiteralValue: LiteralValueClone {
     fn print_value(&self) -> String;
     fn get_type(&self) -> LiteralType;
+
+    /// Iteration protocol used by `for (item in collection)` loops.
+    /// Returns None for values that can't be iterated over.
+    fn iterate(&self) -> Option<Box<dyn Iterator<Item = Box<dyn LiteralValue>>>> {
+        None
+    }
+
+    /// How many elements `value[i]` can index, or None for values that can't be indexed
+    fn length(&self) -> Option<usize> {
+        None
+    }
+
+    /// The element at `index`, counted from 0, if there is one
+    fn index(&self, _index: usize) -> Option<Box<dyn LiteralValue>> {
+        None
+    }
+
+    /// Returns the value as something that can be called, if it is a function
+    fn as_callable(&self) -> Option<&dyn Callable> {
+        None
+    }
+
+    /// Returns the value itself if it is a number, so arithmetic doesn't
+    /// go through its printed form
+    fn as_number(&self) -> Option<f32> {
+        None
+    }
+
+    /// Returns the value itself if it is a boolean, so conditions don't go
+    /// through its printed form
+    fn as_bool(&self) -> Option<bool> {
+        None
+    }
+
+    /// Calls `visit` with every environment this value keeps alive, so the
+    /// garbage collector can follow references between scopes
+    fn trace(&self, _visit: &mut dyn FnMut(&Environment)) {}
+
+    /// The name `type()` reports for this value
+    fn type_name(&self) -> String {
+        self.get_type().name().to_string()
+    }
 }
 
 pub trait LiteralValueClone {
@@ -31,6 +83,42 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Option<Box<dyn LiteralValue>>,
     pub line: usize,
+    /// Column of the token's first character, counted in graphemes from 1
+    pub column: usize,
+    /// Byte offset of the token's first character in the source
+    pub offset: usize,
+    /// The `///` comment above a `var` keyword, which documents the declaration
+    pub doc: Option<Rc<str>>,
+    /// The file the token was scanned from
+    pub source: SourceId,
+}
+
+/// Tokens are equal when they are the same token of a source: the same type and
+/// lexeme at the same position in the same file. Two references to a variable of the same name are
+/// different tokens, so side tables keyed by token can tell them apart. The literal
+/// follows from the lexeme and isn't compared.
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.token_type == other.token_type
+            && self.offset == other.offset
+            && self.line == other.line
+            && self.column == other.column
+            && self.lexeme == other.lexeme
+            && self.source == other.source
+    }
+}
+
+impl Eq for Token {}
+
+impl Hash for Token {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.token_type.hash(state);
+        self.offset.hash(state);
+        self.line.hash(state);
+        self.column.hash(state);
+        self.lexeme.hash(state);
+        self.source.hash(state);
+    }
 }
 
 impl fmt::Display for Token {
@@ -50,12 +138,17 @@ impl Token {
         lexeme: String,
         literal: Option<Box<dyn LiteralValue>>,
         line: usize,
+        column: usize,
     ) -> Self {
         Self {
             token_type,
             lexeme,
             literal,
             line,
+            column,
+            offset: 0,
+            doc: None,
+            source: diagnostic::current_source(),
         }
     }
 }
@@ -66,6 +159,21 @@ pub enum LiteralType {
     StringLiteral,
     BooleanLiteral,
     NilLiteral,
+    FunctionLiteral,
+    RangeLiteral,
+}
+
+impl LiteralType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiteralType::NumberLiteral => "number",
+            LiteralType::StringLiteral => "string",
+            LiteralType::BooleanLiteral => "boolean",
+            LiteralType::NilLiteral => "nil",
+            LiteralType::FunctionLiteral => "function",
+            LiteralType::RangeLiteral => "range",
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -73,20 +181,55 @@ pub struct NumberLiteral {
     pub value: f32,
 }
 
+/// Formats a number after the rules of Java's `Double.toString`, which jlox uses: the
+/// shortest digits that read back as the same number, with at least one digit after
+/// the point, and in scientific notation like `1.0E21` outside of `0.001 <= |n| < 10^7`.
+/// `print` shows the same text without a trailing `.0`.
+///
+/// The output isn't jlox's, though. Numbers are single precision here, so most
+/// fractions have fewer digits than jlox's doubles. And since dividing by zero gives
+/// NaN and the infinities like clox does, they print the way clox prints them, as
+/// `nan`, `inf` and `-inf`, rather than Java's `NaN` and `Infinity`.
+pub fn format_number(value: f32) -> String {
+    if value.is_nan() {
+        return String::from("nan");
+    }
+    if value.is_infinite() {
+        return String::from(if value > 0.0 { "inf" } else { "-inf" });
+    }
+
+    let magnitude = value.abs();
+    if magnitude == 0.0 || (1e-3..1e7).contains(&magnitude) {
+        // Rust's shortest representation, which leaves out the point for whole numbers
+        let text = value.to_string();
+        return match text.contains('.') {
+            true => text,
+            false => format!("{text}.0"),
+        };
+    }
+    // `{:e}` gives the shortest digits as `1.5e-7` or `1e21`
+    let scientific = format!("{value:e}");
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("scientific notation to have an exponent");
+    match mantissa.contains('.') {
+        true => format!("{mantissa}E{exponent}"),
+        false => format!("{mantissa}.0E{exponent}"),
+    }
+}
+
 impl LiteralValue for NumberLiteral {
     fn print_value(&self) -> String {
-        // In Rust, `42.0f32.to_string()` yields `42` and not `42.0`,
-        // so we have to handle that case manually
-        if self.value.fract() == 0.0 {
-            return format!("{:.1}", self.value);
-        } else {
-            self.value.to_string()
-        }
+        format_number(self.value)
     }
 
     fn get_type(&self) -> LiteralType {
         LiteralType::NumberLiteral
     }
+
+    fn as_number(&self) -> Option<f32> {
+        Some(self.value)
+    }
 }
 
 #[derive(Clone)]
@@ -102,6 +245,94 @@ impl LiteralValue for StringLiteral {
     fn get_type(&self) -> LiteralType {
         LiteralType::StringLiteral
     }
+
+    /// Strings are indexed by character (grapheme), like they are iterated over
+    fn length(&self) -> Option<usize> {
+        Some(self.value.graphemes(true).count())
+    }
+
+    fn index(&self, index: usize) -> Option<Box<dyn LiteralValue>> {
+        let character = self.value.graphemes(true).nth(index)?;
+        Some(Box::new(StringLiteral {
+            value: character.to_string(),
+        }))
+    }
+
+    /// Strings iterate over their characters (graphemes), each as a one-character string
+    fn iterate(&self) -> Option<Box<dyn Iterator<Item = Box<dyn LiteralValue>>>> {
+        let characters = self
+            .value
+            .graphemes(true)
+            .map(|g| {
+                Box::new(StringLiteral {
+                    value: g.to_string(),
+                }) as Box<dyn LiteralValue>
+            })
+            .collect::<Vec<Box<dyn LiteralValue>>>();
+        Some(Box::new(characters.into_iter()))
+    }
+}
+
+/// The numbers from `start` up to but not including `end`, `step` apart, made by
+/// `range()`. They're computed as they are iterated over, so a large range takes no
+/// more memory than a small one.
+#[derive(Clone)]
+pub struct RangeLiteral {
+    pub start: f32,
+    pub end: f32,
+    pub step: f32,
+}
+
+impl RangeLiteral {
+    /// The `n`th number, multiplied out so long ranges don't add up rounding errors
+    fn nth(&self, n: usize) -> f32 {
+        self.start + n as f32 * self.step
+    }
+}
+
+impl LiteralValue for RangeLiteral {
+    fn print_value(&self) -> String {
+        let show = |n: f32| {
+            let text = format_number(n);
+            match text.strip_suffix(".0") {
+                Some(whole) => whole.to_string(),
+                None => text,
+            }
+        };
+        format!(
+            "range({}, {}, {})",
+            show(self.start),
+            show(self.end),
+            show(self.step)
+        )
+    }
+
+    fn get_type(&self) -> LiteralType {
+        LiteralType::RangeLiteral
+    }
+
+    fn length(&self) -> Option<usize> {
+        // Saturates for huge ranges, and is 0 when `step` points away from `end`
+        Some(((self.end - self.start) / self.step).ceil().max(0.0) as usize)
+    }
+
+    fn index(&self, index: usize) -> Option<Box<dyn LiteralValue>> {
+        (index < self.length()?).then(|| {
+            Box::new(NumberLiteral {
+                value: self.nth(index),
+            }) as Box<dyn LiteralValue>
+        })
+    }
+
+    fn iterate(&self) -> Option<Box<dyn Iterator<Item = Box<dyn LiteralValue>>>> {
+        let range = self.clone();
+        let numbers = (0..self.length()?).map(move |n| {
+            Box::new(NumberLiteral {
+                value: range.nth(n),
+            }) as Box<dyn LiteralValue>
+        });
+        Some(Box::new(numbers))
+    }
 }
 
 #[derive(Clone)]
@@ -120,6 +351,10 @@ impl LiteralValue for BooleanLiteral {
     fn get_type(&self) -> LiteralType {
         LiteralType::BooleanLiteral
     }
+
+    fn as_bool(&self) -> Option<bool> {
+        Some(self.value)
+    }
 }
 
 #[derive(Clone)]
@@ -0,0 +1,19 @@
+//! Deciding when `run --watch` runs its scripts again.
+
+use std::{fs, path::Path, time::SystemTime};
+
+/// When each file was last modified, or `None` for files that can't be read right now
+pub fn modification_times<P: AsRef<Path>>(paths: &[P]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Whether any file was modified between the two calls to `modification_times`.
+/// Editors may briefly remove a file while saving, so a missing file isn't a change.
+pub fn changed(last: &[Option<SystemTime>], now: &[Option<SystemTime>]) -> bool {
+    now.iter()
+        .zip(last)
+        .any(|(now, last)| now.is_some() && now != last)
+}
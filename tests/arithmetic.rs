@@ -0,0 +1,69 @@
+//! Checks that arithmetic works on the stored `f32`s bit for bit, so values whose
+//! printed form doesn't round-trip keep their exact value.
+
+use codecrafters_interpreter::{
+    interpret::Interpreter, parse::Parser, scan::Scanner, token::Token, TokenType,
+};
+
+fn interpreter(source: &str) -> Interpreter {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let statements = Parser::new(scanner.tokens)
+        .parse()
+        .ok()
+        .expect("valid source");
+    Interpreter::new(statements)
+}
+
+/// Evaluates `expression` and returns the number it produced
+fn evaluate(expression: &str) -> f32 {
+    let mut interpreter = interpreter(&format!("var result = {expression};"));
+    assert!(interpreter.interpret().is_ok());
+    let name = Token::new(TokenType::Identifier, String::from("result"), None, 1, 1);
+    interpreter
+        .globals()
+        .get(name)
+        .ok()
+        .expect("result to be defined")
+        .expect("result to have a value")
+        .as_number()
+        .expect("result to be a number")
+}
+
+#[test]
+fn results_match_f32_arithmetic_exactly() {
+    let cases = [
+        ("0.1 + 0.2", 0.1f32 + 0.2f32),
+        ("16777216 + 1", 16777216f32 + 1f32),
+        ("1 / 3", 1f32 / 3f32),
+        ("1 / 3 * 3 - 1", 1f32 / 3f32 * 3f32 - 1f32),
+        ("340282350000000000000000000000000000000 * 1", f32::MAX),
+        (
+            "0.000000000000000000000000000000000000000000001 * 1",
+            1e-45f32,
+        ),
+        ("-(1 / 7)", -(1f32 / 7f32)),
+    ];
+    for (expression, expected) in cases {
+        assert_eq!(
+            evaluate(expression).to_bits(),
+            expected.to_bits(),
+            "{expression}"
+        );
+    }
+}
+
+#[test]
+fn strings_are_not_numbers() {
+    let mut interpreter = interpreter("var result = \"1\" + \"2\";");
+    assert!(interpreter.interpret().is_ok());
+    let name = Token::new(TokenType::Identifier, String::from("result"), None, 1, 1);
+    let value = interpreter
+        .globals()
+        .get(name)
+        .ok()
+        .expect("result to be defined")
+        .expect("result to have a value");
+    assert_eq!(value.as_number(), None);
+    assert_eq!(value.print_value(), "12");
+}
@@ -0,0 +1,73 @@
+//! Checks that the tokens read back from a `compile` cache are the ones that were written,
+//! and that caches of other sources or interpreter versions aren't used.
+
+use codecrafters_interpreter::{cache, scan::Scanner, token::Token};
+use std::{env, fs, path::PathBuf};
+
+const SOURCE: &str = "var a = 1_000.5e2;
+var b = \"tab\there
+and a \\\\ backslash ${a} \";
+print a; // comment
+";
+
+fn scan(source: &str) -> Vec<Token> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    assert!(!scanner.has_error());
+    scanner.tokens
+}
+
+fn render(tokens: &[Token]) -> Vec<String> {
+    tokens
+        .iter()
+        .map(|t| format!("{}:{} {}", t.line, t.column, t))
+        .collect()
+}
+
+fn cache_file(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("lox-cache-{}-{name}.loxc", std::process::id()))
+}
+
+#[test]
+fn tokens_round_trip() {
+    let path = cache_file("round-trip");
+    let tokens = scan(SOURCE);
+    cache::write(&path, SOURCE, &tokens).expect("to be able to write the cache");
+    let cached = cache::read(&path, SOURCE).expect("a fresh cache");
+    let _ = fs::remove_file(&path);
+    assert_eq!(render(&cached), render(&tokens));
+}
+
+#[test]
+fn stale_cache_is_ignored() {
+    let path = cache_file("stale");
+    cache::write(&path, SOURCE, &scan(SOURCE)).expect("to be able to write the cache");
+    let edited = SOURCE.replace("print a;", "print b;");
+    let cached = cache::read(&path, &edited);
+    let _ = fs::remove_file(&path);
+    assert!(cached.is_none());
+}
+
+#[test]
+fn cache_from_another_version_is_ignored() {
+    let path = cache_file("version");
+    cache::write(&path, SOURCE, &scan(SOURCE)).expect("to be able to write the cache");
+    let contents = fs::read_to_string(&path).expect("to be able to read the cache");
+    let version = env!("CARGO_PKG_VERSION");
+    assert!(contents.starts_with(&format!("LOXC 3 {version} ")));
+    fs::write(&path, contents.replacen(version, "0.0.0-other", 1)).expect("to rewrite the cache");
+    let cached = cache::read(&path, SOURCE);
+    let _ = fs::remove_file(&path);
+    assert!(cached.is_none());
+}
+
+#[test]
+fn corrupt_cache_is_ignored() {
+    let path = cache_file("corrupt");
+    cache::write(&path, SOURCE, &scan(SOURCE)).expect("to be able to write the cache");
+    let contents = fs::read_to_string(&path).expect("to be able to read the cache");
+    fs::write(&path, contents.replace("VAR", "NOT_A_TOKEN")).expect("to rewrite the cache");
+    let cached = cache::read(&path, SOURCE);
+    let _ = fs::remove_file(&path);
+    assert!(cached.is_none());
+}
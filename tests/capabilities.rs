@@ -0,0 +1,35 @@
+//! Checks that embedders can turn groups of natives on and off.
+
+use codecrafters_interpreter::{
+    interpret::Interpreter, native::Capability, parse::Parser, scan::Scanner,
+};
+
+fn interpreter(source: &str) -> Interpreter {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let statements = Parser::new(scanner.tokens)
+        .parse()
+        .ok()
+        .expect("valid source");
+    Interpreter::new(statements)
+}
+
+#[test]
+fn disabled_natives_raise_an_error() {
+    let mut interpreter = interpreter("clock();").with_capabilities(&[]);
+    let error = interpreter.interpret().expect_err("clock() to be disabled");
+    assert_eq!(
+        error.message,
+        "clock() is disabled because the 'time' capability isn't allowed."
+    );
+}
+
+#[test]
+fn capabilities_can_be_enabled_individually() {
+    let source = "var time = clock();\nvar home = getenv(\"LOX_SURELY_UNSET_VARIABLE\");";
+    let mut interpreter = interpreter(source)
+        .with_capabilities(&[])
+        .with_capability(Capability::Time, true)
+        .with_capability(Capability::Env, true);
+    assert!(interpreter.interpret().is_ok());
+}
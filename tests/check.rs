@@ -0,0 +1,41 @@
+//! Checks that `check` reports errors without running the program.
+
+use std::{env, fs, path::PathBuf, process::Command, process::Output};
+
+/// Writes `source` to a script in the temporary directory that's unique to this test
+fn script(name: &str, source: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("lox-check-{}-{name}.lox", std::process::id()));
+    fs::write(&path, source).expect("to be able to write the script");
+    path
+}
+
+fn check(name: &str, source: &str) -> Output {
+    let path = script(name, source);
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .arg("check")
+        .arg(&path)
+        .output()
+        .expect("to be able to run the interpreter");
+    let _ = fs::remove_file(path);
+    output
+}
+
+#[test]
+fn valid_programs_are_not_run() {
+    let output = check(
+        "valid",
+        "print \"side effect\";\nwrite(\"more\");\nassert(false, \"fails when run\");\nexit(3);",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn parse_errors_are_reported() {
+    let output = check("invalid", "print \"side effect\";\nprint (1;");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("2 | print (1;"), "{stderr}");
+}
@@ -0,0 +1,190 @@
+//! Conformance tests in the format of the Crafting Interpreters test suite: every `.lox`
+//! file states what it should do in comments, which are checked against the `run` command.
+//!
+//! - `// expect: <line>` is a line the program prints, in order
+//! - `// expect runtime error: <message>` makes it fail with exit code 70 and that message
+//! - `// Error ...` or `// [line N] Error ...` makes it fail to compile with exit code 65
+//!
+//! The files in `tests/conformance` cover the chapters this interpreter implements. To
+//! measure progress against the whole book, point `LOX_CONFORMANCE_DIR` at the `test`
+//! directory of a checkout of the official suite and run
+//! `cargo test --test conformance -- --ignored`. Chapters listed in `UNSUPPORTED` are skipped.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Directories of the official suite for features the interpreter doesn't have yet
+const UNSUPPORTED: [&str; 20] = [
+    "benchmark",
+    "call",
+    "class",
+    "closure",
+    "constructor",
+    "expressions",
+    "field",
+    "for",
+    "function",
+    "if",
+    "inheritance",
+    "limit",
+    "logical_operator",
+    "method",
+    "regression",
+    "return",
+    "scanning",
+    "super",
+    "this",
+    "while",
+];
+
+/// What a test file says running it should do
+struct Expectations {
+    output: Vec<String>,
+    runtime_error: Option<String>,
+    compile_error: bool,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Expectations {
+            output: Vec::new(),
+            runtime_error: None,
+            compile_error: false,
+        };
+        for line in source.lines() {
+            let Some((_, comment)) = line.split_once("// ") else {
+                continue;
+            };
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_string());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some(message.to_string());
+            } else if comment.starts_with("Error") || is_line_error(comment) {
+                expectations.compile_error = true;
+            }
+        }
+        expectations
+    }
+
+    fn exit_code(&self) -> i32 {
+        match (self.compile_error, &self.runtime_error) {
+            (true, _) => 65,
+            (false, Some(_)) => 70,
+            (false, None) => 0,
+        }
+    }
+}
+
+/// Whether the comment is a `[line N] Error` or `[java line N] Error` expectation.
+/// `[c line N]` ones are for clox, which reports some errors differently.
+fn is_line_error(comment: &str) -> bool {
+    let rest = comment
+        .strip_prefix("[line ")
+        .or_else(|| comment.strip_prefix("[java line "));
+    rest.and_then(|rest| rest.split_once("] "))
+        .is_some_and(|(_, message)| message.starts_with("Error"))
+}
+
+/// Every `.lox` file under `dir`, sorted
+fn test_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir).expect("to be able to read the test directory");
+        for entry in entries {
+            let path = entry.expect("to be able to read a test file").path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "lox") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Runs the test file and describes how it didn't meet its expectations, if it didn't
+fn check(path: &Path) -> Option<String> {
+    let source = fs::read_to_string(path).ok()?;
+    let expected = Expectations::parse(&source);
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .arg("run")
+        .arg(path)
+        .output()
+        .expect("to be able to run the interpreter");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let mut problems = Vec::new();
+    let exit_code = output.status.code().unwrap_or(-1);
+    if exit_code != expected.exit_code() {
+        problems.push(format!(
+            "exited with {exit_code} instead of {}",
+            expected.exit_code()
+        ));
+    }
+    // A program that fails to compile prints nothing
+    if !expected.compile_error {
+        let lines = stdout.lines().collect::<Vec<&str>>();
+        if lines != expected.output {
+            problems.push(format!(
+                "printed {lines:?} instead of {:?}",
+                expected.output
+            ));
+        }
+    }
+    if let Some(message) = &expected.runtime_error {
+        if !stderr.contains(message.as_str()) {
+            problems.push(format!("didn't report \"{message}\""));
+        }
+    }
+    match problems.is_empty() {
+        true => None,
+        false => Some(format!(
+            "{}: {}\n{stderr}",
+            path.display(),
+            problems.join(", ")
+        )),
+    }
+}
+
+/// Runs every test under `dir` that isn't in an unsupported chapter, returning how
+/// many were run and the failures
+fn run_suite(dir: &Path) -> (usize, Vec<String>) {
+    let supported = |path: &PathBuf| {
+        let chapter = path.strip_prefix(dir).ok().and_then(|p| p.iter().next());
+        !chapter.is_some_and(|c| UNSUPPORTED.iter().any(|u| c == *u))
+    };
+    let tests = test_files(dir)
+        .into_iter()
+        .filter(supported)
+        .collect::<Vec<PathBuf>>();
+    let failures = tests.iter().filter_map(|path| check(path)).collect();
+    (tests.len(), failures)
+}
+
+#[test]
+fn conformance_corpus_passes() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let (run, failures) = run_suite(&dir);
+    assert!(run > 0, "no tests found in {}", dir.display());
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+#[ignore = "needs LOX_CONFORMANCE_DIR to point at the official test suite"]
+fn official_suite_passes() {
+    let Some(dir) = env::var_os("LOX_CONFORMANCE_DIR") else {
+        panic!("set LOX_CONFORMANCE_DIR to the test directory of the official suite");
+    };
+    let (run, failures) = run_suite(Path::new(&dir));
+    eprintln!(
+        "conformance: {} of {run} tests passed",
+        run - failures.len()
+    );
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,22 @@
+//! Checks how many terminal columns diagnostics assume text takes up.
+
+use codecrafters_interpreter::diagnostic::display_width;
+
+#[test]
+fn characters_take_one_or_two_columns() {
+    assert_eq!(display_width("print"), 5);
+    assert_eq!(display_width("日本語"), 6);
+    assert_eq!(display_width("한글"), 4);
+    assert_eq!(display_width("🇩🇪"), 2);
+    assert_eq!(display_width("🦀x"), 3);
+    assert_eq!(display_width("e\u{301}"), 1);
+    assert_eq!(display_width(""), 0);
+}
+
+#[test]
+fn tabs_go_to_the_next_tab_stop() {
+    assert_eq!(display_width("\t"), 4);
+    assert_eq!(display_width("ab\t"), 4);
+    assert_eq!(display_width("abcd\tx"), 9);
+    assert_eq!(display_width("日本\t"), 8);
+}
@@ -0,0 +1,80 @@
+//! Checks depth-indexed variable access on nested scopes.
+
+use codecrafters_interpreter::{
+    environment::Environment,
+    interpret::Interpreter,
+    token::{LiteralValue, NumberLiteral, Token},
+    TokenType,
+};
+
+fn name(lexeme: &str) -> Token {
+    Token::new(TokenType::Identifier, lexeme.to_string(), None, 1, 1)
+}
+
+fn number(value: f32) -> Option<Box<dyn LiteralValue>> {
+    Some(Box::new(NumberLiteral { value }))
+}
+
+fn value_at(environment: &Environment, depth: usize, lexeme: &str) -> Option<String> {
+    match environment.get_at(depth, name(lexeme)) {
+        Ok(value) => value.map(|v| v.print_value()),
+        Err(e) => Some(e.message),
+    }
+}
+
+#[test]
+fn get_at_only_looks_in_one_scope() {
+    let mut globals = Environment::new(None);
+    globals.define(String::from("a"), number(1.0));
+    let mut block = Environment::new(Some(globals.clone()));
+    block.define(String::from("a"), number(2.0));
+    let inner = Environment::new(Some(block.clone()));
+
+    assert_eq!(value_at(&inner, 1, "a"), Some(String::from("2.0")));
+    assert_eq!(value_at(&inner, 2, "a"), Some(String::from("1.0")));
+    assert_eq!(
+        value_at(&inner, 0, "a"),
+        Some(String::from("Undefined variable 'a'."))
+    );
+    assert_eq!(
+        value_at(&inner, 3, "a"),
+        Some(String::from("Undefined variable 'a'."))
+    );
+}
+
+#[test]
+fn assign_at_skips_shadowing_scopes() {
+    let mut globals = Environment::new(None);
+    globals.define(String::from("a"), number(1.0));
+    let mut block = Environment::new(Some(globals.clone()));
+    block.define(String::from("a"), number(2.0));
+
+    assert!(block.assign_at(1, name("a"), number(3.0)).is_ok());
+    assert_eq!(value_at(&block, 0, "a"), Some(String::from("2.0")));
+    assert_eq!(value_at(&globals, 0, "a"), Some(String::from("3.0")));
+    assert!(block.assign_at(0, name("b"), number(4.0)).is_err());
+}
+
+#[test]
+fn interpreter_exposes_globals() {
+    let globals = Interpreter::new(Vec::new()).globals();
+    assert!(globals.get(name("clock")).is_ok());
+    assert!(globals.enclosing().is_none());
+}
+
+#[test]
+fn undefined_variables_suggest_a_visible_name() {
+    let mut globals = Environment::new(None);
+    globals.define(String::from("count"), number(1.0));
+    let mut block = Environment::new(Some(globals.clone()));
+    block.define(String::from("total"), number(2.0));
+
+    let notes = |environment: &Environment, lexeme: &str| match environment.get(name(lexeme)) {
+        Ok(_) => panic!("'{lexeme}' shouldn't be defined"),
+        Err(e) => e.notes,
+    };
+    assert_eq!(notes(&block, "cont"), ["did you mean 'count'?"]);
+    assert_eq!(notes(&block, "totl"), ["did you mean 'total'?"]);
+    assert!(notes(&globals, "totl").is_empty(), "'total' isn't visible");
+    assert!(notes(&block, "x").is_empty(), "nothing is close enough");
+}
@@ -0,0 +1,56 @@
+//! Checks that `evaluate_expr` gives embedders the value of an expression.
+
+use codecrafters_interpreter::{
+    expression::ErrorKind,
+    interpret::{default_environment, evaluate_expr, LoxError},
+};
+
+#[test]
+fn values_are_returned() {
+    let mut environment = default_environment();
+    let value = evaluate_expr("1 + 2 * 3", &mut environment)
+        .ok()
+        .flatten()
+        .expect("a value");
+    assert_eq!(value.as_number(), Some(7.0));
+
+    let value = evaluate_expr("\"a\" + \"b\"", &mut environment)
+        .ok()
+        .flatten()
+        .expect("a value");
+    assert_eq!(value.print_value(), "ab");
+}
+
+#[test]
+fn natives_in_the_environment_can_be_called() {
+    let mut environment = default_environment();
+    let value = evaluate_expr("type(1)", &mut environment)
+        .ok()
+        .flatten()
+        .expect("a value");
+    assert_eq!(value.print_value(), "number");
+
+    // Including the ones of the capabilities allowed by default
+    let value = evaluate_expr("clock() >= 0", &mut environment)
+        .ok()
+        .flatten()
+        .expect("a value");
+    assert_eq!(value.print_value(), "true");
+}
+
+#[test]
+fn each_phase_fails_with_its_own_error() {
+    let mut environment = default_environment();
+    assert!(matches!(
+        evaluate_expr("1 @ 2", &mut environment),
+        Err(LoxError::Scan(errors)) if errors.len() == 1
+    ));
+    assert!(matches!(
+        evaluate_expr("1 +", &mut environment),
+        Err(LoxError::Parse(_))
+    ));
+    match evaluate_expr("-\"a\"", &mut environment) {
+        Err(LoxError::Runtime(e)) => assert!(e.kind == ErrorKind::TypeError),
+        _ => panic!("expected a runtime error"),
+    }
+}
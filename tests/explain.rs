@@ -0,0 +1,70 @@
+//! Checks the explanation `explain` prints for a program.
+
+use codecrafters_interpreter::{explain::explain, parse::Parser, scan::Scanner};
+
+fn explained(source: &str) -> String {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let stmts = Parser::new(scanner.tokens)
+        .parse()
+        .ok()
+        .expect("valid source");
+    explain(source, &stmts)
+}
+
+#[test]
+fn operands_are_evaluated_before_their_operator() {
+    assert_eq!(
+        explained("print -1 + 2 * 3;"),
+        "[line 1] print -1 + 2 * 3;
+  parsed as: (print (+ (- 1.0) (* 2.0 3.0)))
+  evaluates:
+    1. 1.0
+    2. (- 1.0)
+    3. 2.0
+    4. 3.0
+    5. (* 2.0 3.0)
+    6. (+ (- 1.0) (* 2.0 3.0))
+"
+    );
+}
+
+#[test]
+fn calls_evaluate_the_callee_then_the_arguments() {
+    assert_eq!(
+        explained("f(a = 1, 2);"),
+        "[line 1] f(a = 1, 2);
+  parsed as: (; (call IDENTIFIER f null a = 1.0 2.0))
+  evaluates:
+    1. IDENTIFIER f null
+    2. 1.0
+    3. a = 1.0
+    4. 2.0
+    5. (call IDENTIFIER f null a = 1.0 2.0)
+"
+    );
+}
+
+#[test]
+fn desugared_forms_are_shown() {
+    let explanation = explained("var f = (x) -> \"${x}\";");
+    assert!(explanation.contains(
+        "parsed as: (var f = (fun (x) (block (return (+ (+  (str IDENTIFIER x null)) )))))"
+    ));
+}
+
+#[test]
+fn nested_statements_are_explained_after_their_parent() {
+    assert_eq!(
+        explained("{\n  var a;\n  {\n    print a;\n  }\n}"),
+        "[line 1] { ...
+  [line 2] var a;
+    parsed as: (var a)
+  [line 3] { ...
+    [line 4] print a;
+      parsed as: (print IDENTIFIER a null)
+      evaluates:
+        1. IDENTIFIER a null
+"
+    );
+}
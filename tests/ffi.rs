@@ -0,0 +1,131 @@
+//! Drives the C ABI from Rust, the way an embedding application would.
+//! Run with `cargo test --features ffi`.
+#![cfg(feature = "ffi")]
+
+use codecrafters_interpreter::ffi::{
+    lox_free, lox_last_error, lox_new, lox_register_native, lox_run, LoxValue, LoxValueType,
+};
+use std::{
+    ffi::{c_int, c_void, CStr, CString},
+    ptr,
+};
+
+fn run(lox: *mut codecrafters_interpreter::ffi::Lox, source: &str) -> c_int {
+    let source = CString::new(source).expect("source without NUL");
+    unsafe { lox_run(lox, source.as_ptr()) }
+}
+
+fn last_error(lox: *mut codecrafters_interpreter::ffi::Lox) -> Option<String> {
+    let error = unsafe { lox_last_error(lox) };
+    if error.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+/// Adds its two number arguments to the running total in `user_data`
+extern "C" fn accumulate(
+    user_data: *mut c_void,
+    argc: usize,
+    argv: *const LoxValue,
+    result: *mut LoxValue,
+) -> c_int {
+    let total = unsafe { &mut *(user_data as *mut f64) };
+    let arguments = unsafe { std::slice::from_raw_parts(argv, argc) };
+    for argument in arguments {
+        if argument.value_type != LoxValueType::Number {
+            unsafe {
+                (*result).value_type = LoxValueType::String;
+                (*result).string = c"accumulate() expects numbers.".as_ptr();
+            }
+            return 1;
+        }
+        *total += argument.number;
+    }
+    unsafe {
+        (*result).value_type = LoxValueType::Number;
+        (*result).number = *total;
+    }
+    0
+}
+
+/// Writes a `value_type` that isn't one of `LoxValueType`, as buggy C code could
+extern "C" fn unknown_type(
+    _user_data: *mut c_void,
+    _argc: usize,
+    _argv: *const LoxValue,
+    result: *mut LoxValue,
+) -> c_int {
+    unsafe { *result.cast::<c_int>() = 7 };
+    0
+}
+
+#[test]
+fn globals_persist_between_runs() {
+    let lox = lox_new();
+    assert_eq!(run(lox, "var a = 1;"), 0);
+    assert_eq!(run(lox, "a = a + 1; assert(a == 2, \"a is 2\");"), 0);
+    assert_eq!(last_error(lox), None);
+    unsafe { lox_free(lox) };
+}
+
+#[test]
+fn errors_are_reported() {
+    let lox = lox_new();
+    assert_eq!(run(lox, "print (1;"), 65);
+    assert!(last_error(lox).is_some());
+    assert_eq!(run(lox, "print undefined;"), 70);
+    let error = last_error(lox).expect("a runtime error");
+    assert!(error.contains("Undefined variable 'undefined'."));
+    assert_eq!(run(lox, "exit(3);"), 3);
+    unsafe { lox_free(lox) };
+}
+
+#[test]
+fn natives_are_called_with_user_data() {
+    let lox = lox_new();
+    let mut total = 0.0f64;
+    let name = CString::new("accumulate").expect("name without NUL");
+    let status = unsafe {
+        lox_register_native(
+            lox,
+            name.as_ptr(),
+            2,
+            Some(accumulate),
+            &mut total as *mut f64 as *mut c_void,
+        )
+    };
+    assert_eq!(status, 0);
+    assert_eq!(
+        run(lox, "assert(accumulate(1, 2) == 3, \"first call\");"),
+        0
+    );
+    assert_eq!(run(lox, "accumulate(4, 5);"), 0);
+    assert_eq!(total, 12.0);
+
+    assert_eq!(run(lox, "accumulate(1, \"two\");"), 70);
+    let error = last_error(lox).expect("the native's error");
+    assert!(error.contains("accumulate() expects numbers."));
+    unsafe { lox_free(lox) };
+    assert!(unsafe { lox_last_error(ptr::null()) }.is_null());
+}
+
+#[test]
+fn invalid_natives_are_rejected() {
+    let lox = lox_new();
+    let name = CString::new("broken").expect("name without NUL");
+    let status = unsafe { lox_register_native(lox, name.as_ptr(), 0, None, ptr::null_mut()) };
+    assert_eq!(status, -1);
+
+    let status =
+        unsafe { lox_register_native(lox, name.as_ptr(), 0, Some(unknown_type), ptr::null_mut()) };
+    assert_eq!(status, 0);
+    assert_eq!(run(lox, "broken();"), 70);
+    let error = last_error(lox).expect("an error about the result");
+    assert!(error.contains("broken() returned a value of unknown type 7."));
+    unsafe { lox_free(lox) };
+}
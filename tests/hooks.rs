@@ -0,0 +1,168 @@
+//! Checks that hooks registered on the interpreter see statements, calls and
+//! variable writes, and can stop the program.
+
+use codecrafters_interpreter::{
+    expression::RuntimeError,
+    hooks::Hooks,
+    interpret::{stringify, Interpreter},
+    parse::Parser,
+    scan::Scanner,
+    statement::Statement,
+    token::{LiteralValue, Token},
+};
+use std::{cell::RefCell, rc::Rc};
+
+fn interpreter(source: &str) -> Interpreter {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let statements = Parser::new(scanner.tokens)
+        .parse()
+        .ok()
+        .expect("valid source");
+    Interpreter::new(statements)
+}
+
+#[derive(Default)]
+struct Recorder {
+    events: RefCell<Vec<String>>,
+}
+
+impl Hooks for Recorder {
+    fn on_statement(&self, statement: &dyn Statement) -> Result<(), RuntimeError> {
+        self.events.borrow_mut().push(statement.accept());
+        Ok(())
+    }
+
+    fn on_call(
+        &self,
+        callee: &dyn LiteralValue,
+        arguments: &[Box<dyn LiteralValue>],
+        paren: &Token,
+    ) -> Result<(), RuntimeError> {
+        let arguments = arguments
+            .iter()
+            .map(|a| stringify(Some(a.clone())))
+            .collect::<Vec<String>>();
+        self.events.borrow_mut().push(format!(
+            "call {} ({}) on line {}",
+            callee.print_value(),
+            arguments.join(", "),
+            paren.line
+        ));
+        Ok(())
+    }
+
+    fn on_variable_write(
+        &self,
+        name: &Token,
+        value: Option<&dyn LiteralValue>,
+    ) -> Result<(), RuntimeError> {
+        let value = stringify(value.map(|v| v.clone_box()));
+        self.events
+            .borrow_mut()
+            .push(format!("write {} = {value}", name.lexeme));
+        Ok(())
+    }
+}
+
+#[test]
+fn hooks_see_statements_calls_and_writes() {
+    let recorder = Rc::new(Recorder::default());
+    let mut interpreter =
+        interpreter("var a = 1;\nvar f = fun (x) { a = x; };\nf(2);").with_hooks(recorder.clone());
+    assert!(interpreter.interpret().is_ok());
+    assert_eq!(
+        recorder.events.borrow().as_slice(),
+        [
+            "(var a = 1.0)",
+            "write a = 1",
+            "(var f = (fun (x) (block (; a = IDENTIFIER x null))))",
+            "write f = <fn>",
+            "(; (call IDENTIFIER f null 2.0))",
+            "call <fn> (2) on line 3",
+            "(; a = IDENTIFIER x null)",
+            "write a = 2",
+        ]
+    );
+}
+
+/// Forbids calling natives
+struct NoNatives;
+
+impl Hooks for NoNatives {
+    fn on_call(
+        &self,
+        callee: &dyn LiteralValue,
+        _arguments: &[Box<dyn LiteralValue>],
+        paren: &Token,
+    ) -> Result<(), RuntimeError> {
+        if callee.print_value() == "<native fn>" {
+            return Err(RuntimeError::new(
+                paren.clone(),
+                String::from("Natives are not allowed."),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn hooks_can_stop_the_program() {
+    let mut interpreter =
+        interpreter("var f = fun () {};\nf();\nclock();").with_hooks(Rc::new(NoNatives));
+    let error = interpreter
+        .interpret()
+        .expect_err("the hook to stop the program");
+    assert_eq!(error.message, "Natives are not allowed.");
+    assert_eq!(error.token.line, 3);
+}
+
+#[test]
+fn hooks_only_apply_to_their_interpreter() {
+    let mut policed = interpreter("var a = 1;").with_hooks(Rc::new(NoNatives));
+    assert!(policed.interpret().is_ok());
+    drop(policed);
+    let mut unpoliced = interpreter("clock();");
+    assert!(unpoliced.interpret().is_ok());
+}
+
+#[test]
+fn hooks_see_callbacks_from_natives() {
+    let recorder = Rc::new(Recorder::default());
+    let mut interpreter =
+        interpreter("reduce((a, c) -> a + c, \"\", \"ab\");").with_hooks(recorder.clone());
+    assert!(interpreter.interpret().is_ok());
+    let events = recorder.events.borrow();
+    let calls = events
+        .iter()
+        .filter(|e| e.starts_with("call"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        calls,
+        [
+            "call <native fn> (<fn>, , ab) on line 1",
+            "call <fn> (, a) on line 1",
+            "call <fn> (a, b) on line 1",
+        ]
+    );
+}
+
+#[test]
+fn hooks_see_writes_of_nil_and_foreach_bodies() {
+    let recorder = Rc::new(Recorder::default());
+    let mut interpreter = interpreter("var a = 1;\na = nil;\nfor (c in \"xy\") print c;")
+        .with_hooks(recorder.clone());
+    assert!(interpreter.interpret().is_ok());
+    assert_eq!(
+        recorder.events.borrow().as_slice(),
+        [
+            "(var a = 1.0)",
+            "write a = 1",
+            "(; a = nil)",
+            "write a = nil",
+            "(for c in xy (print IDENTIFIER c null))",
+            "(print IDENTIFIER c null)",
+            "(print IDENTIFIER c null)",
+        ]
+    );
+}
@@ -0,0 +1,131 @@
+//! Checks that editing a `Document` gives the same statements and tokens as
+//! parsing the edited source from scratch.
+
+use codecrafters_interpreter::incremental::{Document, TextEdit};
+
+const SOURCE: &str = "var a = 1;
+var b = \"two
+lines\";
+print a; print b;
+
+{
+  print a + 1;
+}
+";
+
+fn render(document: &Document) -> (Vec<String>, Vec<String>) {
+    let statements = document
+        .statements()
+        .map(|(s, origin)| format!("{:?} {}", origin.span(s.span()), s.accept()))
+        .collect();
+    let tokens = document
+        .tokens()
+        .map(|t| format!("{}:{}@{} {}", t.line, t.column, t.offset, t))
+        .collect();
+    (statements, tokens)
+}
+
+/// Applies the edit and checks the result against a full parse
+fn assert_edit(source: &str, find: &str, replacement: &str) {
+    let mut document = Document::new(source.to_string())
+        .ok()
+        .expect("valid source");
+    let start = source.find(find).expect("text to replace");
+    let edit = TextEdit {
+        range: start..start + find.len(),
+        text: replacement.to_string(),
+    };
+    assert!(document.apply_edit(edit).is_ok());
+
+    let expected_source = source.replacen(find, replacement, 1);
+    assert_eq!(document.source(), expected_source);
+    let expected = Document::new(expected_source).ok().expect("valid edit");
+    assert_eq!(render(&document), render(&expected));
+}
+
+#[test]
+fn edit_within_a_line() {
+    assert_edit(SOURCE, "var a = 1;", "var a = 100;");
+}
+
+#[test]
+fn edit_shared_line() {
+    assert_edit(SOURCE, "print b;", "print b + a;");
+}
+
+#[test]
+fn edit_adds_lines() {
+    assert_edit(SOURCE, "var a = 1;", "var a = 1;\nvar c = 3;\n");
+}
+
+#[test]
+fn edit_removes_lines() {
+    assert_edit(SOURCE, "\"two\nlines\"", "\"one line\"");
+}
+
+#[test]
+fn edit_between_statements() {
+    assert_edit(SOURCE, "\n\n{", "\n// comment\n\n{");
+}
+
+#[test]
+fn edit_merges_statements() {
+    assert_edit(SOURCE, "}\n", "print a;\n}\n");
+}
+
+#[test]
+fn edit_only_parses_the_statements_it_touches() {
+    let source = (1..=100)
+        .map(|i| format!("var v{i} = {i};\n"))
+        .collect::<String>();
+    let mut document = Document::new(source.clone()).ok().expect("valid source");
+    assert_eq!(document.statements_parsed(), 100);
+
+    // Adding lines and bytes moves every later statement, without parsing them again
+    let start = source.find("var v2 = 2;").expect("statement");
+    let edit = TextEdit {
+        range: start..start + "var v2 = 2;".len(),
+        text: String::from("var v2 =\n  2 + 2;"),
+    };
+    assert!(document.apply_edit(edit).is_ok());
+    assert_eq!(document.statements_parsed(), 101);
+
+    let expected_source = source.replacen("var v2 = 2;", "var v2 =\n  2 + 2;", 1);
+    let expected = Document::new(expected_source).ok().expect("valid edit");
+    assert_eq!(render(&document), render(&expected));
+}
+
+#[test]
+fn broken_edit_recovers_on_the_next_edit() {
+    let mut document = Document::new(SOURCE.to_string())
+        .ok()
+        .expect("valid source");
+    let start = SOURCE.find("print a;").expect("statement");
+    let broken = TextEdit {
+        range: start..start + "print a;".len(),
+        text: String::from("print a"),
+    };
+    assert!(document.apply_edit(broken).is_err());
+
+    let fixed = TextEdit {
+        range: start + "print a".len()..start + "print a".len(),
+        text: String::from(";"),
+    };
+    assert!(document.apply_edit(fixed).is_ok());
+    let expected = Document::new(SOURCE.to_string())
+        .ok()
+        .expect("valid source");
+    assert_eq!(render(&document), render(&expected));
+}
+
+#[test]
+fn out_of_bounds_edit_is_rejected() {
+    let mut document = Document::new(SOURCE.to_string())
+        .ok()
+        .expect("valid source");
+    let edit = TextEdit {
+        range: 0..SOURCE.len() + 1,
+        text: String::new(),
+    };
+    assert!(document.apply_edit(edit).is_err());
+}
@@ -0,0 +1,55 @@
+//! Checks that Ctrl-C stops `run` with the call stack instead of killing it silently.
+#![cfg(unix)]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+#[test]
+fn ctrl_c_prints_the_call_stack() {
+    let script = Path::new(env!("CARGO_TARGET_TMPDIR")).join("interrupt.lox");
+    fs::write(
+        &script,
+        "var spin = fun () {
+  print \"ready\";
+  sleep(1);
+  return 1 + spin();
+};
+var start = fun () {
+  spin();
+};
+start();
+",
+    )
+    .expect("to be able to write the script");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .arg("run")
+        .arg(&script)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("to be able to run the interpreter");
+    // Once the script prints, the handler is installed and spin() is running
+    let mut ready = String::new();
+    BufReader::new(child.stdout.take().expect("piped stdout"))
+        .read_line(&mut ready)
+        .expect("to read the first line");
+    assert_eq!(ready, "ready\n");
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("to be able to run kill");
+    assert!(status.success());
+
+    let output = child.wait_with_output().expect("the interpreter to exit");
+    assert_eq!(output.status.code(), Some(130));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Interrupted: Stopped by Ctrl-C.\n"));
+    // However deep the recursion got, the outermost calls are at the end
+    assert!(stderr
+        .ends_with("note: in spin(), called on line 7\n  note: in start(), called on line 9\n"));
+}
@@ -0,0 +1,204 @@
+//! Checks that `Interpreter::with_limits` stops programs that run too long.
+
+use codecrafters_interpreter::{
+    diagnostic, expression::ErrorKind, interpret::Interpreter, parse::Parser, scan::Scanner,
+    statement::Statement, token::Token, TokenType,
+};
+use std::{
+    env,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn statements(source: &str) -> Vec<Box<dyn Statement>> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    Parser::new(scanner.tokens)
+        .parse()
+        .ok()
+        .expect("valid source")
+}
+
+fn interpreter(source: &str) -> Interpreter {
+    Interpreter::new(statements(source))
+}
+
+const FOREVER: &str = "var f = fun () { f(); };\nf();";
+
+#[test]
+fn step_budget_stops_endless_recursion() {
+    let mut interpreter = interpreter(FOREVER).with_limits(Some(100), None);
+    let error = interpreter.interpret().expect_err("the budget to run out");
+    assert!(error.kind == ErrorKind::BudgetExceeded);
+    assert_eq!(error.message, "Execution budget exceeded.");
+    assert_eq!(error.token.line, 1);
+}
+
+#[test]
+fn time_budget_stops_endless_recursion() {
+    let mut interpreter = interpreter(FOREVER).with_limits(None, Some(0));
+    let error = interpreter.interpret().expect_err("the budget to run out");
+    assert!(error.kind == ErrorKind::BudgetExceeded);
+}
+
+#[test]
+fn programs_within_the_budget_finish() {
+    let source = "var f = fun (n) { return n; };\nvar a = f(1);\nvar b = clock();";
+    let mut interpreter = interpreter(source).with_limits(Some(2), Some(60_000));
+    assert!(interpreter.interpret().is_ok());
+}
+
+#[test]
+fn loops_without_calls_count_against_the_budget() {
+    let source = "var n = 0;\nfor (x in range(0, 20000000)) { n = n + 1; }";
+    let mut interpreter = interpreter(source).with_limits(Some(10), Some(50));
+    let error = interpreter.interpret().expect_err("the budget to run out");
+    assert!(error.kind == ErrorKind::BudgetExceeded);
+    assert_eq!(error.token.line, 2);
+}
+
+#[test]
+fn callbacks_from_natives_count_against_the_budget() {
+    let source = "reduce((a, b) -> a + b, 0, range(1000000));";
+    let mut interpreter = interpreter(source).with_limits(Some(100), None);
+    let error = interpreter.interpret().expect_err("the budget to run out");
+    assert!(error.kind == ErrorKind::BudgetExceeded);
+}
+
+#[test]
+fn limits_only_apply_to_their_interpreter() {
+    let spent = interpreter("print 1;").with_limits(Some(0), None);
+    drop(spent);
+    let mut unlimited = interpreter("print clock() > 0;");
+    assert!(unlimited.interpret().is_ok());
+}
+
+#[test]
+fn time_counts_from_when_the_program_runs() {
+    let mut interpreter = interpreter("clock();").with_limits(None, Some(50));
+    thread::sleep(Duration::from_millis(100));
+    assert!(interpreter.interpret().is_ok());
+}
+
+#[test]
+fn sleep_stops_when_the_time_runs_out() {
+    let mut interpreter =
+        interpreter("sleep(3000);\nprint \"after\";").with_limits(None, Some(100));
+    let start = Instant::now();
+    let error = interpreter.interpret().expect_err("the time to run out");
+    assert!(error.kind == ErrorKind::BudgetExceeded);
+    assert_eq!(error.token.line, 1);
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+/// Run by `readline_stops_when_the_time_runs_out` in a copy of this test binary
+/// whose stdin never ends
+#[test]
+fn readline_with_a_time_limit() {
+    if env::var_os("LOX_LIMITS_WAITING_STDIN").is_none() {
+        return;
+    }
+    let mut interpreter = interpreter("readline();").with_limits(None, Some(100));
+    let error = interpreter.interpret().expect_err("the time to run out");
+    assert!(error.kind == ErrorKind::BudgetExceeded);
+}
+
+#[test]
+fn readline_stops_when_the_time_runs_out() {
+    let test_binary = env::current_exe().expect("the path of the test binary");
+    let mut child = Command::new(test_binary)
+        .args(["--exact", "readline_with_a_time_limit"])
+        .env("LOX_LIMITS_WAITING_STDIN", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("to be able to run the test binary");
+    let start = Instant::now();
+    // Holding on to stdin keeps it open, so readline() waits for a line
+    let _stdin = child.stdin.take();
+    let status = loop {
+        if let Some(status) = child.try_wait().expect("to wait for the test") {
+            break status;
+        }
+        if start.elapsed() > Duration::from_secs(10) {
+            let _ = child.kill();
+            panic!("readline() kept waiting after the time ran out");
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    assert!(status.success());
+}
+
+#[test]
+fn steps_left_carry_over_to_later_statements() {
+    let mut interpreter = interpreter("clock();\nclock();").with_limits(Some(3), None);
+    assert!(interpreter.interpret().is_ok());
+    let error = interpreter
+        .run_statements(statements("clock();\nclock();"))
+        .expect_err("the budget to run out");
+    assert!(error.kind == ErrorKind::BudgetExceeded);
+    assert_eq!(error.token.line, 2);
+}
+
+#[test]
+fn catch_cannot_escape_a_spent_budget() {
+    let source = "var f = fun () { f(); };
+var caught = false;
+try { f(); } catch (e) { caught = true; clock(); }";
+    let mut interpreter = interpreter(source).with_limits(Some(10), None);
+    let error = interpreter.interpret().expect_err("the budget to run out");
+    assert!(error.kind == ErrorKind::BudgetExceeded);
+    assert_eq!(error.token.line, 3);
+    let caught = interpreter
+        .globals()
+        .names()
+        .contains(&String::from("caught"));
+    assert!(caught);
+}
+
+#[test]
+fn stalled_programs_are_warned_about_once() {
+    let source = "var f = fun () { return f(); };\nf();";
+    let mut interpreter = interpreter(source)
+        .with_limits(Some(3_000), None)
+        .with_stall_warning(Some(1_000));
+    let warnings = diagnostic::warning_count();
+    let error = interpreter.interpret().expect_err("the budget to run out");
+    assert!(error.kind == ErrorKind::BudgetExceeded);
+    assert_eq!(diagnostic::warning_count() - warnings, 1);
+}
+
+#[test]
+fn deep_recursion_is_a_catchable_error() {
+    let source = "var f = fun (n) { return 1 + f(n); };
+var caught = nil;
+try { f(1); } catch (e) { caught = e; }";
+    // The default depth needs more stack than test threads get
+    let caught = thread::Builder::new()
+        .stack_size(256 * 1024 * 1024)
+        .spawn(move || {
+            let mut interpreter = interpreter(source);
+            assert!(interpreter.interpret().is_ok());
+            let caught = Token::new(TokenType::Identifier, String::from("caught"), None, 3, 1);
+            let value = interpreter.globals().get(caught).ok().flatten();
+            value.map(|v| v.print_value())
+        })
+        .expect("to be able to start a thread")
+        .join()
+        .expect("the interpreter not to overflow the stack");
+    assert_eq!(caught.as_deref(), Some("Stack overflow."));
+}
+
+#[test]
+fn call_depth_can_be_lowered() {
+    let source = "var f = fun (n) { return 1 + f(n + 1); };\nf(1);";
+    let mut shallow = interpreter(source).with_max_call_depth(50);
+    let error = shallow.interpret().expect_err("a stack overflow");
+    assert!(error.kind == ErrorKind::StackOverflow);
+    assert_eq!(error.token.line, 1);
+    // The calls that failed don't count against later ones
+    let mut shallowest =
+        interpreter("var g = fun (n) { return n; };\ng(1);").with_max_call_depth(1);
+    assert!(shallowest.interpret().is_ok());
+}
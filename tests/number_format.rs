@@ -0,0 +1,57 @@
+//! Number formatting against the rules of Java's `Double.toString`, applied to single
+//! precision numbers, with NaN and the infinities spelled like clox spells them.
+
+use codecrafters_interpreter::{
+    interpret::stringify,
+    token::{format_number, NumberLiteral},
+};
+
+/// (value, literal as `tokenize` shows it, output of `print`)
+const TABLE: [(f32, &str, &str); 18] = [
+    (0.0, "0.0", "0"),
+    (-0.0, "-0.0", "-0"),
+    (1.0, "1.0", "1"),
+    (-42.0, "-42.0", "-42"),
+    (1234.1234, "1234.1234", "1234.1234"),
+    (0.1, "0.1", "0.1"),
+    (0.001, "0.001", "0.001"),
+    (0.0001, "1.0E-4", "1.0E-4"),
+    (0.00015, "1.5E-4", "1.5E-4"),
+    (9999999.0, "9999999.0", "9999999"),
+    (10000000.0, "1.0E7", "1.0E7"),
+    (12345678.0, "1.2345678E7", "1.2345678E7"),
+    (1e21, "1.0E21", "1.0E21"),
+    (-2.5e-10, "-2.5E-10", "-2.5E-10"),
+    (f32::MAX, "3.4028235E38", "3.4028235E38"),
+    (f32::INFINITY, "inf", "inf"),
+    (f32::NEG_INFINITY, "-inf", "-inf"),
+    (f32::NAN, "nan", "nan"),
+];
+
+#[test]
+fn literals_follow_double_to_string() {
+    for (value, literal, _) in TABLE {
+        assert_eq!(format_number(value), literal, "literal of {value:?}");
+    }
+}
+
+#[test]
+fn print_drops_the_trailing_point_zero() {
+    for (value, _, printed) in TABLE {
+        let number = NumberLiteral { value };
+        assert_eq!(
+            stringify(Some(Box::new(number))),
+            printed,
+            "print of {value:?}"
+        );
+    }
+}
+
+#[test]
+fn literals_read_back_as_the_same_number() {
+    for (value, literal, _) in TABLE {
+        if value.is_finite() {
+            assert_eq!(literal.parse::<f32>().ok(), Some(value), "{literal}");
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Property tests for operator precedence and associativity: random expression trees are
+//! printed as source with as few parentheses as possible, parsed again and compared with
+//! the tree they came from. Trees are generated from fixed seeds, so failures reproduce.
+
+use codecrafters_interpreter::{parse::Parser, scan::Scanner};
+
+/// How tightly each kind of expression binds, loosest first
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Precedence {
+    Comma,
+    Assignment,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+#[derive(Clone)]
+enum Expr {
+    Number(u32),
+    Str(&'static str),
+    Variable(&'static str),
+    Group(Box<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(Box<Expr>, &'static str, Box<Expr>),
+    Assign(&'static str, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn precedence(&self) -> Precedence {
+        match self {
+            Expr::Number(_) | Expr::Str(_) | Expr::Variable(_) | Expr::Group(_) => {
+                Precedence::Primary
+            }
+            Expr::Unary(..) => Precedence::Unary,
+            Expr::Call(..) | Expr::Index(..) => Precedence::Call,
+            Expr::Assign(..) => Precedence::Assignment,
+            Expr::Binary(_, operator, _) => binary_precedence(operator),
+        }
+    }
+
+    /// Wraps every operand that binds more loosely than its position allows in a
+    /// group, which is where the source needs parentheses
+    fn grouped(self) -> Expr {
+        let group = |expr: Expr, at_least: Precedence, strictly: bool| {
+            let expr = expr.grouped();
+            let precedence = expr.precedence();
+            if precedence < at_least || (strictly && precedence == at_least) {
+                Expr::Group(Box::new(expr))
+            } else {
+                expr
+            }
+        };
+        match self {
+            Expr::Group(inner) => Expr::Group(Box::new(inner.grouped())),
+            Expr::Unary(operator, right) => {
+                Expr::Unary(operator, Box::new(group(*right, Precedence::Unary, false)))
+            }
+            Expr::Binary(left, operator, right) => {
+                let precedence = binary_precedence(operator);
+                Expr::Binary(
+                    Box::new(group(*left, precedence, false)),
+                    operator,
+                    Box::new(group(*right, precedence, true)),
+                )
+            }
+            Expr::Assign(name, value) => {
+                Expr::Assign(name, Box::new(group(*value, Precedence::Assignment, false)))
+            }
+            Expr::Call(callee, arguments) => Expr::Call(
+                Box::new(group(*callee, Precedence::Call, false)),
+                arguments
+                    .into_iter()
+                    .map(|argument| group(argument, Precedence::Assignment, false))
+                    .collect(),
+            ),
+            // The brackets delimit the index, so any expression fits in them
+            Expr::Index(object, index) => Expr::Index(
+                Box::new(group(*object, Precedence::Call, false)),
+                Box::new(index.grouped()),
+            ),
+            primary => primary,
+        }
+    }
+
+    /// The source of the expression, with parentheses only for groups
+    fn source(&self) -> String {
+        match self {
+            Expr::Number(n) => n.to_string(),
+            Expr::Str(s) => format!("\"{s}\""),
+            Expr::Variable(name) => name.to_string(),
+            Expr::Group(inner) => format!("({})", inner.source()),
+            Expr::Unary(operator, right) => format!("{operator} {}", right.source()),
+            Expr::Binary(left, operator, right) => {
+                format!("{} {operator} {}", left.source(), right.source())
+            }
+            Expr::Assign(name, value) => format!("{name} = {}", value.source()),
+            Expr::Call(callee, arguments) => {
+                let arguments = arguments.iter().map(Expr::source).collect::<Vec<_>>();
+                format!("{}({})", callee.source(), arguments.join(", "))
+            }
+            Expr::Index(object, index) => format!("{}[{}]", object.source(), index.source()),
+        }
+    }
+
+    /// The tree as `parse` prints it
+    fn printed(&self) -> String {
+        match self {
+            Expr::Number(n) => format!("{n}.0"),
+            Expr::Str(s) => s.to_string(),
+            Expr::Variable(name) => format!("IDENTIFIER {name} null"),
+            Expr::Group(inner) => format!("(group {})", inner.printed()),
+            Expr::Unary(operator, right) => format!("({operator} {})", right.printed()),
+            Expr::Binary(left, operator, right) => {
+                format!("({operator} {} {})", left.printed(), right.printed())
+            }
+            Expr::Assign(name, value) => format!("{name} = {}", value.printed()),
+            Expr::Call(callee, arguments) => {
+                let mut parts = vec![callee.printed()];
+                parts.extend(arguments.iter().map(Expr::printed));
+                format!("(call {})", parts.join(" "))
+            }
+            Expr::Index(object, index) => {
+                format!("(index {} {})", object.printed(), index.printed())
+            }
+        }
+    }
+}
+
+fn binary_precedence(operator: &str) -> Precedence {
+    match operator {
+        "," => Precedence::Comma,
+        "==" | "!=" => Precedence::Equality,
+        "<" | "<=" | ">" | ">=" => Precedence::Comparison,
+        "+" | "-" => Precedence::Term,
+        "*" | "/" => Precedence::Factor,
+        _ => unreachable!("not a binary operator: {operator}"),
+    }
+}
+
+/// A xorshift generator, which is plenty for picking tree shapes
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+fn generate(rng: &mut Rng, depth: usize) -> Expr {
+    const NAMES: [&str; 3] = ["a", "b", "c"];
+    const BINARY: [&str; 13] = [
+        ",", "==", "!=", "<", "<=", ">", ">=", "+", "-", "*", "/", "+", "*",
+    ];
+    let choice = match depth {
+        0 => rng.below(3),
+        _ => rng.below(10),
+    };
+    match choice {
+        0 => Expr::Number(rng.below(100) as u32),
+        1 => Expr::Variable(rng.pick(&NAMES)),
+        2 => Expr::Str(rng.pick(&["x", "hello"])),
+        3 => Expr::Group(Box::new(generate(rng, depth - 1))),
+        4 => Expr::Unary(rng.pick(&["!", "-"]), Box::new(generate(rng, depth - 1))),
+        5 => Expr::Assign(rng.pick(&NAMES), Box::new(generate(rng, depth - 1))),
+        6 => {
+            let arguments = (0..rng.below(3))
+                .map(|_| generate(rng, depth - 1))
+                .collect();
+            Expr::Call(Box::new(generate(rng, depth - 1)), arguments)
+        }
+        7 => Expr::Index(
+            Box::new(generate(rng, depth - 1)),
+            Box::new(generate(rng, depth - 1)),
+        ),
+        _ => Expr::Binary(
+            Box::new(generate(rng, depth - 1)),
+            rng.pick(&BINARY),
+            Box::new(generate(rng, depth - 1)),
+        ),
+    }
+}
+
+fn parse(source: &str) -> Option<String> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let expr = Parser::new(scanner.tokens).parse_single_expr().ok()?;
+    Some(expr.accept())
+}
+
+#[test]
+fn printed_trees_parse_back_to_the_same_tree() {
+    for seed in 1..=500u64 {
+        let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let expr = generate(&mut rng, 5).grouped();
+        let source = expr.source();
+        assert_eq!(
+            parse(&source),
+            Some(expr.printed()),
+            "seed {seed}: {source}"
+        );
+    }
+}
+
+#[test]
+fn operators_of_the_same_precedence_group_to_the_left() {
+    assert_eq!(parse("1 - 2 - 3").as_deref(), Some("(- (- 1.0 2.0) 3.0)"));
+    assert_eq!(parse("1 / 2 * 3").as_deref(), Some("(* (/ 1.0 2.0) 3.0)"));
+    assert_eq!(parse("1, 2, 3").as_deref(), Some("(, (, 1.0 2.0) 3.0)"));
+}
+
+#[test]
+fn assignment_groups_to_the_right() {
+    assert_eq!(parse("a = b = 1").as_deref(), Some("a = b = 1.0"));
+}
+
+#[test]
+fn indexing_binds_like_a_call() {
+    assert_eq!(
+        parse("-a[1](2)").as_deref(),
+        Some("(- (call (index IDENTIFIER a null 1.0) 2.0))")
+    );
+}
@@ -0,0 +1,25 @@
+//! Checks the execution counts `run --profile` collects per line.
+
+use codecrafters_interpreter::{
+    interpret::Interpreter, parse::Parser, profile::Profiler, scan::Scanner,
+};
+use std::rc::Rc;
+
+#[test]
+fn loop_bodies_are_counted_per_iteration() {
+    let source = "var total = 0;\nfor (i in range(5)) {\n  total = total + i;\n}\nprint total;";
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let profiler = Rc::new(Profiler::new());
+    let statements = Parser::new(scanner.tokens)
+        .with_profiler(profiler.clone())
+        .parse()
+        .ok()
+        .expect("valid source");
+    assert!(Interpreter::new(statements).interpret().is_ok());
+
+    assert_eq!(profiler.executions(1), 1);
+    assert_eq!(profiler.executions(3), 5);
+    assert_eq!(profiler.executions(5), 1);
+    assert_eq!(profiler.executions(6), 0);
+}
@@ -0,0 +1,93 @@
+//! Checks the scope depths `parse --dump-resolved` reports for variable references.
+
+use codecrafters_interpreter::{parse::Parser, scan::Scanner, token::Token};
+use std::collections::HashMap;
+
+/// Every variable reference as `name@line: depth`
+fn resolve(source: &str) -> Vec<String> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let mut parser = Parser::new(scanner.tokens).with_resolutions();
+    assert!(parser.parse().is_ok());
+    parser
+        .resolutions()
+        .iter()
+        .map(|r| match r.depth {
+            Some(depth) => format!("{}@{}: {depth}", r.name.lexeme, r.name.line),
+            None => format!("{}@{}: global", r.name.lexeme, r.name.line),
+        })
+        .collect()
+}
+
+#[test]
+fn blocks_and_functions_each_add_a_scope() {
+    let source = "var a = 1;
+{
+  var b = a;
+  {
+    print b;
+  }
+}
+var f = fun (x) {
+  var y = x;
+  return fun () { return x + y + a; };
+};";
+    assert_eq!(
+        resolve(source),
+        [
+            "a@3: global",
+            "b@5: 1",
+            "x@9: 0",
+            "x@10: 1",
+            "y@10: 1",
+            "a@10: global",
+        ]
+    );
+}
+
+#[test]
+fn loop_variables_and_caught_values_get_their_own_scope() {
+    let source = "{
+  for (c in \"ab\") print c;
+  try { throw 1; } catch (e) { print e; }
+}";
+    assert_eq!(resolve(source), ["c@2: 0", "e@3: 1"]);
+}
+
+#[test]
+fn initializers_see_the_outer_variable() {
+    let source = "{
+  var a = 1;
+  {
+    var a = a;
+    var f = fun () { return f; };
+  }
+}";
+    assert_eq!(resolve(source), ["a@4: 1", "f@5: 1"]);
+}
+
+#[test]
+fn references_can_key_a_side_table() {
+    let source = "var a = 1;\n{\n  var a = 2;\n  print a;\n}\nprint a;";
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let tokens = scanner.tokens.clone();
+    let mut parser = Parser::new(scanner.tokens).with_resolutions();
+    assert!(parser.parse().is_ok());
+
+    let depths = parser
+        .resolutions()
+        .iter()
+        .map(|r| (r.name.clone(), r.depth))
+        .collect::<HashMap<Token, Option<usize>>>();
+    // Both references are named `a`, but they are different tokens
+    assert_eq!(depths.len(), 2);
+    let reference_on = |line: usize| {
+        tokens
+            .iter()
+            .find(|t| t.lexeme == "a" && t.line == line)
+            .expect("a reference on that line")
+    };
+    assert_eq!(depths[reference_on(4)], Some(0));
+    assert_eq!(depths[reference_on(6)], None);
+}
@@ -0,0 +1,38 @@
+//! Checks that `run_file` reports each way running a script can fail.
+
+use codecrafters_interpreter::interpret::{run_file, LoxError};
+use std::{env, fs, path::PathBuf, process};
+
+/// Writes `source` to a script in the temporary directory that's unique to this test
+fn script(name: &str, source: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("lox-run-file-{}-{name}.lox", process::id()));
+    fs::write(&path, source).expect("to be able to write the script");
+    path
+}
+
+#[test]
+fn scripts_run_to_completion() {
+    let path = script("ok", "var a = 1;\nassert(a == 1, \"a is 1\");");
+    assert!(run_file(&path).is_ok());
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn missing_files_are_io_errors() {
+    let path = env::temp_dir().join("lox-run-file-surely-missing.lox");
+    assert!(matches!(run_file(path), Err(LoxError::Io(_))));
+}
+
+#[test]
+fn failures_are_reported_by_phase() {
+    let path = script("parse", "var;");
+    assert!(matches!(run_file(&path), Err(LoxError::Parse(_))));
+    let _ = fs::remove_file(path);
+
+    let path = script("runtime", "assert(false, \"fails\");");
+    match run_file(&path) {
+        Err(LoxError::Runtime(e)) => assert_eq!(e.message, "fails"),
+        _ => panic!("expected a runtime error"),
+    }
+    let _ = fs::remove_file(path);
+}
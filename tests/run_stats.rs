@@ -0,0 +1,39 @@
+//! Checks the report `run --stats` prints after the program's own output.
+
+use std::{env, fs, process::Command};
+
+/// The number before `unit` on the line of the report that starts with `phase`
+fn count(report: &str, phase: &str, unit: &str) -> usize {
+    let line = report
+        .lines()
+        .find(|line| line.starts_with(phase))
+        .unwrap_or_else(|| panic!("a line for {phase} in\n{report}"));
+    let before = line
+        .strip_suffix(unit)
+        .unwrap_or_else(|| panic!("'{line}' to end with {unit}"));
+    let number = before.split_whitespace().last().expect("a count");
+    number.parse().expect("a whole number")
+}
+
+#[test]
+fn every_phase_is_reported() {
+    let path = env::temp_dir().join(format!("lox-stats-{}.lox", std::process::id()));
+    fs::write(&path, "var a = 1;\n{ var b = a + 2; print b; }").expect("to write the script");
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .arg("run")
+        .arg("--stats")
+        .arg("--no-prelude")
+        .arg(&path)
+        .output()
+        .expect("to be able to run the interpreter");
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    let report = String::from_utf8_lossy(&output.stderr);
+    assert!(report.starts_with("--- stats\n"), "{report}");
+    assert!(count(&report, "scan:", " tokens") > 0);
+    assert!(count(&report, "parse:", " AST nodes") > 0);
+    assert!(count(&report, "execute:", " environments created") > 0);
+    assert!(report.lines().any(|line| line.starts_with("peak memory:")));
+}
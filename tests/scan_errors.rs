@@ -0,0 +1,74 @@
+//! Checks that the scanner collects every lexical error with its position and keeps scanning,
+//! and that it can quote the line an error is on.
+
+use codecrafters_interpreter::{scan::Scanner, TokenType};
+
+fn scan(source: &str) -> Scanner {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    scanner
+}
+
+#[test]
+fn every_error_is_listed_once_in_source_order() {
+    let scanner = scan("var a = 1 @ 2;\nvar b = \"x${a + \"y}\";\n  # \"open");
+    let errors = scanner
+        .errors
+        .iter()
+        .map(|e| format!("{}:{} {}: {}", e.line, e.column, e.code, e.message))
+        .collect::<Vec<String>>();
+    assert_eq!(
+        errors,
+        [
+            "1:11 UnknownCharacter: Unexpected character: @",
+            "2:9 UnterminatedString: Unterminated string.",
+            "3:3 UnknownCharacter: Unexpected character: #",
+            "3:5 UnterminatedString: Unterminated string.",
+        ]
+    );
+    assert!(scanner.has_error());
+}
+
+#[test]
+fn tokens_after_an_error_are_still_scanned() {
+    let scanner = scan("print 1 @ 2;");
+    let types = scanner
+        .tokens
+        .iter()
+        .map(|t| t.token_type)
+        .collect::<Vec<TokenType>>();
+    assert_eq!(
+        types,
+        [
+            TokenType::Print,
+            TokenType::Number,
+            TokenType::Number,
+            TokenType::Semicolon,
+            TokenType::Eof
+        ]
+    );
+}
+
+#[test]
+fn errors_can_quote_their_line() {
+    let scanner = scan("var a = 1;\n\nprint a @ 2;");
+    let error = &scanner.errors[0];
+    assert_eq!(
+        scanner.line_content(error.line).as_deref(),
+        Some("print a @ 2;")
+    );
+    assert_eq!(scanner.line_content(2).as_deref(), Some(""));
+    assert_eq!(scanner.line_content(0), None);
+    assert_eq!(scanner.line_content(4), None);
+}
+
+#[test]
+fn line_content_follows_the_start_line() {
+    let mut scanner = Scanner::new(String::from("var b = 2;\nprint b;\n")).with_start_line(10);
+    scanner.scan_tokens();
+    assert_eq!(scanner.line_content(11).as_deref(), Some("print b;"));
+    assert_eq!(scanner.line_content(12).as_deref(), Some(""));
+    assert_eq!(scanner.line_content(1), None);
+    assert_eq!(scanner.tokens[5].line, 11);
+    assert_eq!(scanner.tokens[5].column, 1);
+}
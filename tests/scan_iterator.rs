@@ -0,0 +1,58 @@
+//! Checks that pulling tokens from the scanner one at a time gives the same tokens as
+//! scanning the whole source, with errors in their place.
+
+use codecrafters_interpreter::{scan::Scanner, TokenType};
+
+fn describe(source: &str, trivia: bool) -> (Vec<String>, Vec<String>) {
+    let lazily = Scanner::new(source.to_string())
+        .with_trivia(trivia)
+        .map(|item| match item {
+            Ok(token) => format!("{} {:?}", token.token_type, token.lexeme),
+            Err(e) => format!("error {}:{} {}", e.line, e.column, e.code),
+        })
+        .collect();
+
+    let mut scanner = Scanner::new(source.to_string()).with_trivia(trivia);
+    scanner.scan_tokens();
+    let eagerly = scanner
+        .tokens
+        .iter()
+        .map(|token| format!("{} {:?}", token.token_type, token.lexeme))
+        .collect();
+    (lazily, eagerly)
+}
+
+#[test]
+fn lazy_tokens_match_scan_tokens() {
+    let source = "var s = \"a ${1 + 2} b\";  \n\n  // done\nprint s;";
+    for trivia in [false, true] {
+        let (lazily, eagerly) = describe(source, trivia);
+        assert_eq!(lazily, eagerly, "with trivia: {trivia}");
+    }
+    let (lazily, _) = describe(source, true);
+    assert!(lazily.contains(&String::from("WHITESPACE \"  \\n\\n  \"")));
+}
+
+#[test]
+fn errors_come_in_place_of_their_token() {
+    let (lazily, _) = describe("1 @ 2 \"open", false);
+    assert_eq!(
+        lazily,
+        [
+            "NUMBER \"1\"",
+            "error 1:3 UnknownCharacter",
+            "NUMBER \"2\"",
+            "error 1:7 UnterminatedString",
+            "EOF \"\"",
+        ]
+    );
+}
+
+#[test]
+fn tokens_can_be_pulled_before_the_rest_is_scanned() {
+    let mut scanner = Scanner::new(String::from("print 1; @"));
+    let first = scanner.next().and_then(|item| item.ok());
+    assert_eq!(first.map(|t| t.token_type), Some(TokenType::Print));
+    assert!(!scanner.has_error());
+    assert_eq!(scanner.count(), 4);
+}
@@ -0,0 +1,108 @@
+//! Truthiness and equality for every kind of value, including the edge cases:
+//! NaN, negative zero, the empty string, nil and functions.
+
+use codecrafters_interpreter::{
+    interpret::{is_equal, is_truthy, Interpreter},
+    parse::Parser,
+    scan::Scanner,
+    token::{BooleanLiteral, LiteralValue, NilLiteral, NumberLiteral, StringLiteral, Token},
+    TokenType,
+};
+
+fn values() -> Vec<(&'static str, Option<Box<dyn LiteralValue>>)> {
+    vec![
+        ("none", None),
+        ("nil", Some(Box::new(NilLiteral))),
+        ("true", Some(Box::new(BooleanLiteral { value: true }))),
+        ("false", Some(Box::new(BooleanLiteral { value: false }))),
+        ("0", Some(Box::new(NumberLiteral { value: 0.0 }))),
+        ("-0", Some(Box::new(NumberLiteral { value: -0.0 }))),
+        ("1", Some(Box::new(NumberLiteral { value: 1.0 }))),
+        ("NaN", Some(Box::new(NumberLiteral { value: f32::NAN }))),
+        (
+            "\"\"",
+            Some(Box::new(StringLiteral {
+                value: String::new(),
+            })),
+        ),
+        (
+            "\"1.0\"",
+            Some(Box::new(StringLiteral {
+                value: String::from("1.0"),
+            })),
+        ),
+        (
+            "\"false\"",
+            Some(Box::new(StringLiteral {
+                value: String::from("false"),
+            })),
+        ),
+    ]
+}
+
+#[test]
+fn only_nil_and_false_are_falsey() {
+    for (name, value) in values() {
+        let expected = !matches!(name, "none" | "nil" | "false");
+        assert_eq!(
+            is_truthy(value.as_deref()),
+            expected,
+            "truthiness of {name}"
+        );
+    }
+}
+
+#[test]
+fn equality_table() {
+    // Pairs of different values that are still equal, in both orders
+    let equal_pairs = [("none", "nil"), ("0", "-0")];
+    for (left_name, left) in values() {
+        for (right_name, right) in values() {
+            let expected = match (left_name, right_name) {
+                ("NaN", _) | (_, "NaN") => false,
+                (l, r) if l == r => true,
+                (l, r) => equal_pairs.contains(&(l, r)) || equal_pairs.contains(&(r, l)),
+            };
+            assert_eq!(
+                is_equal(left.as_deref(), right.as_deref()),
+                expected,
+                "{left_name} == {right_name}"
+            );
+        }
+    }
+}
+
+#[test]
+fn functions_are_only_equal_to_themselves() {
+    let source = "var f = fun (x) { x; };\nvar g = fun (x) { x; };\nvar h = f;";
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let statements = Parser::new(scanner.tokens)
+        .parse()
+        .ok()
+        .expect("valid source");
+    let mut interpreter = Interpreter::new(statements);
+    assert!(interpreter.interpret().is_ok());
+    let globals = interpreter.globals();
+    let get = |name: &str| {
+        let token = Token::new(TokenType::Identifier, name.to_string(), None, 1, 1);
+        globals.get(token).ok().flatten()
+    };
+
+    // Rows are (left, right, expected)
+    let table = [
+        ("f", "f", true),
+        ("f", "h", true),
+        ("f", "g", false),
+        ("clock", "clock", true),
+        ("clock", "len", false),
+        ("f", "clock", false),
+    ];
+    for (left, right, expected) in table {
+        assert_eq!(
+            is_equal(get(left).as_deref(), get(right).as_deref()),
+            expected,
+            "{left} == {right}"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Golden-file tests: every program in `tests/fixtures` is run through the
+//! `tokenize`, `parse` and `run` commands and the exit code, stdout and stderr
+//! are compared against the snapshots checked in under `tests/snapshots`.
+//!
+//! Fixtures in `tests/fixtures/expressions` are single expressions, which go through
+//! `evaluate` instead.
+//!
+//! A fixture whose first line is `// run-args: <flags>` passes those flags to `run`,
+//! and a `<fixture>.stdin` file next to it is fed to the interpreter's stdin.
+//!
+//! Run with `UPDATE_SNAPSHOTS=1 cargo test` to regenerate the snapshots.
+
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const COMMANDS: [&str; 3] = ["tokenize", "parse", "run"];
+const EXPRESSION_COMMANDS: [&str; 1] = ["evaluate"];
+
+/// The fixtures in `dir`, relative to the crate root
+fn fixtures(dir: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let mut fixtures = fs::read_dir(dir)
+        .expect("to be able to read the fixtures directory")
+        .map(|entry| entry.expect("to be able to read fixture").path())
+        .filter(|path| path.extension().is_some_and(|e| e == "lox"))
+        .collect::<Vec<PathBuf>>();
+    fixtures.sort();
+    fixtures
+}
+
+fn snapshot_path(fixture: &Path, command: &str) -> PathBuf {
+    let name = fixture
+        .file_stem()
+        .expect("fixture to have a file name")
+        .to_string_lossy();
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{name}.{command}.snap"))
+}
+
+/// Extra flags for the `run` command, taken from the fixture's first line
+fn run_args(fixture: &Path) -> Vec<String> {
+    let source = fs::read_to_string(fixture).unwrap_or_default();
+    match source
+        .lines()
+        .next()
+        .and_then(|l| l.strip_prefix("// run-args:"))
+    {
+        Some(args) => args.split_whitespace().map(String::from).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Runs the interpreter binary and renders its observable behavior
+fn render(command: &str, fixture: &Path) -> String {
+    let mut interpreter = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"));
+    interpreter.arg(command);
+    // Script arguments after `--` have to follow the file name
+    let mut script_args = Vec::new();
+    if command == "run" {
+        let mut args = run_args(fixture);
+        if let Some(split) = args.iter().position(|a| a == "--") {
+            script_args = args.split_off(split);
+        }
+        interpreter.args(args);
+    }
+    let stdin = fs::read(fixture.with_extension("stdin")).unwrap_or_default();
+    // Relative to the crate root, so file names in diagnostics don't depend on the checkout
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let relative = fixture.strip_prefix(root).unwrap_or(fixture);
+    let mut child = interpreter
+        .current_dir(root)
+        .arg(relative)
+        .args(script_args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("to be able to run the interpreter");
+    // Commands that don't read stdin may exit before it is written, so ignore errors
+    let _ = child
+        .stdin
+        .take()
+        .expect("stdin to be piped")
+        .write_all(&stdin);
+    let output = child
+        .wait_with_output()
+        .expect("to be able to run the interpreter");
+    let exit_code = match output.status.code() {
+        Some(code) => code.to_string(),
+        None => String::from("none"),
+    };
+    format!(
+        "exit code: {}\n--- stdout\n{}--- stderr\n{}",
+        exit_code,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+#[test]
+fn snapshots_match() {
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut failures = Vec::new();
+
+    let programs = fixtures("tests/fixtures")
+        .into_iter()
+        .map(|f| (f, &COMMANDS[..]));
+    let expressions = fixtures("tests/fixtures/expressions")
+        .into_iter()
+        .map(|f| (f, &EXPRESSION_COMMANDS[..]));
+    for (fixture, commands) in programs.chain(expressions) {
+        for &command in commands {
+            let actual = render(command, &fixture);
+            let path = snapshot_path(&fixture, command);
+            if update {
+                fs::write(&path, &actual).expect("to be able to write snapshot");
+                continue;
+            }
+            match fs::read_to_string(&path) {
+                Ok(expected) if expected == actual => (),
+                Ok(expected) => failures.push(format!(
+                    "{} does not match\n=== expected\n{}=== actual\n{}",
+                    path.display(),
+                    expected,
+                    actual
+                )),
+                Err(_) => failures.push(format!("{} is missing", path.display())),
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nRun with UPDATE_SNAPSHOTS=1 to regenerate the snapshots.",
+        failures.join("\n\n")
+    );
+}
+
+/// `tokens.lox` is the tokenize snapshot of every kind of token, so new keywords
+/// have to be added to it
+#[test]
+fn tokens_fixture_covers_every_keyword() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tokens.lox");
+    let source = fs::read_to_string(path).expect("to be able to read tokens.lox");
+    let words = source.split_whitespace().collect::<Vec<&str>>();
+    let mut missing = codecrafters_interpreter::KEYWORDS
+        .keys()
+        .filter(|keyword| !words.contains(keyword))
+        .collect::<Vec<_>>();
+    missing.sort();
+    assert!(missing.is_empty(), "tokens.lox lacks {missing:?}");
+}
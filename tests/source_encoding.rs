@@ -0,0 +1,44 @@
+//! Checks how source files that aren't plain UTF-8 are read.
+
+use codecrafters_interpreter::scan::decode_source;
+use std::{env, fs, process::Command};
+
+#[test]
+fn byte_order_marks_are_dropped() {
+    let source = decode_source(b"\xef\xbb\xbfprint 1;".to_vec(), false).ok();
+    assert_eq!(source.as_deref(), Some("print 1;"));
+}
+
+#[test]
+fn invalid_utf8_is_an_error_unless_lossy() {
+    let bytes = b"print \"\xff\";".to_vec();
+    let error = decode_source(bytes.clone(), false).expect_err("invalid UTF-8");
+    assert_eq!(error.utf8_error().valid_up_to(), 7);
+    let source = decode_source(bytes, true).ok();
+    assert_eq!(source.as_deref(), Some("print \"\u{fffd}\";"));
+}
+
+#[test]
+fn the_cli_reports_invalid_utf8_with_its_line() {
+    let path = env::temp_dir().join(format!("lox-invalid-utf8-{}.lox", std::process::id()));
+    fs::write(&path, b"print 1;\nprint \"\xff\";\n").expect("to be able to write the script");
+    let run = |lossy: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"));
+        command.arg("run").arg(&path);
+        if lossy {
+            command.arg("--lossy");
+        }
+        command.output().expect("to be able to run the interpreter")
+    };
+
+    let output = run(false);
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("[line 2] Error:"), "{stderr}");
+    assert!(output.stdout.is_empty());
+
+    let output = run(true);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n\u{fffd}\n");
+    let _ = fs::remove_file(path);
+}
@@ -0,0 +1,60 @@
+//! Checks that parsed nodes know which part of the source they came from.
+
+use codecrafters_interpreter::{parse::Parser, scan::Scanner};
+
+fn spans(source: &str) -> Vec<String> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let statements = Parser::new(scanner.tokens)
+        .parse()
+        .ok()
+        .expect("valid source");
+    statements
+        .iter()
+        .map(|s| source[s.span()].to_string())
+        .collect()
+}
+
+fn expression_span(source: &str) -> String {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let expr = Parser::new(scanner.tokens)
+        .parse_single_expr()
+        .ok()
+        .expect("valid expression");
+    source[expr.span()].to_string()
+}
+
+#[test]
+fn statements_span_their_source() {
+    let source = "var a = 1;\n  print a +  2;\n{ var b = a; }\nfor (x in \"ab\") print x;\n";
+    assert_eq!(
+        spans(source),
+        [
+            "var a = 1;",
+            "print a +  2;",
+            "{ var b = a; }",
+            "for (x in \"ab\") print x;"
+        ]
+    );
+}
+
+#[test]
+fn expressions_span_their_source() {
+    assert_eq!(expression_span("  1 + 2 * 3  "), "1 + 2 * 3");
+    assert_eq!(expression_span("-(a)"), "-(a)");
+    assert_eq!(expression_span("f(1, 2)(3)"), "f(1, 2)(3)");
+    assert_eq!(expression_span("a = b = \"é\""), "a = b = \"é\"");
+    assert_eq!(expression_span("(x) -> x + 1"), "(x) -> x + 1");
+    assert_eq!(
+        expression_span("fun (x) { return x; }"),
+        "fun (x) { return x; }"
+    );
+    assert_eq!(expression_span("\"a${1 + 2}b\""), "\"a${1 + 2}b\"");
+}
+
+#[test]
+fn spans_count_bytes_after_multibyte_characters() {
+    let source = "print \"héllo\";\nprint 1;";
+    assert_eq!(spans(source), ["print \"héllo\";", "print 1;"]);
+}
@@ -0,0 +1,61 @@
+//! Checks that global variables saved with `save_state` come back with `load_state`.
+
+use codecrafters_interpreter::{interpret::Interpreter, parse::Parser, scan::Scanner};
+use std::{env, fs, path::PathBuf};
+
+fn interpreter(source: &str) -> Interpreter {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let statements = Parser::new(scanner.tokens)
+        .parse()
+        .ok()
+        .expect("valid source");
+    Interpreter::new(statements)
+}
+
+fn state_file(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("lox-state-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn globals_survive_a_new_session() {
+    let path = state_file("round-trip");
+    let mut first = interpreter(
+        "var n = 0.1; var s = \"tab\\there\"; var t = true; var f = false; var none; var fn = fun () {};",
+    );
+    assert!(first.interpret().is_ok());
+    first.save_state(&path).expect("to be able to save");
+
+    let mut second = interpreter(
+        "assert(n == 0.1, \"n\");
+         assert(s == \"tab\\there\", \"s\");
+         assert(t == true, \"t\");
+         assert(f == false, \"f\");
+         assert(none == nil, \"nil\");
+         assert(type(clock) == \"function\", \"natives are still defined\");",
+    );
+    second.load_state(&path).expect("to be able to load");
+    let _ = fs::remove_file(&path);
+    assert!(second.interpret().is_ok());
+}
+
+#[test]
+fn functions_are_not_saved() {
+    let path = state_file("functions");
+    let mut session = interpreter("var f = fun () {};");
+    assert!(session.interpret().is_ok());
+    session.save_state(&path).expect("to be able to save");
+    let saved = fs::read_to_string(&path).expect("to be able to read the state");
+    let _ = fs::remove_file(&path);
+    assert!(!saved.lines().any(|line| line.starts_with("f\t")));
+    assert!(!saved.lines().any(|line| line.starts_with("clock\t")));
+}
+
+#[test]
+fn invalid_state_is_rejected() {
+    let path = state_file("invalid");
+    fs::write(&path, "n\tnumber\tnot hex\n").expect("to be able to write");
+    let result = interpreter("").load_state(&path);
+    let _ = fs::remove_file(&path);
+    assert!(result.is_err());
+}
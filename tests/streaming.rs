@@ -0,0 +1,41 @@
+//! Checks that the parser can pull its tokens straight from the scanner, or from
+//! any other source of tokens.
+
+use codecrafters_interpreter::{
+    interpret::Interpreter, parse::Parser, scan::Scanner, token::Token, TokenType,
+};
+
+#[test]
+fn parses_tokens_as_they_are_scanned() {
+    let source = "var add = (a, b) -> a + b;\nvar total = add(1, 2);";
+    let tokens = Scanner::new(source.to_string()).map(|item| item.expect("valid tokens"));
+    let statements = Parser::new(tokens).parse().ok().expect("valid source");
+    assert_eq!(statements.len(), 2);
+
+    let mut interpreter = Interpreter::new(statements);
+    assert!(interpreter.interpret().is_ok());
+    let total = Token::new(TokenType::Identifier, String::from("total"), None, 2, 5);
+    let value = interpreter.globals().get(total).ok().flatten();
+    assert_eq!(value.map(|v| v.print_value()), Some(String::from("3.0")));
+}
+
+#[test]
+fn tokens_without_eof_end_where_they_run_out() {
+    let mut scanner = Scanner::new(String::from("print 1;\nprint 2"));
+    scanner.scan_tokens();
+    let tokens = scanner
+        .tokens
+        .into_iter()
+        .filter(|t| t.token_type != TokenType::Eof);
+
+    let complete = tokens.clone().take(3).collect::<Vec<Token>>();
+    assert!(Parser::new(complete).parse().is_ok());
+
+    let error = Parser::new(tokens)
+        .parse()
+        .err()
+        .expect("a missing semicolon");
+    assert_eq!(error.code(), "NoSemicolon");
+    assert_eq!(error.token().token_type, TokenType::Eof);
+    assert_eq!((error.token().line, error.token().column), (2, 8));
+}
@@ -0,0 +1,54 @@
+//! Checks the `tokenize --format tsv` and `--format json` output for tokens
+//! with literals and spans across lines, and lossless scanning for `--roundtrip`.
+
+use codecrafters_interpreter::scan::{Scanner, TokenFormat};
+
+const SOURCE: &str = "var s = \"a\tb\nc\";\nprint 1.5;";
+
+fn render(format: TokenFormat) -> String {
+    let mut scanner = Scanner::new(SOURCE.to_string());
+    scanner.scan_tokens();
+    assert!(!scanner.has_error());
+    scanner.render(format)
+}
+
+#[test]
+fn tsv_escapes_lexemes() {
+    let expected = "1\t1\tVAR\tvar\tnull
+1\t5\tIDENTIFIER\ts\tnull
+1\t7\tEQUAL\t=\tnull
+1\t9\tSTRING\t\"a\\tb\\nc\"\ta\\tb\\nc
+2\t3\tSEMICOLON\t;\tnull
+3\t1\tPRINT\tprint\tnull
+3\t7\tNUMBER\t1.5\t1.5
+3\t10\tSEMICOLON\t;\tnull
+3\t11\tEOF\t\tnull";
+    assert_eq!(render(TokenFormat::Tsv), expected);
+}
+
+#[test]
+fn json_has_spans() {
+    let json = render(TokenFormat::Json);
+    let lines = json.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.first(), Some(&"["));
+    assert_eq!(lines.last(), Some(&"]"));
+    assert_eq!(
+        lines[4],
+        "{\"type\":\"STRING\",\"lexeme\":\"\\\"a\\tb\\nc\\\"\",\"literal\":\"a\\tb\\nc\",\"start\":{\"line\":1,\"column\":9},\"end\":{\"line\":2,\"column\":3}},"
+    );
+    assert_eq!(
+        lines[7],
+        "{\"type\":\"NUMBER\",\"lexeme\":\"1.5\",\"literal\":1.5,\"start\":{\"line\":3,\"column\":7},\"end\":{\"line\":3,\"column\":10}},"
+    );
+}
+
+#[test]
+fn trivia_round_trips() {
+    let source = "// comment\nvar  a = \"x ${ 1 + 2 } y\";\t\n\n  print a; // trailing";
+    let mut scanner = Scanner::new(source.to_string()).with_trivia(true);
+    scanner.scan_tokens();
+    assert!(!scanner.has_error());
+    assert_eq!(scanner.source_text(), source);
+    assert_eq!(scanner.tokens[0].lexeme, "// comment");
+    assert_eq!(scanner.tokens[1].lexeme, "\n");
+}
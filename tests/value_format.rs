@@ -0,0 +1,53 @@
+//! The two ways values are turned into text: `stringify`, which is what `print`
+//! shows, and `repr`, which shows what kind of value it is.
+
+use codecrafters_interpreter::{
+    environment::Environment,
+    interpret::{evaluate_expr, repr, stringify},
+    native::define_natives,
+    token::{LiteralValue, StringLiteral},
+};
+
+/// (expression, as `print` shows it, as `repr` shows it)
+const TABLE: [(&str, &str, &str); 12] = [
+    ("1", "1", "1"),
+    ("1.5", "1.5", "1.5"),
+    ("-0", "-0", "-0"),
+    ("\"hi\"", "hi", "\"hi\""),
+    ("\"\"", "", "\"\""),
+    ("\"1\"", "1", "\"1\""),
+    ("\"nil\"", "nil", "\"nil\""),
+    ("true", "true", "true"),
+    ("nil", "nil", "nil"),
+    ("fun () {}", "<fn>", "<fn>"),
+    ("type", "<native fn>", "<native fn>"),
+    ("repr(\"a\")", "\"a\"", "\"\\\"a\\\"\""),
+];
+
+#[test]
+fn values_print_and_repr_as_documented() {
+    let mut environment = Environment::new(None);
+    define_natives(&mut environment);
+    for (source, printed, represented) in TABLE {
+        let value = evaluate_expr(source, &mut environment)
+            .ok()
+            .unwrap_or_else(|| panic!("{source} to evaluate"));
+        assert_eq!(stringify(value.clone()), printed, "print {source}");
+        assert_eq!(repr(value), represented, "repr {source}");
+    }
+}
+
+#[test]
+fn repr_escapes_quotes_and_control_characters() {
+    let value: Box<dyn LiteralValue> = Box::new(StringLiteral {
+        value: String::from("say \"hi\"\\\n\t\u{7}"),
+    });
+    assert_eq!(stringify(Some(value.clone())), "say \"hi\"\\\n\t\u{7}");
+    assert_eq!(repr(Some(value)), "\"say \\\"hi\\\"\\\\\\n\\t\\u{7}\"");
+}
+
+#[test]
+fn a_missing_value_is_nil_either_way() {
+    assert_eq!(stringify(None), "nil");
+    assert_eq!(repr(None), "nil");
+}
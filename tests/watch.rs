@@ -0,0 +1,33 @@
+//! Checks when `run --watch` considers its scripts changed.
+
+use codecrafters_interpreter::watch::{changed, modification_times};
+use std::{
+    env, fs,
+    time::{Duration, SystemTime},
+};
+
+#[test]
+fn only_new_modification_times_are_changes() {
+    let earlier = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    let later = earlier + Duration::from_secs(1);
+    assert!(!changed(&[Some(earlier)], &[Some(earlier)]));
+    assert!(changed(&[Some(earlier)], &[Some(later)]));
+    assert!(changed(
+        &[Some(earlier), Some(earlier)],
+        &[Some(earlier), Some(later)]
+    ));
+    // A file that is briefly missing while an editor saves it
+    assert!(!changed(&[Some(earlier)], &[None]));
+    assert!(changed(&[None], &[Some(earlier)]));
+}
+
+#[test]
+fn missing_files_have_no_modification_time() {
+    let path = env::temp_dir().join(format!("lox-watch-{}.lox", std::process::id()));
+    fs::write(&path, "print 1;").expect("to be able to write the script");
+    let missing = env::temp_dir().join("lox-watch-surely-missing.lox");
+    let times = modification_times(&[&path, &missing]);
+    let _ = fs::remove_file(&path);
+    assert!(times[0].is_some());
+    assert!(times[1].is_none());
+}